@@ -0,0 +1,259 @@
+use chrono::DateTime;
+use persister::{Period, TimePoint};
+
+use crate::{format_count, format_percent, percent};
+
+const WIDTH: f64 = 600.0;
+const HEIGHT: f64 = 160.0;
+/// room below the plot for the dates
+const LABELS: f64 = 20.0;
+
+/// A chart of the report, `svg` is markup to embed as is.
+pub(crate) struct Chart {
+    pub title: &'static str,
+    pub svg: String,
+}
+
+fn bucket_label(bucket: i64, step: Period) -> String {
+    let format = match step {
+        Period::Hour => "%Y-%m-%d %H:00",
+        Period::Day => "%Y-%m-%d",
+    };
+    DateTime::from_timestamp_millis(bucket)
+        .map(|time| time.format(format).to_string())
+        .unwrap_or_default()
+}
+
+/// An area chart of one value per bucket. Every bucket has an invisible column with a
+/// `<title>`, browsers show it as a tooltip so the values can be read without any script.
+pub(crate) fn area_chart(
+    points: &[(i64, f64)],
+    step: Period,
+    format: impl Fn(f64) -> String,
+) -> String {
+    let max = points.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+    let column = WIDTH / points.len().max(1) as f64;
+    // values sit in the middle of their column
+    let x = |i: usize| column * (i as f64 + 0.5);
+    let y = |value: f64| {
+        if max > 0.0 {
+            HEIGHT - value / max * (HEIGHT - 4.0)
+        } else {
+            HEIGHT
+        }
+    };
+    let line: Vec<String> = points
+        .iter()
+        .enumerate()
+        .map(|(i, (_, value))| format!("{:.1},{:.1}", x(i), y(*value)))
+        .collect();
+    let line = line.join(" ");
+    let mut svg = format!(
+        r#"<svg class="chart" viewBox="0 0 {WIDTH} {}" role="img">"#,
+        HEIGHT + LABELS
+    );
+    if !points.is_empty() {
+        svg.push_str(&format!(
+            r#"<polygon class="chart-area" points="{:.1},{HEIGHT} {line} {:.1},{HEIGHT}"/>"#,
+            x(0),
+            x(points.len() - 1)
+        ));
+        svg.push_str(&format!(
+            r#"<polyline class="chart-line" points="{line}"/>"#
+        ));
+    }
+    svg.push_str(r#"<g class="chart-hover">"#);
+    for (i, (bucket, value)) in points.iter().enumerate() {
+        svg.push_str(&format!(
+            r#"<rect x="{:.1}" y="0" width="{column:.1}" height="{HEIGHT}"><title>{} &#8212; {}</title></rect>"#,
+            column * i as f64,
+            bucket_label(*bucket, step),
+            format(*value)
+        ));
+    }
+    svg.push_str("</g>");
+    svg.push_str(&axes(points.first(), points.last(), step, &format(max)));
+    svg.push_str("</svg>");
+    svg
+}
+
+/// The baseline, the largest value top left and the first and last bucket below.
+fn axes<T>(first: Option<&(i64, T)>, last: Option<&(i64, T)>, step: Period, max: &str) -> String {
+    let mut svg =
+        format!(r#"<line class="chart-axis" x1="0" y1="{HEIGHT}" x2="{WIDTH}" y2="{HEIGHT}"/>"#);
+    svg.push_str(&format!(
+        r#"<text class="chart-label" x="4" y="12">{max}</text>"#
+    ));
+    if let (Some((first, _)), Some((last, _))) = (first, last) {
+        let bottom = HEIGHT + LABELS - 4.0;
+        svg.push_str(&format!(
+            r#"<text class="chart-label" x="0" y="{bottom}">{}</text>"#,
+            bucket_label(*first, step)
+        ));
+        svg.push_str(&format!(
+            r#"<text class="chart-label" x="{WIDTH}" y="{bottom}" text-anchor="end">{}</text>"#,
+            bucket_label(*last, step)
+        ));
+    }
+    svg
+}
+
+/// Requests per status class as stacked columns, 1xx at the bottom. The tooltip of a column
+/// lists the classes it has.
+pub(crate) fn status_chart(points: &[(i64, [i32; 5])], step: Period) -> String {
+    let max = points
+        .iter()
+        .map(|(_, status)| status.iter().sum::<i32>())
+        .max()
+        .unwrap_or(0);
+    let column = WIDTH / points.len().max(1) as f64;
+    let height = |count: i32| {
+        if max > 0 {
+            f64::from(count) / f64::from(max) * (HEIGHT - 4.0)
+        } else {
+            0.0
+        }
+    };
+    let mut svg = format!(
+        r#"<svg class="chart" viewBox="0 0 {WIDTH} {}" role="img">"#,
+        HEIGHT + LABELS
+    );
+    let mut hover = String::from(r#"<g class="chart-hover">"#);
+    for (i, (bucket, status)) in points.iter().enumerate() {
+        let x = column * i as f64;
+        let mut top = HEIGHT;
+        let mut counts = Vec::new();
+        for (class, count) in status.iter().enumerate().filter(|(_, count)| **count > 0) {
+            top -= height(*count);
+            svg.push_str(&format!(
+                r#"<rect class="status-{}xx" x="{:.1}" y="{top:.1}" width="{:.1}" height="{:.1}"/>"#,
+                class + 1,
+                x + column * 0.1,
+                column * 0.8,
+                height(*count)
+            ));
+            counts.push(format!("{}xx {count}", class + 1));
+        }
+        if counts.is_empty() {
+            counts.push(String::from("0"));
+        }
+        hover.push_str(&format!(
+            r#"<rect x="{x:.1}" y="0" width="{column:.1}" height="{HEIGHT}"><title>{} &#8212; {}</title></rect>"#,
+            bucket_label(*bucket, step),
+            counts.join(" · ")
+        ));
+    }
+    svg.push_str(&hover);
+    svg.push_str("</g>");
+    svg.push_str(&axes(points.first(), points.last(), step, &max.to_string()));
+    svg.push_str("</svg>");
+    svg
+}
+
+/// A bare line of the values to sit under a number, no axes or labels.
+pub(crate) fn sparkline(values: &[f64]) -> String {
+    let (width, height) = (120.0, 28.0);
+    let max = values.iter().copied().fold(0.0, f64::max);
+    let step = width / values.len().saturating_sub(1).max(1) as f64;
+    let points: Vec<String> = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let y = if max > 0.0 {
+                height - 1.0 - value / max * (height - 2.0)
+            } else {
+                height - 1.0
+            };
+            format!("{:.1},{y:.1}", step * i as f64)
+        })
+        .collect();
+    format!(
+        r#"<svg class="sparkline" viewBox="0 0 {width} {height}" preserveAspectRatio="none" aria-hidden="true"><polyline points="{}"/></svg>"#,
+        points.join(" ")
+    )
+}
+
+fn count(value: f64) -> String {
+    format_count(value.round() as i64)
+}
+
+/// Requests, pageviews, visitors and the share of bots over the series.
+pub(crate) fn charts(timeseries: &[TimePoint], step: Period) -> Vec<Chart> {
+    let series = |value: fn(&TimePoint) -> f64| -> Vec<(i64, f64)> {
+        timeseries
+            .iter()
+            .map(|point| (point.bucket, value(point)))
+            .collect()
+    };
+    vec![
+        Chart {
+            title: "Requests",
+            svg: area_chart(&series(|point| f64::from(point.requests)), step, count),
+        },
+        Chart {
+            title: "Pageviews",
+            svg: area_chart(&series(|point| f64::from(point.pageviews)), step, count),
+        },
+        Chart {
+            title: "Unique Visitors",
+            svg: area_chart(
+                &series(|point| f64::from(point.unique_visitors)),
+                step,
+                count,
+            ),
+        },
+        Chart {
+            title: "Bot Share",
+            svg: area_chart(
+                &series(|point| percent(f64::from(point.bot_requests), f64::from(point.requests))),
+                step,
+                format_percent,
+            ),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_a_point_per_bucket() {
+        let hour = Period::Hour.length();
+        let points = [(0, 2.0), (hour, 0.0), (2 * hour, 4.0)];
+        let svg = area_chart(&points, Period::Hour, count);
+        assert!(svg.contains(
+            r#"<polyline class="chart-line" points="100.0,82.0 300.0,160.0 500.0,4.0"/>"#
+        ));
+        assert_eq!(svg.matches("<rect").count(), 3);
+        assert!(svg.contains("<title>1970-01-01 01:00 &#8212; 0</title>"));
+        assert!(svg.contains(r#"<text class="chart-label" x="4" y="12">4</text>"#));
+    }
+
+    #[test]
+    fn draws_sparklines_across_the_whole_width() {
+        let svg = sparkline(&[0.0, 5.0, 10.0]);
+        assert!(svg.contains(r#"<polyline points="0.0,27.0 60.0,14.0 120.0,1.0"/>"#));
+    }
+
+    #[test]
+    fn stacks_status_classes() {
+        let points = [(0, [0, 6, 0, 2, 0]), (Period::Day.length(), [0; 5])];
+        let svg = status_chart(&points, Period::Day);
+        assert!(svg.contains(
+            r#"<rect class="status-2xx" x="30.0" y="43.0" width="240.0" height="117.0"/>"#
+        ));
+        assert!(svg.contains(
+            r#"<rect class="status-4xx" x="30.0" y="4.0" width="240.0" height="39.0"/>"#
+        ));
+        assert!(svg.contains("<title>1970-01-01 &#8212; 2xx 6 · 4xx 2</title>"));
+        assert!(svg.contains("<title>1970-01-02 &#8212; 0</title>"));
+    }
+
+    #[test]
+    fn draws_empty_series() {
+        let svg = area_chart(&[], Period::Day, count);
+        assert!(!svg.contains("<polyline"));
+        assert!(svg.ends_with("</svg>"));
+    }
+}
@@ -0,0 +1,458 @@
+//! The JSON reports, `stats-7d.json` and so on next to every HTML page and `index.json` next to
+//! `index.html`. Every document carries `schema_version`, which goes up when a field is
+//! renamed, removed or changes meaning; fields may be added without it. Times are unix
+//! milliseconds (UTC) unless the name says otherwise, durations are milliseconds, `*_percent`
+//! fields are percentages and fields that can be unknown are `null`.
+//!
+//! A report looks like
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "host": "example.com",
+//!   "period": "7d",
+//!   "generated_at": "2025-01-01T10:00:00Z",
+//!   "totals": {"requests": 4, "pageviews": 3, "unique_visitors": 2, "human_requests": 3,
+//!              "bot_requests": 1, "vpn_requests": 0, "bytes_sent": 2048,
+//!              "avg_response_time": 20.0, "bot_share_percent": 25.0},
+//!   "changes": {"requests_percent": 12.5, "pageviews_percent": null, ...,
+//!               "bot_share_points": null},
+//!   "sessions": {"sessions": 1, "bounces": 0, "avg_duration": 120000.0, "avg_pages": 2.0},
+//!   "latency": {"request_time": {"p50": 22.5, "p90": 24.5, "p95": 24.8, "p99": 25.0},
+//!               "upstream_time": null, "slowest": [{"path": "/search", "requests": 5,
+//!               "request_time": {...}, "upstream_time": null}]},
+//!   "top": {"pages": [{"value": "/", "hits": 2}], "countries": [...], "cities": [...],
+//!           "referrers": [...]},
+//!   "status": {"classes": {"1xx": 0, "2xx": 4, "3xx": 0, "4xx": 0, "5xx": 0},
+//!              "error_rate_percent": 0.0, "human_errors": 0, "bot_errors": 0,
+//!              "not_found": [{"path": "/old", "hits": 3,
+//!                             "referrers": [{"value": "https://news.example/", "hits": 2}]}],
+//!              "server_errors": [...]},
+//!   "bandwidth": {"paths": [{"value": "/", "bytes": 1024}], "countries": [...], "bots": [...]},
+//!   "timeseries": {"step": "hour", "points": [{"start": 1735725600000, "requests": 3, ...}]}
+//! }
+//! ```
+//!
+//! `period` is `7d`, `30d`, `12m` or `all`, the `step` of the time series `hour` or `day`.
+//! Changes are against the previous period of the same length and all `null` for all time.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use persister::{EndpointLatency, TimePoint};
+use serde::Serialize;
+
+use crate::{HostSummary, Links, Report, ReportPeriod};
+
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Totals {
+    requests: i32,
+    pageviews: i32,
+    unique_visitors: i32,
+    human_requests: i32,
+    bot_requests: i32,
+    vpn_requests: i32,
+    bytes_sent: i64,
+    avg_response_time: f64,
+    bot_share_percent: f64,
+}
+
+#[derive(Serialize)]
+struct Changes {
+    requests_percent: Option<f64>,
+    pageviews_percent: Option<f64>,
+    unique_visitors_percent: Option<f64>,
+    bytes_sent_percent: Option<f64>,
+    avg_response_time_percent: Option<f64>,
+    /// percentage points
+    bot_share_points: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct Sessions {
+    sessions: i32,
+    bounces: i32,
+    avg_duration: f64,
+    avg_pages: f64,
+}
+
+#[derive(Serialize)]
+struct Percentiles {
+    p50: f64,
+    p90: f64,
+    p95: f64,
+    p99: f64,
+}
+
+impl Percentiles {
+    fn new(percentiles: &persister::Percentiles) -> Percentiles {
+        Percentiles {
+            p50: percentiles.p50,
+            p90: percentiles.p90,
+            p95: percentiles.p95,
+            p99: percentiles.p99,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Endpoint<'a> {
+    path: &'a str,
+    requests: i32,
+    request_time: Percentiles,
+    upstream_time: Option<Percentiles>,
+}
+
+impl<'a> Endpoint<'a> {
+    fn list(endpoints: &'a [EndpointLatency]) -> Vec<Endpoint<'a>> {
+        endpoints
+            .iter()
+            .map(|endpoint| Endpoint {
+                path: &endpoint.path,
+                requests: endpoint.requests,
+                request_time: Percentiles::new(&endpoint.request_time),
+                upstream_time: endpoint.upstream_time.as_ref().map(Percentiles::new),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct Latency<'a> {
+    request_time: Percentiles,
+    upstream_time: Option<Percentiles>,
+    slowest: Vec<Endpoint<'a>>,
+}
+
+#[derive(Serialize)]
+struct Hits<'a> {
+    value: &'a str,
+    hits: i32,
+}
+
+fn hits(list: &[(String, i32)]) -> Vec<Hits<'_>> {
+    list.iter()
+        .map(|(value, hits)| Hits { value, hits: *hits })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Top<'a> {
+    pages: Vec<Hits<'a>>,
+    countries: Vec<Hits<'a>>,
+    cities: Vec<Hits<'a>>,
+    referrers: Vec<Hits<'a>>,
+}
+
+#[derive(Serialize)]
+struct Classes {
+    #[serde(rename = "1xx")]
+    informational: i32,
+    #[serde(rename = "2xx")]
+    success: i32,
+    #[serde(rename = "3xx")]
+    redirection: i32,
+    #[serde(rename = "4xx")]
+    client_error: i32,
+    #[serde(rename = "5xx")]
+    server_error: i32,
+}
+
+impl Classes {
+    fn new(classes: [i32; 5]) -> Classes {
+        let [
+            informational,
+            success,
+            redirection,
+            client_error,
+            server_error,
+        ] = classes;
+        Classes {
+            informational,
+            success,
+            redirection,
+            client_error,
+            server_error,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BrokenLink<'a> {
+    path: &'a str,
+    hits: i32,
+    referrers: Vec<Hits<'a>>,
+}
+
+#[derive(Serialize)]
+struct Status<'a> {
+    classes: Classes,
+    error_rate_percent: f64,
+    human_errors: i32,
+    bot_errors: i32,
+    not_found: Vec<BrokenLink<'a>>,
+    server_errors: Vec<Endpoint<'a>>,
+}
+
+#[derive(Serialize)]
+struct Bytes<'a> {
+    value: &'a str,
+    bytes: i64,
+}
+
+fn bytes(list: &[(String, i64)]) -> Vec<Bytes<'_>> {
+    list.iter()
+        .map(|(value, bytes)| Bytes {
+            value,
+            bytes: *bytes,
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Bandwidth<'a> {
+    paths: Vec<Bytes<'a>>,
+    countries: Vec<Bytes<'a>>,
+    bots: Vec<Bytes<'a>>,
+}
+
+#[derive(Serialize)]
+struct Point {
+    start: i64,
+    requests: i32,
+    pageviews: i32,
+    unique_visitors: i32,
+    human_requests: i32,
+    bot_requests: i32,
+    avg_response_time: f64,
+    bytes_sent: i64,
+    status: Classes,
+}
+
+impl Point {
+    fn new(point: &TimePoint) -> Point {
+        Point {
+            start: point.bucket,
+            requests: point.requests,
+            pageviews: point.pageviews,
+            unique_visitors: point.unique_visitors,
+            human_requests: point.human_requests,
+            bot_requests: point.bot_requests,
+            avg_response_time: point.avg_response_time,
+            bytes_sent: point.bytes_sent,
+            status: Classes::new(point.status),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Timeseries {
+    step: &'static str,
+    points: Vec<Point>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct JsonReport<'a> {
+    schema_version: u32,
+    host: &'a str,
+    period: &'static str,
+    generated_at: String,
+    totals: Totals,
+    changes: Changes,
+    sessions: Sessions,
+    latency: Latency<'a>,
+    top: Top<'a>,
+    status: Status<'a>,
+    bandwidth: Bandwidth<'a>,
+    timeseries: Timeseries,
+}
+
+/// The report as the document the JSON files hold, user templates get it too.
+pub(crate) fn document(report: &Report) -> JsonReport<'_> {
+    let Report {
+        stats,
+        changes,
+        status,
+        bandwidth,
+        ..
+    } = report;
+    JsonReport {
+        schema_version: JSON_SCHEMA_VERSION,
+        host: &report.host,
+        period: report.period.key(),
+        generated_at: report
+            .generated_at
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+        totals: Totals {
+            requests: stats.total_requests,
+            pageviews: stats.pageviews,
+            unique_visitors: stats.unique_visitors,
+            human_requests: stats.human_requests,
+            bot_requests: stats.bot_requests,
+            vpn_requests: stats.vpn_requests,
+            bytes_sent: stats.bytes_sent,
+            avg_response_time: f64::from(stats.avg_response_time),
+            bot_share_percent: stats.bot_share(),
+        },
+        changes: Changes {
+            requests_percent: changes.total_requests,
+            pageviews_percent: changes.pageviews,
+            unique_visitors_percent: changes.unique_visitors,
+            bytes_sent_percent: changes.bytes_sent,
+            avg_response_time_percent: changes.avg_response_time,
+            bot_share_points: changes.bot_share,
+        },
+        sessions: Sessions {
+            sessions: stats.sessions.sessions,
+            bounces: stats.sessions.bounces,
+            avg_duration: stats.sessions.avg_duration,
+            avg_pages: stats.sessions.avg_pages,
+        },
+        latency: Latency {
+            request_time: Percentiles::new(&stats.latency.request_time),
+            upstream_time: stats.latency.upstream_time.as_ref().map(Percentiles::new),
+            slowest: Endpoint::list(&stats.latency.slowest),
+        },
+        top: Top {
+            pages: hits(&stats.pages),
+            countries: hits(&stats.countries),
+            cities: hits(&stats.cities),
+            referrers: hits(&stats.referrers),
+        },
+        status: Status {
+            classes: Classes::new(status.classes),
+            error_rate_percent: status.error_rate(),
+            human_errors: status.human_errors,
+            bot_errors: status.bot_errors,
+            not_found: status
+                .not_found
+                .iter()
+                .map(|link| BrokenLink {
+                    path: &link.path,
+                    hits: link.hits,
+                    referrers: hits(&link.referrers),
+                })
+                .collect(),
+            server_errors: Endpoint::list(&status.server_errors),
+        },
+        bandwidth: Bandwidth {
+            paths: bytes(&bandwidth.paths),
+            countries: bytes(&bandwidth.countries),
+            bots: bytes(&bandwidth.bots),
+        },
+        timeseries: Timeseries {
+            step: report.step.as_str(),
+            points: report.timeseries.iter().map(Point::new).collect(),
+        },
+    }
+}
+
+/// The report as a JSON document.
+pub(crate) fn report(report: &Report) -> String {
+    serde_json::to_string_pretty(&document(report)).unwrap()
+}
+
+#[derive(Serialize)]
+struct IndexHost<'a> {
+    host: &'a str,
+    /// of the last 30 days
+    pageviews: i32,
+    unique_visitors: i32,
+    requests: i32,
+    /// paths of the reports relative to `index.json`, or their urls, by period
+    reports: BTreeMap<&'static str, String>,
+}
+
+#[derive(Serialize)]
+struct JsonIndex<'a> {
+    schema_version: u32,
+    generated_at: String,
+    hosts: Vec<IndexHost<'a>>,
+}
+
+/// The hosts with a report and where to find them.
+pub(crate) fn index(hosts: &[HostSummary], generated_at: DateTime<Utc>, links: &Links) -> String {
+    let json = JsonIndex {
+        schema_version: JSON_SCHEMA_VERSION,
+        generated_at: generated_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        hosts: hosts
+            .iter()
+            .map(|summary| IndexHost {
+                host: &summary.host,
+                pageviews: summary.pageviews,
+                unique_visitors: summary.unique_visitors,
+                requests: summary.total_requests,
+                reports: ReportPeriod::ALL
+                    .iter()
+                    .map(|period| (period.key(), links.host_json(&summary.host, *period)))
+                    .collect(),
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persister::{Period, Stats, StatusReport};
+    use serde_json::Value;
+
+    #[test]
+    fn writes_the_documented_fields() {
+        let report = Report {
+            host: String::from("example.com"),
+            period: ReportPeriod::Week,
+            stats: Stats {
+                total_requests: 4,
+                bot_requests: 1,
+                pages: vec![(String::from("/"), 2)],
+                ..Stats::default()
+            },
+            step: Period::Hour,
+            timeseries: vec![TimePoint {
+                bucket: 1_735_725_600_000,
+                requests: 4,
+                status: [0, 4, 0, 0, 0],
+                ..TimePoint::default()
+            }],
+            changes: persister::Changes {
+                total_requests: Some(12.5),
+                ..persister::Changes::default()
+            },
+            status: StatusReport {
+                classes: [0, 4, 0, 0, 0],
+                ..StatusReport::default()
+            },
+            bandwidth: persister::Bandwidth::default(),
+            generated_at: DateTime::from_timestamp(1_735_725_600, 0).unwrap(),
+        };
+        let json: Value = serde_json::from_str(&super::report(&report)).unwrap();
+        assert_eq!(json["schema_version"], JSON_SCHEMA_VERSION);
+        assert_eq!(json["period"], "7d");
+        assert_eq!(json["generated_at"], "2025-01-01T10:00:00Z");
+        assert_eq!(json["totals"]["requests"], 4);
+        assert_eq!(json["totals"]["bot_share_percent"], 25.0);
+        assert_eq!(json["changes"]["requests_percent"], 12.5);
+        assert_eq!(json["changes"]["pageviews_percent"], Value::Null);
+        assert_eq!(json["top"]["pages"][0]["value"], "/");
+        assert_eq!(json["status"]["classes"]["2xx"], 4);
+        assert_eq!(json["latency"]["upstream_time"], Value::Null);
+        assert_eq!(json["timeseries"]["step"], "hour");
+        assert_eq!(
+            json["timeseries"]["points"][0]["start"],
+            1_735_725_600_000_i64
+        );
+        assert_eq!(json["timeseries"]["points"][0]["status"]["2xx"], 4);
+
+        let hosts = [HostSummary::new("example.com", &report.stats)];
+        let index = index(&hosts, report.generated_at, &Links::Files);
+        let index: Value = serde_json::from_str(&index).unwrap();
+        assert_eq!(
+            index["hosts"][0]["reports"]["30d"],
+            "example.com/stats-30d.json"
+        );
+    }
+}
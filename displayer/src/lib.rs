@@ -1,126 +1,861 @@
+mod charts;
+mod json;
+mod links;
+mod metrics;
+mod templates;
+
 use askama::Template;
-use persister::Stats;
-use std::fs::File;
+use charts::{Chart, charts, status_chart};
+use chrono::{DateTime, Utc};
+use persister::{
+    Bandwidth, Changes, EndpointLatency, Percentiles, Period, Stats, StatusReport, TimePoint,
+};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub use json::JSON_SCHEMA_VERSION;
+pub use links::{FilterForm, Links, url_encode};
+use links::{NavLink, nav};
+use metrics::{Ranked, bar_width};
+pub use metrics::{
+    format_bytes, format_count, format_duration, format_latency, format_percent, percent,
+};
+pub use templates::{Appearance, HostPanels, PANELS, Pages, Panels, Theme};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// The time ranges every host gets a page for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportPeriod {
+    Week,
+    Month,
+    Year,
+    All,
+}
+
+impl ReportPeriod {
+    pub const ALL: [ReportPeriod; 4] = [
+        ReportPeriod::Week,
+        ReportPeriod::Month,
+        ReportPeriod::Year,
+        ReportPeriod::All,
+    ];
+
+    /// `7d`, how the JSON reports name the period
+    pub fn key(&self) -> &'static str {
+        match self {
+            ReportPeriod::Week => "7d",
+            ReportPeriod::Month => "30d",
+            ReportPeriod::Year => "12m",
+            ReportPeriod::All => "all",
+        }
+    }
+
+    /// The period `key()` names, `7d`, `30d`, `12m` or `all`.
+    pub fn parse(key: &str) -> Result<ReportPeriod, &'static str> {
+        ReportPeriod::ALL
+            .into_iter()
+            .find(|period| period.key() == key)
+            .ok_or("unknown period, expected 7d, 30d, 12m or all")
+    }
+
+    /// `stats-7d.json`, next to the page
+    pub fn json_file_name(&self) -> String {
+        format!("stats-{}.json", self.key())
+    }
+
+    /// `stats-7d.html`, what the navigation of the pages links to
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ReportPeriod::Week => "stats-7d.html",
+            ReportPeriod::Month => "stats-30d.html",
+            ReportPeriod::Year => "stats-12m.html",
+            ReportPeriod::All => "stats-all.html",
+        }
+    }
+
+    /// `status-7d.html`, the status codes of the period on their own
+    pub fn status_file_name(&self) -> &'static str {
+        match self {
+            ReportPeriod::Week => "status-7d.html",
+            ReportPeriod::Month => "status-30d.html",
+            ReportPeriod::Year => "status-12m.html",
+            ReportPeriod::All => "status-all.html",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReportPeriod::Week => "Last 7 days",
+            ReportPeriod::Month => "Last 30 days",
+            ReportPeriod::Year => "Last 12 months",
+            ReportPeriod::All => "All time",
+        }
+    }
+
+    /// What changes are measured against, `vs the previous 7 days`.
+    pub fn previous_label(&self) -> String {
+        match self {
+            ReportPeriod::All => String::new(),
+            period => period.label().replace("Last", "vs the previous"),
+        }
+    }
+
+    /// Bucket length of the charts, hours for the last week and days otherwise.
+    pub fn step(&self) -> Period {
+        match self {
+            ReportPeriod::Week => Period::Hour,
+            _ => Period::Day,
+        }
+    }
+
+    /// Start of the period ending at `now`, unix milliseconds, `None` for all time.
+    pub fn since(&self, now: i64) -> Option<i64> {
+        match self {
+            ReportPeriod::Week => Some(now - 7 * DAY_MS),
+            ReportPeriod::Month => Some(now - 30 * DAY_MS),
+            ReportPeriod::Year => Some(now - 365 * DAY_MS),
+            ReportPeriod::All => None,
+        }
+    }
+}
+
+/// Everything a report page shows.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub host: String,
+    pub period: ReportPeriod,
+    pub stats: Stats,
+    /// bucket length of `timeseries`, from the range the report covers
+    pub step: Period,
+    pub timeseries: Vec<TimePoint>,
+    /// against the previous period, nothing for all time
+    pub changes: Changes,
+    pub status: StatusReport,
+    pub bandwidth: Bandwidth,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// The name of a host's directory, hosts come from request headers and could be anything.
+pub fn host_dir(host: &str) -> String {
+    let dir: String = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dir.trim_start_matches('.').to_string()
+}
+
+/// A line of the index page.
+#[derive(Debug, Clone)]
+pub struct HostSummary {
+    pub host: String,
+    pub pageviews: i32,
+    pub unique_visitors: i32,
+    pub total_requests: i32,
+}
+
+impl HostSummary {
+    pub fn new(host: &str, stats: &Stats) -> HostSummary {
+        HostSummary {
+            host: host.to_string(),
+            pageviews: stats.pageviews,
+            unique_visitors: stats.unique_visitors,
+            total_requests: stats.total_requests,
+        }
+    }
+}
+
+struct IndexHost<'a> {
+    host: &'a str,
+    /// the last 30 days
+    href: String,
+    /// the other periods
+    periods: Vec<NavLink>,
+    pageviews: String,
+    unique_visitors: String,
+    total_requests: String,
+}
+
+#[derive(Template)]
+#[template(path = "index.html")]
+struct IndexTemplate<'a> {
+    theme: &'static str,
+    generated_at: &'a str,
+    hosts: Vec<IndexHost<'a>>,
+}
 
 #[derive(Template)]
 #[template(path = "stats.html")]
 pub struct StatsTemplate<'a> {
-    active_7d: &'a str,
-    active_30d: &'a str,
-    active_12m: &'a str,
-    active_all: &'a str,
+    theme: &'static str,
+    panels: Panels,
+    index: String,
+    nav: Vec<NavLink>,
+    /// only on the pages of `kirinox serve`
+    form: Option<FilterForm>,
 
     domain: &'a str,
     generated_at: &'a str,
     date_range: &'a str,
-    total_requests: i32,
-    unique_visitors: i32,
-    human_requests: i32,
-    human_percent: f64,
-    bot_requests: i32,
-    avg_response_time: f32,
-    vpn_requests: i32,
-    vpn_percent: f64,
-    top_pages: Vec<TopPage>,
-    top_countries: Vec<Country>,
-    top_cities: Vec<City>,
-    top_referrers: Vec<Referrer>,
-}
-
-struct TopPage {
-    path: String,
-    hits: i32,
-    percent: f32,
+    total_requests: String,
+    pageviews: String,
+    unique_visitors: String,
+    human_requests: String,
+    /// of all requests
+    human_percent: String,
+    bot_requests: String,
+    avg_response_time: String,
+    response_time_percentiles: String,
+    bot_share: String,
+    compared_to: String,
+    requests_trend: Trend,
+    pageviews_trend: Trend,
+    visitors_trend: Trend,
+    response_trend: Trend,
+    bot_share_trend: Trend,
+    bytes_sent: String,
+    bandwidth_trend: Trend,
+    bandwidth_chart: String,
+    bandwidth_paths: Vec<Usage>,
+    bandwidth_countries: Vec<Usage>,
+    bandwidth_bots: Vec<Usage>,
+    vpn_requests: String,
+    /// of all requests
+    vpn_percent: String,
+    sessions: String,
+    /// of all sessions
+    bounce_rate: String,
+    avg_session_duration: String,
+    pages_per_session: String,
+    /// shares of all pageviews
+    top_pages: Vec<Ranked>,
+    /// shares of all requests, like cities and referrers
+    top_countries: Vec<Ranked>,
+    top_cities: Vec<Ranked>,
+    top_referrers: Vec<Ranked>,
+    slowest_endpoints: Vec<Endpoint>,
+    charts: Vec<Chart>,
+    status: StatusSection,
 }
 
-struct Country {
-    name: String,
-    count: i32,
-    percent: f32,
+#[derive(Template)]
+#[template(path = "status.html")]
+struct StatusTemplate<'a> {
+    theme: &'static str,
+    index: String,
+    nav: Vec<NavLink>,
+
+    domain: &'a str,
+    generated_at: &'a str,
+    date_range: &'a str,
+    /// the stats page of the same period
+    stats_page: String,
+    status: StatusSection,
 }
 
-struct City {
+/// A line of the bandwidth tables.
+struct Usage {
     name: String,
-    count: i32,
-    percent: f32,
+    bytes: String,
+    /// of all bytes sent
+    percent: f64,
 }
 
-struct Referrer {
-    url: String,
-    count: i32,
-    percent: f32,
+impl Usage {
+    fn list(usage: &[(String, i64)], total: i64) -> Vec<Usage> {
+        usage
+            .iter()
+            .map(|(name, bytes)| Usage {
+                name: name.clone(),
+                bytes: format_bytes(*bytes as f64),
+                percent: bar_width(*bytes as f64, total as f64),
+            })
+            .collect()
+    }
 }
 
-pub struct Displayer {}
+/// Change badge and sparkline of a headline card.
+struct Trend {
+    /// `▲ 12.5%`, empty when there is nothing to compare with
+    change: String,
+    /// `up` when the change is good news, `down` when it's bad
+    class: &'static str,
+    sparkline: String,
+}
 
-impl Displayer {
-    pub fn get_template<'a>(&self, stats: Stats, host: &'a str) {
-        let mut top_pages = vec![];
-        let mut countries = vec![];
-        let mut cities = vec![];
-        let mut referrers = vec![];
-        for page in stats.pages {
-            top_pages.push(TopPage {
-                path: page.0,
-                hits: page.1,
-                percent: 0.0,
-            })
+impl Trend {
+    /// `rising_is_good` tells whether a higher number is good news, it isn't for response
+    /// times. `unit` follows the number, `%` or ` pts`.
+    fn new(change: Option<f64>, rising_is_good: bool, unit: &str, values: &[f64]) -> Trend {
+        let sparkline = charts::sparkline(values);
+        let Some(change) = change.filter(|change| change.abs() >= 0.05) else {
+            return Trend {
+                change: change.map(|_| format!("0.0{unit}")).unwrap_or_default(),
+                class: "",
+                sparkline,
+            };
+        };
+        let arrow = if change > 0.0 { '▲' } else { '▼' };
+        Trend {
+            change: format!("{arrow} {:.1}{unit}", change.abs()),
+            class: if (change > 0.0) == rising_is_good {
+                "up"
+            } else {
+                "down"
+            },
+            sparkline,
         }
+    }
+}
 
-        for country in stats.countries {
-            countries.push(Country {
-                name: country.0,
-                count: country.1,
-                percent: 0.0,
-            })
+struct Endpoint {
+    path: String,
+    requests: String,
+    p50: String,
+    p95: String,
+    p99: String,
+    /// p95 of the upstream time, empty when nginx served it itself
+    upstream_p95: String,
+}
+
+impl Endpoint {
+    fn new(endpoint: &EndpointLatency) -> Endpoint {
+        Endpoint {
+            path: endpoint.path.clone(),
+            requests: format_count(endpoint.requests),
+            p50: format_latency(endpoint.request_time.p50),
+            p95: format_latency(endpoint.request_time.p95),
+            p99: format_latency(endpoint.request_time.p99),
+            upstream_p95: endpoint
+                .upstream_time
+                .map(|upstream| format_latency(upstream.p95))
+                .unwrap_or_default(),
         }
-        for city in stats.cities {
-            cities.push(City {
-                name: city.0,
-                count: city.1,
-                percent: 0.0,
+    }
+}
+
+struct StatusClass {
+    label: String,
+    count: String,
+    /// of all answered requests
+    percent: String,
+}
+
+/// A line of the broken links.
+struct NotFound {
+    path: String,
+    hits: String,
+    /// shares of the hits
+    referrers: Vec<Ranked>,
+}
+
+/// The status codes part of a report, on the stats page and on its own.
+struct StatusSection {
+    /// link to the page of its own, empty on that page
+    page: String,
+    classes: Vec<StatusClass>,
+    chart: String,
+    error_rate: String,
+    human_errors: String,
+    bot_errors: String,
+    /// `75.0% of errors went to humans`, empty without errors
+    error_split: String,
+    not_found: Vec<NotFound>,
+    server_errors: Vec<Endpoint>,
+}
+
+impl StatusSection {
+    fn new(status: &StatusReport, timeseries: &[TimePoint], step: Period) -> StatusSection {
+        let total: i32 = status.classes.iter().sum();
+        let classes = status
+            .classes
+            .iter()
+            .enumerate()
+            .map(|(class, count)| StatusClass {
+                label: format!("{}xx", class + 1),
+                count: format_count(*count),
+                percent: format_percent(percent(f64::from(*count), f64::from(total))),
             })
+            .collect();
+        let points: Vec<(i64, [i32; 5])> = timeseries
+            .iter()
+            .map(|point| (point.bucket, point.status))
+            .collect();
+        StatusSection {
+            page: String::new(),
+            classes,
+            chart: status_chart(&points, step),
+            error_rate: format_percent(status.error_rate()),
+            human_errors: format_count(status.human_errors),
+            bot_errors: format_count(status.bot_errors),
+            error_split: status
+                .human_error_share()
+                .map(|share| format!("{} of errors went to humans", format_percent(share)))
+                .unwrap_or_default(),
+            not_found: status
+                .not_found
+                .iter()
+                .map(|link| NotFound {
+                    path: link.path.clone(),
+                    hits: format_count(link.hits),
+                    referrers: Ranked::list(&link.referrers, link.hits),
+                })
+                .collect(),
+            server_errors: status.server_errors.iter().map(Endpoint::new).collect(),
         }
+    }
+}
+
+/// Writes the report pages, `<output_dir>/<host>/stats-7d.html` and so on for every host and
+/// `<output_dir>/index.html` listing the hosts.
+pub struct Displayer {
+    output_dir: PathBuf,
+    pages: Pages,
+}
+
+pub(crate) fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn format_percentiles(percentiles: &Percentiles) -> String {
+    format!(
+        "p50 {} · p95 {} · p99 {}",
+        format_latency(percentiles.p50),
+        format_latency(percentiles.p95),
+        format_latency(percentiles.p99)
+    )
+}
+
+/// The built-in stats page of `report`, with the filter form when `form` is given.
+pub(crate) fn stats_page(
+    report: &Report,
+    links: &Links,
+    form: Option<FilterForm>,
+    theme: Theme,
+    panels: Panels,
+) -> io::Result<String> {
+    let Report {
+        host,
+        period,
+        stats,
+        step,
+        timeseries,
+        changes,
+        status,
+        bandwidth,
+        generated_at,
+    } = report;
+    let period = *period;
+    let step = *step;
+    let generated_at = format_time(*generated_at);
+    let series =
+        |value: fn(&TimePoint) -> f64| -> Vec<f64> { timeseries.iter().map(value).collect() };
+    let bot_shares =
+        series(|point| percent(f64::from(point.bot_requests), f64::from(point.requests)));
+    let slowest_endpoints = stats.latency.slowest.iter().map(Endpoint::new).collect();
+    let requests = f64::from(stats.total_requests);
+    let page = StatsTemplate {
+        theme: theme.key(),
+        panels,
+        index: links.index(),
+        nav: nav(period, |period| links.stats(host, period)),
+        form,
+        domain: host,
+        generated_at: &generated_at,
+        date_range: period.label(),
+        total_requests: format_count(stats.total_requests),
+        pageviews: format_count(stats.pageviews),
+        unique_visitors: format_count(stats.unique_visitors),
+        human_requests: format_count(stats.human_requests),
+        human_percent: format_percent(percent(f64::from(stats.human_requests), requests)),
+        bot_requests: format_count(stats.bot_requests),
+        avg_response_time: format_latency(f64::from(stats.avg_response_time)),
+        response_time_percentiles: format_percentiles(&stats.latency.request_time),
+        bot_share: format_percent(stats.bot_share()),
+        compared_to: period.previous_label(),
+        requests_trend: Trend::new(
+            changes.total_requests,
+            true,
+            "%",
+            &series(|point| f64::from(point.requests)),
+        ),
+        pageviews_trend: Trend::new(
+            changes.pageviews,
+            true,
+            "%",
+            &series(|point| f64::from(point.pageviews)),
+        ),
+        visitors_trend: Trend::new(
+            changes.unique_visitors,
+            true,
+            "%",
+            &series(|point| f64::from(point.unique_visitors)),
+        ),
+        response_trend: Trend::new(
+            changes.avg_response_time,
+            false,
+            "%",
+            &series(|point| point.avg_response_time),
+        ),
+        bot_share_trend: Trend::new(changes.bot_share, false, " pts", &bot_shares),
+        bytes_sent: format_bytes(stats.bytes_sent as f64),
+        // bytes are egress that has to be paid for
+        bandwidth_trend: Trend::new(
+            changes.bytes_sent,
+            false,
+            "%",
+            &series(|point| point.bytes_sent as f64),
+        ),
+        bandwidth_chart: charts::area_chart(
+            &timeseries
+                .iter()
+                .map(|point| (point.bucket, point.bytes_sent as f64))
+                .collect::<Vec<(i64, f64)>>(),
+            step,
+            format_bytes,
+        ),
+        bandwidth_paths: Usage::list(&bandwidth.paths, stats.bytes_sent),
+        bandwidth_countries: Usage::list(&bandwidth.countries, stats.bytes_sent),
+        bandwidth_bots: Usage::list(&bandwidth.bots, stats.bytes_sent),
+        vpn_requests: format_count(stats.vpn_requests),
+        vpn_percent: format_percent(percent(f64::from(stats.vpn_requests), requests)),
+        sessions: format_count(stats.sessions.sessions),
+        bounce_rate: format_percent(percent(
+            f64::from(stats.sessions.bounces),
+            f64::from(stats.sessions.sessions),
+        )),
+        avg_session_duration: format_duration(stats.sessions.avg_duration),
+        pages_per_session: format!("{:.1}", stats.sessions.avg_pages),
+        top_pages: Ranked::list(&stats.pages, stats.pageviews),
+        top_countries: Ranked::list(&stats.countries, stats.total_requests),
+        top_cities: Ranked::list(&stats.cities, stats.total_requests),
+        top_referrers: Ranked::list(&stats.referrers, stats.total_requests),
+        slowest_endpoints,
+        charts: charts(timeseries, step),
+        status: StatusSection {
+            page: links.status(host, period),
+            ..StatusSection::new(status, timeseries, step)
+        },
+    };
+    page.render().map_err(io::Error::other)
+}
 
-        for referrer in stats.referrers {
-            referrers.push(Referrer {
-                url: referrer.0,
-                count: referrer.1,
-                percent: 0.0,
+/// The built-in status page of `report`.
+pub(crate) fn status_page(report: &Report, links: &Links, theme: Theme) -> io::Result<String> {
+    let host = &report.host;
+    let period = report.period;
+    let page = StatusTemplate {
+        theme: theme.key(),
+        index: links.index(),
+        nav: nav(period, |period| links.status(host, period)),
+        domain: host,
+        generated_at: &format_time(report.generated_at),
+        date_range: period.label(),
+        stats_page: links.stats(host, period),
+        status: StatusSection::new(&report.status, &report.timeseries, report.step),
+    };
+    page.render().map_err(io::Error::other)
+}
+
+/// The built-in landing page listing `hosts`.
+pub(crate) fn index_page(
+    hosts: &[HostSummary],
+    generated_at: DateTime<Utc>,
+    links: &Links,
+    theme: Theme,
+) -> io::Result<String> {
+    let page = IndexTemplate {
+        theme: theme.key(),
+        generated_at: &format_time(generated_at),
+        hosts: hosts
+            .iter()
+            .map(|summary| IndexHost {
+                host: &summary.host,
+                href: links.host_stats(&summary.host, ReportPeriod::Month),
+                periods: [ReportPeriod::Week, ReportPeriod::Year, ReportPeriod::All]
+                    .into_iter()
+                    .map(|period| NavLink {
+                        label: period.key(),
+                        href: links.host_stats(&summary.host, period),
+                        active: "",
+                    })
+                    .collect(),
+                pageviews: format_count(summary.pageviews),
+                unique_visitors: format_count(summary.unique_visitors),
+                total_requests: format_count(summary.total_requests),
             })
+            .collect(),
+    };
+    page.render().map_err(io::Error::other)
+}
+
+/// The JSON report, see `json` for the schema.
+pub fn report_json(report: &Report) -> String {
+    json::report(report)
+}
+
+/// The JSON index listing `hosts` and their reports.
+pub fn index_json(hosts: &[HostSummary], generated_at: DateTime<Utc>, links: &Links) -> String {
+    json::index(hosts, generated_at, links)
+}
+
+impl Displayer {
+    pub fn new(output_dir: &Path) -> Displayer {
+        Displayer {
+            output_dir: output_dir.to_path_buf(),
+            pages: Pages::default(),
         }
-        let res  = StatsTemplate {
-            active_7d: "nah",
-            active_30d: "nah",
-            active_12m: "nah",
-            active_all: "active",
-            domain: host,
-            generated_at: "today",
-            date_range: "all time",
-            total_requests: stats.total_requests,
-            unique_visitors: stats.unique_visitors,
-            human_requests: stats.human_requests,
-            human_percent: f64::from(stats.total_requests) / f64::from(stats.human_requests),
-            bot_requests: stats.total_requests - stats.human_requests,
-            avg_response_time: stats.avg_response_time,
-            vpn_requests: stats.vpn_requests,
-            vpn_percent: f64::from(stats.total_requests) / f64::from(stats.vpn_requests),
-            top_pages: top_pages,
-            top_countries: countries,
-            top_cities: cities,
-            top_referrers: referrers,
-        };
-        let mut writer = File::create("stats.html").unwrap();
-        res.write_into(&mut writer).unwrap();
+    }
+
+    /// Renders with `pages` instead of the built-in pages in the dark theme.
+    pub fn pages(mut self, pages: Pages) -> Displayer {
+        self.pages = pages;
+        self
+    }
+
+    /// Writes the stats and status pages and the JSON report of the report's host and period
+    /// and returns the path of the stats page.
+    pub fn write_report(&self, report: Report) -> io::Result<PathBuf> {
+        let dir = self.output_dir.join(host_dir(&report.host));
+        fs::create_dir_all(&dir)?;
+        fs::write(
+            dir.join(report.period.json_file_name()),
+            report_json(&report),
+        )?;
+        fs::write(
+            dir.join(report.period.status_file_name()),
+            self.pages.status(&report, &Links::Files)?,
+        )?;
+        let path = dir.join(report.period.file_name());
+        fs::write(&path, self.pages.stats(&report, &Links::Files, None)?)?;
+        Ok(path)
+    }
+
+    /// Writes the landing page linking to the pages of every host, and `index.json` listing
+    /// their JSON reports.
+    pub fn write_index(
+        &self,
+        hosts: &[HostSummary],
+        generated_at: DateTime<Utc>,
+    ) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.output_dir)?;
+        fs::write(
+            self.output_dir.join("index.json"),
+            index_json(hosts, generated_at, &Links::Files),
+        )?;
+        let path = self.output_dir.join("index.html");
+        fs::write(&path, self.pages.index(hosts, generated_at, &Links::Files)?)?;
+        Ok(path)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use persister::BrokenLink;
 
     #[test]
     fn it_works() {
         assert_eq!(4, 4);
     }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(0.0), "0s");
+        assert_eq!(format_duration(42_400.0), "42s");
+        assert_eq!(format_duration(135_000.0), "2m 15s");
+    }
+
+    #[test]
+    fn writes_a_page_per_host_and_period_and_an_index() {
+        let dir = std::env::temp_dir().join(format!("kirinox-displayer-{}", std::process::id()));
+        let displayer = Displayer::new(&dir);
+        let stats = Stats {
+            bytes_sent: 4096,
+            ..Stats::default()
+        };
+        for period in ReportPeriod::ALL {
+            let report = Report {
+                host: String::from("example.com"),
+                period,
+                stats: stats.clone(),
+                step: period.step(),
+                timeseries: vec![TimePoint::default()],
+                changes: Changes {
+                    total_requests: Some(12.5),
+                    avg_response_time: Some(-3.0),
+                    ..Changes::default()
+                },
+                status: StatusReport {
+                    classes: [0, 6, 0, 2, 0],
+                    not_found: vec![BrokenLink {
+                        path: String::from("/old"),
+                        hits: 2,
+                        referrers: vec![(String::from("https://news.example/"), 2)],
+                    }],
+                    human_errors: 1,
+                    bot_errors: 1,
+                    ..StatusReport::default()
+                },
+                bandwidth: Bandwidth {
+                    bots: vec![(String::from("googlebot"), 2048)],
+                    ..Bandwidth::default()
+                },
+                generated_at: DateTime::from_timestamp(1_735_725_600, 0).unwrap(),
+            };
+            let path = displayer.write_report(report).unwrap();
+            assert_eq!(path, dir.join("example.com").join(period.file_name()));
+        }
+        let week = fs::read_to_string(dir.join("example.com/stats-7d.html")).unwrap();
+        assert!(week.contains("Last 7 days"));
+        assert!(week.contains(r#"<a href="stats-7d.html" class="active">"#));
+        assert!(week.contains(r#"<a href="stats-all.html" class="">"#));
+        assert!(week.contains(r#"<svg class="chart""#));
+        assert!(week.contains(
+            r#"<span class="stat-change up" title="vs the previous 7 days">▲ 12.5%</span>"#
+        ));
+        // faster responses are good news
+        assert!(week.contains(
+            r#"<span class="stat-change up" title="vs the previous 7 days">▼ 3.0%</span>"#
+        ));
+
+        assert!(week.contains(r#"<a class="section-link" href="status-7d.html">"#));
+        assert!(week.contains("25.0% of requests were errors"));
+        assert!(week.contains("4.0 KiB"));
+        assert!(week.contains(r#"<td class="num">2.0 KiB</td>"#));
+        assert!(week.contains(r#"style="width: 50%""#));
+        let status = fs::read_to_string(dir.join("example.com/status-7d.html")).unwrap();
+        assert!(status.contains(r#"<a href="stats-7d.html">example.com</a>"#));
+        assert!(status.contains(r#"<a href="status-30d.html" class="">"#));
+        assert!(status.contains("&larr; https://news.example/ (2)"));
+        assert!(status.contains("50.0% of errors went to humans"));
+        assert!(!status.contains(r#"<a class="section-link""#));
+
+        let hosts = [HostSummary::new("example.com", &stats)];
+        displayer
+            .write_index(&hosts, DateTime::from_timestamp(1_735_725_600, 0).unwrap())
+            .unwrap();
+        let index = fs::read_to_string(dir.join("index.html")).unwrap();
+        assert!(index.contains(r#"<a href="example.com/stats-30d.html">example.com</a>"#));
+        assert!(index.contains("2025-01-01 10:00 UTC"));
+        assert!(dir.join("index.json").exists());
+        assert!(dir.join("example.com/stats-all.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn derives_shares_of_the_right_totals() {
+        let mut report = Report {
+            host: String::from("example.com"),
+            period: ReportPeriod::Week,
+            stats: Stats {
+                total_requests: 2000,
+                human_requests: 1500,
+                bot_requests: 500,
+                pageviews: 4,
+                avg_response_time: 1250.0,
+                pages: vec![(String::from("/"), 3)],
+                countries: vec![(String::from("Germany"), 500)],
+                ..Stats::default()
+            },
+            step: Period::Hour,
+            timeseries: Vec::new(),
+            changes: Changes::default(),
+            status: StatusReport::default(),
+            bandwidth: Bandwidth::default(),
+            generated_at: DateTime::from_timestamp(1_735_725_600, 0).unwrap(),
+        };
+        let page = Pages::default()
+            .stats(&report, &Links::Files, None)
+            .unwrap();
+        assert!(page.contains(r#"<div class="card-value">1,500</div>"#));
+        assert!(page.contains(r#"<span class="stat-change">75.0%</span>"#));
+        assert!(page.contains(r#"<span class="stat-change">0.0%</span>"#));
+        assert!(page.contains("0.0% bounce"));
+        assert!(page.contains(r#"<div class="card-value">1.25s</div>"#));
+        assert!(page.contains(r#"style="width: 75%""#));
+        assert!(page.contains(r#"style="width: 25%""#));
+
+        // nothing to share in an empty period
+        report.stats = Stats::default();
+        let page = Pages::default()
+            .stats(&report, &Links::Files, None)
+            .unwrap();
+        assert!(!page.contains("inf") && !page.contains("NaN"));
+    }
+
+    #[test]
+    fn renders_server_pages_with_the_filter_form() {
+        let report = Report {
+            host: String::from("example.com"),
+            period: ReportPeriod::Month,
+            stats: Stats::default(),
+            step: Period::Day,
+            timeseries: Vec::new(),
+            changes: Changes::default(),
+            status: StatusReport::default(),
+            bandwidth: Bandwidth::default(),
+            generated_at: DateTime::from_timestamp(1_735_725_600, 0).unwrap(),
+        };
+        let links = Links::Server {
+            query: String::from("&traffic=humans"),
+        };
+        let form = FilterForm {
+            host: String::from("example.com"),
+            period: String::from("30d"),
+            traffic: String::from("humans"),
+            ..FilterForm::default()
+        };
+        let pages = Pages::default();
+        let page = pages.stats(&report, &links, Some(form)).unwrap();
+        assert!(page.contains(r#"<a href="/">All hosts</a>"#));
+        assert!(page.contains(
+            r#"<a href="/stats?host=example.com&#38;period=7d&#38;traffic=humans" class="">"#
+        ));
+        assert!(page.contains(r#"<option value="humans" selected>Humans</option>"#));
+        assert!(page.contains(r#"<input type="hidden" name="period" value="30d">"#));
+        let files = pages.stats(&report, &Links::Files, None).unwrap();
+        assert!(!files.contains("<form"));
+        let hosts = [HostSummary::new("example.com", &report.stats)];
+        let index = pages.index(&hosts, report.generated_at, &links).unwrap();
+        assert!(index.contains(
+            r#"<a href="/stats?host=example.com&#38;period=12m&#38;traffic=humans">12m</a>"#
+        ));
+    }
+
+    #[test]
+    fn parses_period_keys() {
+        for period in ReportPeriod::ALL {
+            assert_eq!(ReportPeriod::parse(period.key()), Ok(period));
+        }
+        assert!(ReportPeriod::parse("1w").is_err());
+    }
+
+    #[test]
+    fn keeps_host_directories_inside_the_output() {
+        assert_eq!(host_dir("example.com"), "example.com");
+        assert_eq!(host_dir("../etc"), "_etc");
+        assert_eq!(host_dir("[::1]:8080"), "___1__8080");
+    }
+
+    #[test]
+    fn formats_latencies() {
+        assert_eq!(format_latency(0.45), "0.5ms");
+        assert_eq!(format_latency(87.4), "87ms");
+        assert_eq!(format_latency(1250.0), "1.25s");
+    }
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(format_bytes(512.0), "512 B");
+        assert_eq!(format_bytes(1536.0), "1.5 KiB");
+        assert_eq!(format_bytes(3.0 * 1024.0 * 1024.0 * 1024.0), "3.0 GiB");
+    }
 }
@@ -0,0 +1,154 @@
+use serde::Serialize;
+
+use crate::{ReportPeriod, host_dir};
+
+/// Where the links between the pages lead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Links {
+    /// the files `Displayer` writes, relative to each other
+    Files,
+    /// the pages of `kirinox serve`, each keeping `query`, the url encoded filter of the page
+    /// without host and period, `&country=Germany`
+    Server { query: String },
+}
+
+impl Links {
+    fn server(path: &str, host: &str, period: ReportPeriod, query: &str) -> String {
+        format!(
+            "{path}?host={}&period={}{query}",
+            url_encode(host),
+            period.key()
+        )
+    }
+
+    /// the index, from a page of a host
+    pub(crate) fn index(&self) -> String {
+        match self {
+            Links::Files => String::from("../index.html"),
+            Links::Server { .. } => String::from("/"),
+        }
+    }
+
+    /// the stats page of a host, from another page of that host
+    pub(crate) fn stats(&self, host: &str, period: ReportPeriod) -> String {
+        match self {
+            Links::Files => period.file_name().to_string(),
+            Links::Server { query } => Links::server("/stats", host, period, query),
+        }
+    }
+
+    /// the status page of a host, from another page of that host
+    pub(crate) fn status(&self, host: &str, period: ReportPeriod) -> String {
+        match self {
+            Links::Files => period.status_file_name().to_string(),
+            Links::Server { query } => Links::server("/status", host, period, query),
+        }
+    }
+
+    /// the stats page of a host, from the index
+    pub(crate) fn host_stats(&self, host: &str, period: ReportPeriod) -> String {
+        match self {
+            Links::Files => format!("{}/{}", host_dir(host), period.file_name()),
+            Links::Server { .. } => self.stats(host, period),
+        }
+    }
+
+    /// the JSON report of a host, from the JSON index
+    pub(crate) fn host_json(&self, host: &str, period: ReportPeriod) -> String {
+        match self {
+            Links::Files => format!("{}/{}", host_dir(host), period.json_file_name()),
+            Links::Server { query } => Links::server("/api/stats", host, period, query),
+        }
+    }
+}
+
+/// A link of the period navigation.
+#[derive(Serialize)]
+pub(crate) struct NavLink {
+    pub label: &'static str,
+    pub href: String,
+    /// `active` on the link of the page it is on
+    pub active: &'static str,
+}
+
+/// A link per period, `href` making them.
+pub(crate) fn nav(current: ReportPeriod, href: impl Fn(ReportPeriod) -> String) -> Vec<NavLink> {
+    ReportPeriod::ALL
+        .into_iter()
+        .map(|period| NavLink {
+            label: match period {
+                ReportPeriod::Week => "7 Days",
+                ReportPeriod::Month => "30 Days",
+                ReportPeriod::Year => "12 Months",
+                ReportPeriod::All => "All Time",
+            },
+            href: href(period),
+            active: if period == current { "active" } else { "" },
+        })
+        .collect()
+}
+
+/// The filter form of the pages `kirinox serve` renders, filled with what the page shows.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct FilterForm {
+    pub host: String,
+    /// `ReportPeriod::key()`
+    pub period: String,
+    /// `2025-01-31`, empty for the start of the period
+    pub since: String,
+    /// `2025-01-31`, exclusive, empty for now
+    pub until: String,
+    pub country: String,
+    /// start of the request uri
+    pub path: String,
+    /// `humans`, `bots` or empty for both
+    pub traffic: String,
+}
+
+/// `value` with everything but unreserved characters percent encoded, for query strings.
+pub fn url_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(char::from(byte))
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_files_or_server_pages() {
+        let server = Links::Server {
+            query: String::from("&country=Germany"),
+        };
+        assert_eq!(
+            Links::Files.stats("a.example", ReportPeriod::Week),
+            "stats-7d.html"
+        );
+        assert_eq!(
+            Links::Files.host_json("a.example", ReportPeriod::Week),
+            "a.example/stats-7d.json"
+        );
+        assert_eq!(
+            server.status("a.example", ReportPeriod::All),
+            "/status?host=a.example&period=all&country=Germany"
+        );
+        assert_eq!(
+            server.host_json("a b", ReportPeriod::Year),
+            "/api/stats?host=a%20b&period=12m&country=Germany"
+        );
+    }
+
+    #[test]
+    fn encodes_query_values() {
+        assert_eq!(url_encode("/blog/a b?c=ü"), "%2Fblog%2Fa%20b%3Fc%3D%C3%BC");
+        assert_eq!(url_encode("example.com"), "example.com");
+    }
+}
@@ -0,0 +1,122 @@
+//! The numbers the pages show: shares of the total they belong to, safe on empty periods, and
+//! how counts, percentages, durations and sizes are written everywhere.
+
+/// `part` in percent of `total`, 0 when there's no total.
+pub fn percent(part: f64, total: f64) -> f64 {
+    if total > 0.0 {
+        part * 100.0 / total
+    } else {
+        0.0
+    }
+}
+
+/// `percent` to one decimal, for the width of bars.
+pub(crate) fn bar_width(part: f64, total: f64) -> f64 {
+    (percent(part, total) * 10.0).round() / 10.0
+}
+
+/// `1,234,567`
+pub fn format_count(count: impl Into<i64>) -> String {
+    let count = count.into();
+    let digits = count.unsigned_abs().to_string();
+    let mut formatted = String::from(if count < 0 { "-" } else { "" });
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// `12.5%`
+pub fn format_percent(percent: f64) -> String {
+    format!("{percent:.1}%")
+}
+
+/// `42s`, `2m 15s`
+pub fn format_duration(millis: f64) -> String {
+    let seconds = (millis / 1000.0).round() as i64;
+    if seconds < 60 {
+        return format!("{seconds}s");
+    }
+    format!("{}m {:02}s", seconds / 60, seconds % 60)
+}
+
+/// `512 B`, `1.5 KiB`
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value:.0} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// `0.5ms`, `87ms`, `1.25s`
+pub fn format_latency(millis: f64) -> String {
+    if millis >= 1000.0 {
+        format!("{:.2}s", millis / 1000.0)
+    } else if millis >= 10.0 {
+        format!("{millis:.0}ms")
+    } else {
+        format!("{millis:.1}ms")
+    }
+}
+
+/// A line of the top pages, countries, cities and referrers.
+pub(crate) struct Ranked {
+    pub name: String,
+    pub count: String,
+    /// of the total the list is drawn from
+    pub percent: f64,
+}
+
+impl Ranked {
+    pub(crate) fn list(rows: &[(String, i32)], total: i32) -> Vec<Ranked> {
+        rows.iter()
+            .map(|(name, count)| Ranked {
+                name: name.clone(),
+                count: format_count(*count),
+                percent: bar_width(f64::from(*count), f64::from(total)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_are_safe_on_empty_totals() {
+        assert_eq!(percent(1.0, 4.0), 25.0);
+        assert_eq!(percent(3.0, 0.0), 0.0);
+        assert_eq!(bar_width(1.0, 3.0), 33.3);
+    }
+
+    #[test]
+    fn formats_counts_and_percentages() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1000), "1,000");
+        assert_eq!(format_count(1_234_567_i64), "1,234,567");
+        assert_eq!(format_count(-12_345), "-12,345");
+        assert_eq!(format_percent(100.0 / 3.0), "33.3%");
+        assert_eq!(format_percent(0.0), "0.0%");
+    }
+
+    #[test]
+    fn ranks_against_the_total() {
+        let ranked = Ranked::list(&[(String::from("/"), 3), (String::from("/about"), 1)], 4);
+        assert_eq!(ranked[0].percent, 75.0);
+        assert_eq!(ranked[1].percent, 25.0);
+        assert_eq!(Ranked::list(&[(String::from("/"), 3)], 0)[0].percent, 0.0);
+    }
+}
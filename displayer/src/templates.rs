@@ -0,0 +1,407 @@
+//! How the pages look: the theme, the panels of the stats page and user templates.
+//!
+//! A templates directory may hold `stats.html`, `status.html` and `index.html`. Each one found
+//! replaces the built-in page of that name, the others stay built in. They are
+//! [minijinja](https://docs.rs/minijinja) (Jinja2) templates, may include each other, and get:
+//!
+//! - `report`: the report as the JSON files hold it, see `json`; nothing on the index
+//! - `hosts`: on the index, `{host, pageviews, unique_visitors, requests, href}` per host
+//! - `generated_at`: `2025-01-01 10:00 UTC`
+//! - `links`: `index`, `stats` and `status`, the pages of the same host and period, and `nav`,
+//!   a `{label, href, active}` per period
+//! - `panels`: the panels of the host, `{% if "cities" in panels %}`
+//! - `form`: the filter of the page on `kirinox serve`, `none` on written pages
+//! - `theme`: `dark`, `light` or `auto`, for `<html data-theme="{{ theme }}">`
+//! - `style`: the built-in stylesheet, `<style>{{ style|safe }}</style>` keeps its look
+//!
+//! ```html
+//! <html data-theme="{{ theme }}">
+//! <style>{{ style|safe }}</style>
+//! <h1>{{ report.host }}</h1>
+//! <p>{{ report.totals.pageviews }} pageviews</p>
+//! {% if "pages" in panels %}
+//!   {% for page in report.top.pages %}<p>{{ page.value }} {{ page.hits }}</p>{% endfor %}
+//! {% endif %}
+//! ```
+
+use std::{collections::HashMap, io, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use minijinja::{Environment, ErrorKind, path_loader};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    FilterForm, HostSummary, Links, Report, ReportPeriod, format_time, index_page,
+    json::{self, JsonReport},
+    links::{NavLink, nav},
+    stats_page, status_page,
+};
+
+/// The pages a templates directory can replace.
+const TEMPLATES: [&str; 3] = ["stats.html", "status.html", "index.html"];
+
+/// The panels of the stats page, top to bottom.
+pub const PANELS: [&str; 9] = [
+    "overview",
+    "charts",
+    "pages",
+    "countries",
+    "cities",
+    "referrers",
+    "bandwidth",
+    "latency",
+    "status",
+];
+
+const STYLE: &str = include_str!("../templates/style.css");
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    /// light or dark as the browser prefers
+    Auto,
+}
+
+impl Theme {
+    pub(crate) fn key(&self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::Auto => "auto",
+        }
+    }
+}
+
+/// The panels a stats page shows, in the order of `PANELS`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Panels(Vec<&'static str>);
+
+impl Default for Panels {
+    fn default() -> Panels {
+        Panels(PANELS.to_vec())
+    }
+}
+
+impl Panels {
+    pub fn show(&self, panel: &str) -> bool {
+        self.0.contains(&panel)
+    }
+}
+
+/// Panels of one host, on top of those of every host.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HostPanels {
+    /// shown although every host hides them
+    pub show: Vec<String>,
+    pub hide: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Appearance {
+    pub theme: Theme,
+    /// directory of user templates
+    pub templates: Option<PathBuf>,
+    /// panels of `PANELS` no host shows
+    pub hide: Vec<String>,
+    pub hosts: HashMap<String, HostPanels>,
+}
+
+impl Appearance {
+    pub fn panels(&self, host: &str) -> Panels {
+        let named = |names: &[String], panel: &str| names.iter().any(|name| name == panel);
+        let host = self.hosts.get(host);
+        Panels(
+            PANELS
+                .into_iter()
+                .filter(|panel| match host {
+                    Some(host) if named(&host.hide, panel) => false,
+                    Some(host) if named(&host.show, panel) => true,
+                    _ => !named(&self.hide, panel),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct PageLinks {
+    index: String,
+    stats: String,
+    status: String,
+    nav: Vec<NavLink>,
+}
+
+#[derive(Serialize)]
+struct HostLink<'a> {
+    host: &'a str,
+    pageviews: i32,
+    unique_visitors: i32,
+    requests: i32,
+    /// its stats page of the last 30 days
+    href: String,
+}
+
+/// What user templates get, see the module docs.
+#[derive(Serialize)]
+struct Context<'a> {
+    report: Option<JsonReport<'a>>,
+    hosts: Vec<HostLink<'a>>,
+    generated_at: String,
+    links: Option<PageLinks>,
+    panels: Panels,
+    form: Option<FilterForm>,
+    theme: Theme,
+    style: &'static str,
+}
+
+fn failed<E: std::fmt::Display>(err: E) -> &'static str {
+    eprintln!("{err}");
+    "a template could not be loaded"
+}
+
+/// Renders the pages, with the user templates where there are any.
+#[derive(Debug, Default)]
+pub struct Pages {
+    appearance: Appearance,
+    /// `None` without a templates directory
+    templates: Option<Environment<'static>>,
+}
+
+impl Pages {
+    /// Checks the panels and parses the user templates.
+    pub fn new(appearance: Appearance) -> Result<Pages, &'static str> {
+        let hosts = appearance.hosts.values();
+        let named = appearance
+            .hide
+            .iter()
+            .chain(hosts.flat_map(|host| host.show.iter().chain(&host.hide)));
+        for panel in named {
+            if !PANELS.contains(&panel.as_str()) {
+                eprintln!("{panel}");
+                return Err(
+                    "unknown panel, expected overview, charts, pages, countries, \
+                    cities, referrers, bandwidth, latency or status",
+                );
+            }
+        }
+        let templates = match &appearance.templates {
+            Some(dir) if !dir.is_dir() => return Err("the templates directory does not exist"),
+            Some(dir) => {
+                let mut env = Environment::new();
+                env.set_loader(path_loader(dir));
+                for name in TEMPLATES {
+                    match env.get_template(name) {
+                        Err(err) if err.kind() != ErrorKind::TemplateNotFound => {
+                            return Err(failed(err));
+                        }
+                        _ => {}
+                    }
+                }
+                Some(env)
+            }
+            None => None,
+        };
+        Ok(Pages {
+            appearance,
+            templates,
+        })
+    }
+
+    /// The user template `name` rendered with `context`, `None` when there's no such template.
+    fn user<'a>(
+        &self,
+        name: &str,
+        context: impl FnOnce() -> Context<'a>,
+    ) -> Option<io::Result<String>> {
+        let template = match self.templates.as_ref()?.get_template(name) {
+            Ok(template) => template,
+            Err(err) if err.kind() == ErrorKind::TemplateNotFound => return None,
+            Err(err) => return Some(Err(io::Error::other(failed(err)))),
+        };
+        Some(
+            template
+                .render(context())
+                .map_err(|err| io::Error::other(failed(err))),
+        )
+    }
+
+    fn links(report: &Report, links: &Links) -> PageLinks {
+        PageLinks {
+            index: links.index(),
+            stats: links.stats(&report.host, report.period),
+            status: links.status(&report.host, report.period),
+            nav: nav(report.period, |period| links.stats(&report.host, period)),
+        }
+    }
+
+    fn context<'a>(&self, report: &'a Report, links: &Links) -> Context<'a> {
+        Context {
+            report: Some(json::document(report)),
+            hosts: Vec::new(),
+            generated_at: format_time(report.generated_at),
+            links: Some(Pages::links(report, links)),
+            panels: self.appearance.panels(&report.host),
+            form: None,
+            theme: self.appearance.theme,
+            style: STYLE,
+        }
+    }
+
+    /// The stats page of `report`, with the filter form when `form` is given.
+    pub fn stats(
+        &self,
+        report: &Report,
+        links: &Links,
+        form: Option<FilterForm>,
+    ) -> io::Result<String> {
+        let panels = self.appearance.panels(&report.host);
+        let user = self.user("stats.html", || Context {
+            form: form.clone(),
+            ..self.context(report, links)
+        });
+        user.unwrap_or_else(|| stats_page(report, links, form, self.appearance.theme, panels))
+    }
+
+    /// The status page of `report`.
+    pub fn status(&self, report: &Report, links: &Links) -> io::Result<String> {
+        let user = self.user("status.html", || self.context(report, links));
+        user.unwrap_or_else(|| status_page(report, links, self.appearance.theme))
+    }
+
+    /// The landing page listing `hosts`.
+    pub fn index(
+        &self,
+        hosts: &[HostSummary],
+        generated_at: DateTime<Utc>,
+        links: &Links,
+    ) -> io::Result<String> {
+        let user = self.user("index.html", || Context {
+            report: None,
+            hosts: hosts
+                .iter()
+                .map(|summary| HostLink {
+                    host: &summary.host,
+                    pageviews: summary.pageviews,
+                    unique_visitors: summary.unique_visitors,
+                    requests: summary.total_requests,
+                    href: links.host_stats(&summary.host, ReportPeriod::Month),
+                })
+                .collect(),
+            generated_at: format_time(generated_at),
+            links: None,
+            panels: Panels::default(),
+            form: None,
+            theme: self.appearance.theme,
+            style: STYLE,
+        });
+        user.unwrap_or_else(|| index_page(hosts, generated_at, links, self.appearance.theme))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persister::{Bandwidth, Changes, Period, Stats, StatusReport};
+    use std::fs;
+
+    fn report() -> Report {
+        Report {
+            host: String::from("example.com"),
+            period: ReportPeriod::Week,
+            stats: Stats {
+                pageviews: 3,
+                pages: vec![(String::from("/about"), 3)],
+                ..Stats::default()
+            },
+            step: Period::Hour,
+            timeseries: Vec::new(),
+            changes: Changes::default(),
+            status: StatusReport::default(),
+            bandwidth: Bandwidth::default(),
+            generated_at: DateTime::from_timestamp(1_735_725_600, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn hides_panels_per_host() {
+        let appearance = Appearance {
+            hide: vec![String::from("cities"), String::from("latency")],
+            hosts: HashMap::from([(
+                String::from("example.com"),
+                HostPanels {
+                    show: vec![String::from("cities")],
+                    hide: vec![String::from("bandwidth")],
+                },
+            )]),
+            ..Appearance::default()
+        };
+        let panels = appearance.panels("example.com");
+        assert!(panels.show("cities"));
+        assert!(!panels.show("bandwidth"));
+        assert!(!panels.show("latency"));
+        let other = appearance.panels("other.example");
+        assert!(!other.show("cities"));
+        assert!(other.show("bandwidth"));
+        assert!(
+            Pages::new(Appearance {
+                hide: vec![String::from("weather")],
+                ..Appearance::default()
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn renders_built_in_pages_with_the_theme_and_panels() {
+        let pages = Pages::new(Appearance {
+            theme: Theme::Light,
+            hide: vec![String::from("pages")],
+            ..Appearance::default()
+        })
+        .unwrap();
+        let page = pages.stats(&report(), &Links::Files, None).unwrap();
+        assert!(page.contains(r#"<html lang="en" data-theme="light">"#));
+        assert!(!page.contains("Top Pages"));
+        assert!(page.contains("Top Countries"));
+    }
+
+    #[test]
+    fn renders_user_templates_where_there_are_any() {
+        let dir = std::env::temp_dir().join(format!("kirinox-templates-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("stats.html"),
+            "{% include \"header.html\" %}{{ report.totals.pageviews }} \
+            {% for page in report.top.pages %}{{ page.value }}{% endfor %} \
+            {{ links.nav[0].href }} {{ theme }} {% if \"status\" in panels %}status{% endif %}",
+        )
+        .unwrap();
+        fs::write(dir.join("header.html"), "<h1>{{ report.host }}</h1>").unwrap();
+        let pages = Pages::new(Appearance {
+            templates: Some(dir.clone()),
+            ..Appearance::default()
+        })
+        .unwrap();
+        let page = pages.stats(&report(), &Links::Files, None).unwrap();
+        assert_eq!(
+            page,
+            "<h1>example.com</h1>3 &#x2f;about stats-7d.html dark status"
+        );
+        // the status page has no template of its own and stays built in
+        let status = pages.status(&report(), &Links::Files).unwrap();
+        assert!(status.contains("<!DOCTYPE html>"));
+
+        fs::write(dir.join("index.html"), "{% for host in hosts %}").unwrap();
+        assert!(
+            Pages::new(Appearance {
+                templates: Some(dir.clone()),
+                ..Appearance::default()
+            })
+            .is_err()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+use parser::LogStruct;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    Pageview,
+    Asset,
+    Api,
+    Feed,
+    Other,
+}
+
+impl RequestKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestKind::Pageview => "pageview",
+            RequestKind::Asset => "asset",
+            RequestKind::Api => "api",
+            RequestKind::Feed => "feed",
+            RequestKind::Other => "other",
+        }
+    }
+
+    /// The kind stored under `as_str()`, anything unknown is `Other`.
+    pub fn parse(kind: &str) -> RequestKind {
+        match kind {
+            "pageview" => RequestKind::Pageview,
+            "asset" => RequestKind::Asset,
+            "api" => RequestKind::Api,
+            "feed" => RequestKind::Feed,
+            _ => RequestKind::Other,
+        }
+    }
+}
+
+/// Rules deciding what a request is. Anything that isn't api, feed or asset counts as a
+/// pageview when it's a successful `GET` of a path without extension or with one of the
+/// `page_extensions`; the rest (redirects, errors, `POST` to forms, ...) is `other`.
+///
+/// ```toml
+/// [classification]
+/// api_prefixes = ["/api/"]
+/// feed_paths = ["/feed", "/rss.xml"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClassifierRules {
+    pub asset_extensions: Vec<String>,
+    pub page_extensions: Vec<String>,
+    pub feed_extensions: Vec<String>,
+    pub api_prefixes: Vec<String>,
+    pub asset_prefixes: Vec<String>,
+    pub feed_paths: Vec<String>,
+    pub pageview_methods: Vec<String>,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+impl Default for ClassifierRules {
+    fn default() -> ClassifierRules {
+        ClassifierRules {
+            asset_extensions: strings(&[
+                "css",
+                "js",
+                "mjs",
+                "map",
+                "png",
+                "jpg",
+                "jpeg",
+                "gif",
+                "webp",
+                "avif",
+                "svg",
+                "ico",
+                "bmp",
+                "woff",
+                "woff2",
+                "ttf",
+                "otf",
+                "eot",
+                "mp4",
+                "webm",
+                "mp3",
+                "ogg",
+                "wav",
+                "pdf",
+                "zip",
+                "gz",
+                "txt",
+                "json",
+                "wasm",
+                "webmanifest",
+            ]),
+            page_extensions: strings(&["html", "htm", "php", "asp", "aspx", "jsp"]),
+            feed_extensions: strings(&["rss", "atom"]),
+            api_prefixes: strings(&["/api/"]),
+            asset_prefixes: strings(&["/static/", "/assets/", "/_next/", "/wp-content/"]),
+            feed_paths: strings(&[
+                "/feed",
+                "/rss",
+                "/atom.xml",
+                "/rss.xml",
+                "/feed.xml",
+                "/index.xml",
+            ]),
+            pageview_methods: strings(&["GET"]),
+        }
+    }
+}
+
+pub struct Classifier {
+    rules: ClassifierRules,
+}
+
+impl Classifier {
+    pub fn new(rules: ClassifierRules) -> Classifier {
+        Classifier { rules }
+    }
+
+    pub fn classify(&self, log_line: &LogStruct) -> RequestKind {
+        let path = log_line
+            .request_uri
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default();
+        let file_name = path.rsplit('/').next().unwrap_or_default();
+        let extension = file_name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_lowercase());
+        let has =
+            |values: &[String], extension: &str| values.iter().any(|value| value == extension);
+
+        if self
+            .rules
+            .api_prefixes
+            .iter()
+            .any(|prefix| path.starts_with(prefix.as_str()))
+        {
+            return RequestKind::Api;
+        }
+        let is_feed = self
+            .rules
+            .feed_paths
+            .iter()
+            .any(|feed| path.trim_end_matches('/') == feed.trim_end_matches('/'))
+            || extension
+                .as_deref()
+                .is_some_and(|extension| has(&self.rules.feed_extensions, extension));
+        if is_feed {
+            return RequestKind::Feed;
+        }
+        let is_asset = self
+            .rules
+            .asset_prefixes
+            .iter()
+            .any(|prefix| path.starts_with(prefix.as_str()))
+            || extension
+                .as_deref()
+                .is_some_and(|extension| has(&self.rules.asset_extensions, extension));
+        if is_asset {
+            return RequestKind::Asset;
+        }
+        let is_page = extension
+            .as_deref()
+            .is_none_or(|extension| has(&self.rules.page_extensions, extension));
+        let is_pageview_method = self
+            .rules
+            .pageview_methods
+            .iter()
+            .any(|method| method.eq_ignore_ascii_case(log_line.method));
+        let is_content = (200..300).contains(&log_line.status) || log_line.status == 304;
+        if is_page && is_pageview_method && is_content {
+            return RequestKind::Pageview;
+        }
+        RequestKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(method: &str, request_uri: &str, status: u16) -> RequestKind {
+        let line = format!(
+            "203.0.113.7\t-\t2025-01-01T10:00:00+00:00\t{method}\thttps\texample.com\t{request_uri}\tHTTP/2.0\t{status}\t512\t0.010\t0.009\t-\tMozilla/5.0"
+        );
+        let log_line = LogStruct::from_line(&line).unwrap();
+        Classifier::new(ClassifierRules::default()).classify(&log_line)
+    }
+
+    #[test]
+    fn classifies_requests() {
+        assert_eq!(kind("GET", "/", 200), RequestKind::Pageview);
+        assert_eq!(
+            kind("GET", "/blog/post.html?ref=x", 200),
+            RequestKind::Pageview
+        );
+        assert_eq!(kind("GET", "/blog/", 304), RequestKind::Pageview);
+        assert_eq!(kind("GET", "/style.CSS", 200), RequestKind::Asset);
+        assert_eq!(kind("GET", "/static/app", 200), RequestKind::Asset);
+        assert_eq!(kind("POST", "/api/login", 200), RequestKind::Api);
+        assert_eq!(kind("GET", "/feed/", 200), RequestKind::Feed);
+        assert_eq!(kind("GET", "/missing", 404), RequestKind::Other);
+        assert_eq!(kind("GET", "/old", 301), RequestKind::Other);
+        assert_eq!(kind("POST", "/contact", 200), RequestKind::Other);
+    }
+
+    #[test]
+    fn parses_what_it_stores() {
+        for kind in [
+            RequestKind::Pageview,
+            RequestKind::Asset,
+            RequestKind::Api,
+            RequestKind::Feed,
+            RequestKind::Other,
+        ] {
+            assert_eq!(RequestKind::parse(kind.as_str()), kind);
+        }
+    }
+}
@@ -4,21 +4,26 @@ use parser::LogStruct;
 use serde::Deserialize;
 use ureq::Agent;
 
+mod classifier;
+
+pub use classifier::{Classifier, ClassifierRules, RequestKind};
+
 #[derive(Debug)]
 pub struct EnrichedLog {
     pub is_bot: bool,
     pub country: String,
     pub city: String,
     pub is_vpn: bool,
+    pub kind: RequestKind,
 }
 
-const BOT_ASNS: [&'static str; 24] = [
+const BOT_ASNS: [&str; 24] = [
     "as15169", "as8075", "as16509", "as14618", "as714", "as13238", "as38365", "as40509", "as32934",
     "as13414", "as14413", "as396982", "as209366", "as26347", "as24940", "as203020", "as45090",
     "as14061", "as16276", "as63949", "as20473", "as45102", "as398705", "as64512",
 ];
 
-const BOT_USER_AGENTS: [&'static str; 54] = [
+const BOT_USER_AGENTS: [&str; 54] = [
     "googlebot",
     "bingbot",
     "slurp",
@@ -75,52 +80,53 @@ const BOT_USER_AGENTS: [&'static str; 54] = [
     "scanner",
 ];
 
+/// The crawler or tool a bot's user agent names, the first of the known bot user agents it
+/// contains. Bots told apart by their network alone have none.
+pub fn bot_family(user_agent: &str) -> Option<&'static str> {
+    let user_agent = user_agent.to_lowercase();
+    BOT_USER_AGENTS
+        .iter()
+        .copied()
+        .find(|token| user_agent.contains(token))
+}
+
 pub struct Enricher {
     client: Agent,
+    classifier: Classifier,
 }
 
 #[derive(Deserialize, Debug)]
 struct IpData {
     #[serde(rename = "as")]
     asn: String,
-    asname: String,
     city: String,
     country: String,
-    #[serde(rename = "countryCode")]
-    country_code: String,
     hosting: bool,
-    isp: String,
-    lat: f32,
-    lon: f32,
-    org: String,
     proxy: bool,
-    query: String,
-    region: String,
-    #[serde(rename = "regionName")]
-    region_name: String,
-    status: String,
-    timezone: String,
-    zip: String,
 }
 
 impl Enricher {
-    pub fn new() -> Enricher {
+    pub fn new(classifier: Classifier) -> Enricher {
         Enricher {
             client: Agent::config_builder()
                 .timeout_global(Some(Duration::from_secs(5)))
                 .build()
                 .into(),
+            classifier,
         }
     }
 
     fn fetch_ip_data(&self, ip_addr: &str) -> IpData {
-        self
-            .client
-            .get(format!("http://ip-api.com/json/{}?fields=status,message,country,countryCode,region,regionName,city,zip,lat,lon,timezone,isp,org,as,asname,proxy,hosting,query", ip_addr))
+        self.client
+            .get(format!(
+                "http://ip-api.com/json/{}?fields=country,city,as,proxy,hosting",
+                ip_addr
+            ))
             .call()
             .unwrap()
             .body_mut()
-            .read_json::<IpData>().unwrap()
+            .read_json::<IpData>()
+            .unwrap()
     }
 
     fn is_bot(&self, log_line: &LogStruct, ip_data: &IpData) -> bool {
@@ -128,9 +134,7 @@ impl Enricher {
             || BOT_ASNS
                 .iter()
                 .any(|asn| ip_data.asn.to_lowercase().contains(asn))
-            || BOT_USER_AGENTS
-                .iter()
-                .any(|ua| log_line.http_user_agent.to_lowercase().contains(ua))
+            || bot_family(log_line.http_user_agent).is_some()
     }
 
     fn is_vpn(&self, ip_data: &IpData) -> bool {
@@ -145,13 +149,54 @@ impl Enricher {
             is_bot,
             is_vpn,
             country: ip_data.country,
-            city: ip_data.city
+            city: ip_data.city,
+            kind: self.classifier.classify(log_line),
         }
-
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn ip_data(asn: &str, hosting: bool) -> IpData {
+        IpData {
+            asn: asn.to_string(),
+            city: String::new(),
+            country: String::new(),
+            hosting,
+            proxy: false,
+        }
+    }
+
+    #[test]
+    fn detects_bots() {
+        let enricher = Enricher::new(Classifier::new(ClassifierRules::default()));
+        let line = "203.0.113.7\t-\t2025-01-01T10:00:00+00:00\tGET\thttps\texample.com\t/\tHTTP/2.0\t200\t512\t0.010\t0.009\t-\tMozilla/5.0 (compatible; Googlebot/2.1)";
+        let log_line = LogStruct::from_line(line).unwrap();
+        assert!(enricher.is_bot(&log_line, &ip_data("AS3320 Deutsche Telekom AG", false)));
+
+        let line = "203.0.113.7\t-\t2025-01-01T10:00:00+00:00\tGET\thttps\texample.com\t/\tHTTP/2.0\t200\t512\t0.010\t0.009\t-\tMozilla/5.0 (X11; Linux x86_64) Firefox/120.0";
+        let log_line = LogStruct::from_line(line).unwrap();
+        assert!(!enricher.is_bot(&log_line, &ip_data("AS3320 Deutsche Telekom AG", false)));
+        assert!(enricher.is_bot(&log_line, &ip_data("AS16509 Amazon.com, Inc.", false)));
+        assert!(enricher.is_bot(&log_line, &ip_data("AS3320 Deutsche Telekom AG", true)));
+    }
+
+    #[test]
+    fn names_bot_families() {
+        assert_eq!(
+            bot_family("Mozilla/5.0 (compatible; Googlebot/2.1)"),
+            Some("googlebot")
+        );
+        assert_eq!(
+            bot_family("Mozilla/5.0 (compatible; SomeBot/1.0)"),
+            Some("bot")
+        );
+        assert_eq!(bot_family("curl/8.5.0"), Some("curl"));
+        assert_eq!(
+            bot_family("Mozilla/5.0 (X11; Linux x86_64) Firefox/120.0"),
+            None
+        );
+    }
 }
@@ -0,0 +1,330 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use displayer::{Appearance, HostPanels, Theme};
+use enricher::ClassifierRules;
+use parser::TrustedProxies;
+use persister::{
+    DEFAULT_BATCH_SIZE, DEFAULT_SESSION_GAP_MS, DbOptions, Location, Privacy, Retention,
+};
+use serde::Deserialize;
+
+/// Settings read from the TOML file given with `--config`. Every section is optional, a
+/// missing file section falls back to the defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub proxies: ProxiesConfig,
+    pub privacy: PrivacyConfig,
+    pub sessions: SessionsConfig,
+    pub classification: ClassifierRules,
+    pub database: DatabaseConfig,
+    pub retention: RetentionConfig,
+    pub server: ServerConfig,
+    pub pages: PagesConfig,
+}
+
+/// ```toml
+/// [proxies]
+/// trusted = ["10.0.0.0/8", "173.245.48.0/20"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProxiesConfig {
+    pub trusted: Vec<String>,
+}
+
+impl ProxiesConfig {
+    pub fn trusted_proxies(&self) -> Result<TrustedProxies, &'static str> {
+        TrustedProxies::new(&self.trusted)
+    }
+}
+
+/// ```toml
+/// [privacy]
+/// enabled = true
+/// ipv4_prefix = 24
+/// ipv6_prefix = 48
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    pub enabled: bool,
+    pub ipv4_prefix: u8,
+    pub ipv6_prefix: u8,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> PrivacyConfig {
+        let privacy = Privacy::default();
+        PrivacyConfig {
+            enabled: false,
+            ipv4_prefix: privacy.ipv4_prefix,
+            ipv6_prefix: privacy.ipv6_prefix,
+        }
+    }
+}
+
+impl PrivacyConfig {
+    pub fn privacy(&self) -> Option<Privacy> {
+        if !self.enabled {
+            return None;
+        }
+        Some(Privacy {
+            ipv4_prefix: self.ipv4_prefix,
+            ipv6_prefix: self.ipv6_prefix,
+        })
+    }
+}
+
+/// ```toml
+/// [sessions]
+/// timeout_minutes = 30
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SessionsConfig {
+    pub timeout_minutes: u32,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> SessionsConfig {
+        SessionsConfig {
+            timeout_minutes: (DEFAULT_SESSION_GAP_MS / 60_000) as u32,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Sqlite,
+    Postgres,
+}
+
+/// A relative `path` is taken from the directory of the config file, so cron jobs find the
+/// same database whatever their working directory is. `url` is only used by the postgres
+/// backend, which needs kirinox built with the `postgres` feature.
+///
+/// ```toml
+/// [database]
+/// backend = "sqlite"
+/// path = "/var/lib/kirinox/krx.db"
+/// # backend = "postgres"
+/// # url = "host=localhost user=kirinox dbname=kirinox"
+/// batch_size = 1000
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub backend: Backend,
+    pub path: PathBuf,
+    pub url: Option<String>,
+    pub batch_size: usize,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> DatabaseConfig {
+        DatabaseConfig {
+            backend: Backend::Sqlite,
+            path: PathBuf::from("krx.db"),
+            url: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    pub fn location(&self) -> Location {
+        match self.backend {
+            Backend::Sqlite => Location::Sqlite(self.path.clone()),
+            Backend::Postgres => Location::Postgres(self.url.clone().unwrap_or_default()),
+        }
+    }
+}
+
+/// Days kept by `kirinox prune`, anything left out is kept forever.
+///
+/// ```toml
+/// [retention]
+/// raw_days = 90
+/// bot_days = 7
+/// hourly_days = 90
+/// # daily rollups are kept forever unless daily_days is set
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    pub raw_days: Option<u32>,
+    pub bot_days: Option<u32>,
+    pub hourly_days: Option<u32>,
+    pub daily_days: Option<u32>,
+}
+
+impl RetentionConfig {
+    pub fn retention(&self) -> Retention {
+        Retention {
+            raw_days: self.raw_days,
+            bot_days: self.bot_days,
+            hourly_days: self.hourly_days,
+            daily_days: self.daily_days,
+        }
+    }
+}
+
+/// Where `kirinox serve` listens. It asks for the username and password when they are set.
+///
+/// ```toml
+/// [server]
+/// listen = "127.0.0.1:8080"
+/// username = "admin"
+/// password = "change me"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub listen: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            listen: String::from("127.0.0.1:8080"),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn credentials(&self) -> Option<(&str, &str)> {
+        Some((self.username.as_deref()?, self.password.as_deref()?))
+    }
+}
+
+/// How the HTML pages look. `templates` is a directory of minijinja templates replacing the
+/// built-in pages of the same name, relative to the config file like the database path. The
+/// panels are overview, charts, pages, countries, cities, referrers, bandwidth, latency and
+/// status, a host can show panels every host hides and hide more.
+///
+/// ```toml
+/// [pages]
+/// theme = "dark" # or light, or auto to follow the browser
+/// templates = "templates"
+/// hide = ["cities"]
+///
+/// [pages.hosts."example.com"]
+/// show = ["cities"]
+/// hide = ["bandwidth", "latency"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PagesConfig {
+    pub theme: Theme,
+    pub templates: Option<PathBuf>,
+    pub hide: Vec<String>,
+    pub hosts: HashMap<String, HostPanelsConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HostPanelsConfig {
+    pub show: Vec<String>,
+    pub hide: Vec<String>,
+}
+
+impl PagesConfig {
+    pub fn appearance(&self) -> Appearance {
+        Appearance {
+            theme: self.theme,
+            templates: self.templates.clone(),
+            hide: self.hide.clone(),
+            hosts: self
+                .hosts
+                .iter()
+                .map(|(host, panels)| {
+                    let panels = HostPanels {
+                        show: panels.show.clone(),
+                        hide: panels.hide.clone(),
+                    };
+                    (host.clone(), panels)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Config {
+    pub fn db_options(&self) -> DbOptions {
+        DbOptions {
+            location: self.database.location(),
+            privacy: self.privacy.privacy(),
+            session_gap: i64::from(self.sessions.timeout_minutes) * 60_000,
+            batch_size: self.database.batch_size,
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, &'static str> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            eprintln!("{err}");
+            "could not read the config file"
+        })?;
+        let mut config: Config = toml::from_str(&contents).map_err(|err| {
+            eprintln!("{err}");
+            "could not parse the config file"
+        })?;
+        if config.database.backend == Backend::Postgres && config.database.url.is_none() {
+            return Err("the postgres backend needs a database url");
+        }
+        config.proxies.trusted_proxies().map_err(|err| {
+            eprintln!("{err}: {:?}", config.proxies.trusted);
+            "the trusted proxies must be addresses or CIDR ranges"
+        })?;
+        if config.server.username.is_some() != config.server.password.is_some() {
+            return Err("the server needs both a username and a password");
+        }
+        if let Some(dir) = path.parent() {
+            if config.database.path.is_relative() {
+                config.database.path = dir.join(&config.database.path);
+            }
+            if let Some(templates) = &config.pages.templates
+                && templates.is_relative()
+            {
+                config.pages.templates = Some(dir.join(templates));
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_toml(name: &str, toml: &str) -> Result<Config, &'static str> {
+        let path = std::env::temp_dir().join(format!("kirinox-{name}-{}.toml", std::process::id()));
+        fs::write(&path, toml).unwrap();
+        let config = Config::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn rejects_bad_trusted_proxies() {
+        let config = from_toml("proxies", "[proxies]\ntrusted = [\"10.0.0.0/8\"]\n").unwrap();
+        assert!(
+            config
+                .proxies
+                .trusted_proxies()
+                .unwrap()
+                .contains("10.1.2.3")
+        );
+        assert!(from_toml("typo", "[proxies]\ntrusted = [\"10.0.0/8\"]\n").is_err());
+    }
+}
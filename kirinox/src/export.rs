@@ -0,0 +1,448 @@
+use std::{
+    env::Args,
+    fs::File,
+    io::{self, BufWriter, Write},
+    iter::Peekable,
+    path::PathBuf,
+};
+
+use persister::{AccessRecord, Db, Filter};
+
+use crate::{FILTER_OPTIONS, config::Config, filter_option};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Result<Format, &'static str> {
+        match format {
+            "csv" => Ok(Format::Csv),
+            "ndjson" | "jsonl" => Ok(Format::Ndjson),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err("unknown export format, expected csv, ndjson or parquet"),
+        }
+    }
+}
+
+/// `kirinox export --format <csv|ndjson|parquet> [filter options] [--output <path>]
+/// [--config <path>]`, the filter options being those of `filter_option`
+#[derive(Debug)]
+pub struct ExportArgs {
+    pub format: Format,
+    pub filter: Filter,
+    /// standard output when not given
+    pub output: Option<PathBuf>,
+    pub config: Config,
+}
+
+impl ExportArgs {
+    pub fn from_args(args: &mut Peekable<Args>) -> Result<ExportArgs, &'static str> {
+        let mut format = None;
+        let mut export = ExportArgs {
+            format: Format::Csv,
+            filter: Default::default(),
+            output: None,
+            config: Config::default(),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or("export options expect a value");
+            match arg.as_str() {
+                "--format" => format = Some(Format::parse(&value()?)?),
+                option if FILTER_OPTIONS.contains(&option) => {
+                    export.filter = filter_option(export.filter, option, &value()?)?
+                }
+                "--output" => export.output = Some(PathBuf::from(value()?)),
+                "--config" => export.config = Config::from_file(&PathBuf::from(value()?))?,
+                _ => return Err("unknown argument"),
+            }
+        }
+        export.format = format.ok_or("export needs --format csv, ndjson or parquet")?;
+        Ok(export)
+    }
+}
+
+/// Turns batches of records into one of the export formats.
+trait RecordWriter {
+    fn write(&mut self, records: &[AccessRecord]) -> Result<(), &'static str>;
+
+    fn finish(self: Box<Self>) -> Result<(), &'static str>;
+}
+
+fn write_failed<E: std::fmt::Display>(err: E) -> &'static str {
+    eprintln!("{err}");
+    "could not write the export"
+}
+
+struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write> RecordWriter for CsvWriter<W> {
+    fn write(&mut self, records: &[AccessRecord]) -> Result<(), &'static str> {
+        for record in records {
+            self.writer.serialize(record).map_err(write_failed)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), &'static str> {
+        self.writer.flush().map_err(write_failed)
+    }
+}
+
+struct NdjsonWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> RecordWriter for NdjsonWriter<W> {
+    fn write(&mut self, records: &[AccessRecord]) -> Result<(), &'static str> {
+        for record in records {
+            serde_json::to_writer(&mut self.writer, record).map_err(write_failed)?;
+            self.writer.write_all(b"\n").map_err(write_failed)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), &'static str> {
+        self.writer.flush().map_err(write_failed)
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_writer {
+    use std::{io::Write, sync::Arc};
+
+    use parquet::{
+        basic::Compression,
+        data_type::{
+            BoolType, ByteArray, ByteArrayType, DataType, DoubleType, Int32Type, Int64Type,
+        },
+        file::{
+            properties::WriterProperties,
+            writer::{SerializedFileWriter, SerializedRowGroupWriter},
+        },
+        schema::{parser::parse_message_type, types::Type},
+    };
+    use persister::AccessRecord;
+
+    use super::{RecordWriter, write_failed};
+
+    const SCHEMA: &str = "
+        message access_log {
+            REQUIRED INT64 id;
+            REQUIRED INT64 timestamp (TIMESTAMP(MILLIS, true));
+            REQUIRED BINARY remote_addr (STRING);
+            OPTIONAL BINARY remote_user (STRING);
+            REQUIRED BINARY method (STRING);
+            REQUIRED BINARY scheme (STRING);
+            REQUIRED BINARY http_host (STRING);
+            REQUIRED BINARY request_uri (STRING);
+            REQUIRED BINARY server_protocol (STRING);
+            REQUIRED INT32 status;
+            REQUIRED INT64 body_bytes_sent;
+            REQUIRED DOUBLE request_time;
+            OPTIONAL DOUBLE upstream_response_time;
+            OPTIONAL BINARY http_referer (STRING);
+            OPTIONAL BINARY http_user_agent (STRING);
+            OPTIONAL BINARY visitor_hash (STRING);
+            REQUIRED BOOLEAN is_bot;
+            OPTIONAL BINARY country (STRING);
+            OPTIONAL BINARY city (STRING);
+            REQUIRED BOOLEAN is_vpn;
+            REQUIRED BINARY request_kind (STRING);
+        }
+    ";
+
+    /// Rows per row group, the export batches are much smaller than what readers like.
+    const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+    pub(super) fn schema() -> Arc<Type> {
+        Arc::new(parse_message_type(SCHEMA).unwrap())
+    }
+
+    pub(super) struct ParquetWriter<W: Write + Send> {
+        writer: SerializedFileWriter<W>,
+        pending: Vec<AccessRecord>,
+    }
+
+    impl<W: Write + Send> ParquetWriter<W> {
+        pub(super) fn new(output: W) -> Result<ParquetWriter<W>, &'static str> {
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let writer = SerializedFileWriter::new(output, schema(), Arc::new(properties))
+                .map_err(write_failed)?;
+            Ok(ParquetWriter {
+                writer,
+                pending: Vec::new(),
+            })
+        }
+
+        fn write_row_group(&mut self) -> parquet::errors::Result<()> {
+            let records = std::mem::take(&mut self.pending);
+            let mut row_group = self.writer.next_row_group()?;
+            let strings = |field: fn(&AccessRecord) -> &str| -> Vec<ByteArray> {
+                records
+                    .iter()
+                    .map(|record| ByteArray::from(field(record)))
+                    .collect()
+            };
+            let optional_strings = |field: fn(&AccessRecord) -> Option<&str>| {
+                optional(
+                    records
+                        .iter()
+                        .map(|record| field(record).map(ByteArray::from)),
+                )
+            };
+            required::<Int64Type, _>(
+                &mut row_group,
+                records.iter().map(|record| record.id).collect(),
+            )?;
+            required::<Int64Type, _>(
+                &mut row_group,
+                records.iter().map(|record| record.timestamp).collect(),
+            )?;
+            required::<ByteArrayType, _>(&mut row_group, strings(|record| &record.remote_addr))?;
+            write::<ByteArrayType, _>(
+                &mut row_group,
+                optional_strings(|record| record.remote_user.as_deref()),
+            )?;
+            required::<ByteArrayType, _>(&mut row_group, strings(|record| &record.method))?;
+            required::<ByteArrayType, _>(&mut row_group, strings(|record| &record.scheme))?;
+            required::<ByteArrayType, _>(&mut row_group, strings(|record| &record.http_host))?;
+            required::<ByteArrayType, _>(&mut row_group, strings(|record| &record.request_uri))?;
+            required::<ByteArrayType, _>(
+                &mut row_group,
+                strings(|record| &record.server_protocol),
+            )?;
+            required::<Int32Type, _>(
+                &mut row_group,
+                records.iter().map(|record| record.status).collect(),
+            )?;
+            required::<Int64Type, _>(
+                &mut row_group,
+                records
+                    .iter()
+                    .map(|record| record.body_bytes_sent)
+                    .collect(),
+            )?;
+            required::<DoubleType, _>(
+                &mut row_group,
+                records.iter().map(|record| record.request_time).collect(),
+            )?;
+            write::<DoubleType, _>(
+                &mut row_group,
+                optional(records.iter().map(|record| record.upstream_response_time)),
+            )?;
+            write::<ByteArrayType, _>(
+                &mut row_group,
+                optional_strings(|record| record.http_referer.as_deref()),
+            )?;
+            write::<ByteArrayType, _>(
+                &mut row_group,
+                optional_strings(|record| record.http_user_agent.as_deref()),
+            )?;
+            write::<ByteArrayType, _>(
+                &mut row_group,
+                optional_strings(|record| record.visitor_hash.as_deref()),
+            )?;
+            required::<BoolType, _>(
+                &mut row_group,
+                records.iter().map(|record| record.is_bot).collect(),
+            )?;
+            write::<ByteArrayType, _>(
+                &mut row_group,
+                optional_strings(|record| record.country.as_deref()),
+            )?;
+            write::<ByteArrayType, _>(
+                &mut row_group,
+                optional_strings(|record| record.city.as_deref()),
+            )?;
+            required::<BoolType, _>(
+                &mut row_group,
+                records.iter().map(|record| record.is_vpn).collect(),
+            )?;
+            required::<ByteArrayType, _>(&mut row_group, strings(|record| &record.request_kind))?;
+            row_group.close()?;
+            Ok(())
+        }
+    }
+
+    /// Values of the rows that have one, with the definition level of every row.
+    fn optional<T>(values: impl Iterator<Item = Option<T>>) -> (Vec<T>, Option<Vec<i16>>) {
+        let mut present = Vec::new();
+        let mut levels = Vec::new();
+        for value in values {
+            levels.push(i16::from(value.is_some()));
+            present.extend(value);
+        }
+        (present, Some(levels))
+    }
+
+    fn required<T: DataType, W: Write + Send>(
+        row_group: &mut SerializedRowGroupWriter<'_, W>,
+        values: Vec<T::T>,
+    ) -> parquet::errors::Result<()> {
+        write::<T, W>(row_group, (values, None))
+    }
+
+    fn write<T: DataType, W: Write + Send>(
+        row_group: &mut SerializedRowGroupWriter<'_, W>,
+        (values, levels): (Vec<T::T>, Option<Vec<i16>>),
+    ) -> parquet::errors::Result<()> {
+        let mut column = row_group.next_column()?.unwrap();
+        column
+            .typed::<T>()
+            .write_batch(&values, levels.as_deref(), None)?;
+        column.close()
+    }
+
+    impl<W: Write + Send> RecordWriter for ParquetWriter<W> {
+        fn write(&mut self, records: &[AccessRecord]) -> Result<(), &'static str> {
+            self.pending.extend_from_slice(records);
+            if self.pending.len() >= ROW_GROUP_SIZE {
+                self.write_row_group().map_err(write_failed)?;
+            }
+            Ok(())
+        }
+
+        fn finish(mut self: Box<Self>) -> Result<(), &'static str> {
+            if !self.pending.is_empty() {
+                self.write_row_group().map_err(write_failed)?;
+            }
+            self.writer.close().map_err(write_failed)?;
+            Ok(())
+        }
+    }
+}
+
+fn record_writer<W: Write + Send + 'static>(
+    format: Format,
+    output: W,
+) -> Result<Box<dyn RecordWriter>, &'static str> {
+    match format {
+        Format::Csv => Ok(Box::new(CsvWriter {
+            writer: csv::Writer::from_writer(output),
+        })),
+        Format::Ndjson => Ok(Box::new(NdjsonWriter { writer: output })),
+        #[cfg(feature = "parquet")]
+        Format::Parquet => Ok(Box::new(parquet_writer::ParquetWriter::new(output)?)),
+        #[cfg(not(feature = "parquet"))]
+        Format::Parquet => Err("kirinox was built without Parquet support"),
+    }
+}
+
+/// Writes the rows matching the filter in the requested format and returns how many there
+/// were.
+pub fn export(args: &ExportArgs) -> Result<usize, &'static str> {
+    let persister = Db::new(args.config.db_options())?;
+    let output: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path).map_err(|err| {
+            eprintln!("{}: {err}", path.display());
+            "could not create the export file"
+        })?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut writer = record_writer(args.format, output)?;
+    let exported = persister.export(&args.filter, |records| writer.write(records))?;
+    writer.finish()?;
+    Ok(exported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> AccessRecord {
+        AccessRecord {
+            id: 1,
+            timestamp: 1_735_725_600_000,
+            remote_addr: String::from("203.0.113.7"),
+            remote_user: None,
+            method: String::from("GET"),
+            scheme: String::from("https"),
+            http_host: String::from("example.com"),
+            request_uri: String::from("/about"),
+            server_protocol: String::from("HTTP/2.0"),
+            status: 200,
+            body_bytes_sent: 512,
+            request_time: 0.02,
+            upstream_response_time: Some(0.01),
+            http_referer: Some(String::from("https://news.example/")),
+            http_user_agent: Some(String::from("Mozilla/5.0")),
+            visitor_hash: None,
+            is_bot: false,
+            country: Some(String::from("Germany")),
+            city: None,
+            is_vpn: false,
+            request_kind: String::from("pageview"),
+            // only for merging, the files leave it out
+            fingerprint: Some(String::from("5f2a")),
+        }
+    }
+
+    /// A `Write` the test can still read after the writer took it.
+    #[derive(Clone, Default)]
+    struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn written(format: Format) -> Vec<u8> {
+        let output = Shared::default();
+        let mut writer = record_writer(format, output.clone()).unwrap();
+        writer.write(&[record(), record()]).unwrap();
+        writer.finish().unwrap();
+        output.0.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn writes_csv_with_a_header() {
+        let csv = String::from_utf8(written(Format::Csv)).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("id,timestamp,remote_addr,remote_user,method"));
+        assert!(lines[1].starts_with("1,1735725600000,203.0.113.7,,GET,https,example.com,/about"));
+    }
+
+    #[test]
+    fn writes_one_json_object_per_line() {
+        let ndjson = String::from_utf8(written(Format::Ndjson)).unwrap();
+        let first: serde_json::Value =
+            serde_json::from_str(ndjson.lines().next().unwrap()).unwrap();
+        assert_eq!(first["request_uri"], "/about");
+        assert_eq!(first["city"], serde_json::Value::Null);
+        assert!(first.get("fingerprint").is_none());
+        assert_eq!(ndjson.lines().count(), 2);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn writes_parquet_that_reads_back() {
+        use parquet::{
+            file::reader::{FileReader, SerializedFileReader},
+            record::RowAccessor,
+        };
+
+        let path =
+            std::env::temp_dir().join(format!("kirinox-export-{}.parquet", std::process::id()));
+        std::fs::write(&path, written(Format::Parquet)).unwrap();
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        let row = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+        assert_eq!(row.get_string(7).unwrap(), "/about");
+        assert_eq!(row.get_int(9).unwrap(), 200);
+        std::fs::remove_file(path).unwrap();
+    }
+}
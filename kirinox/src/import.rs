@@ -0,0 +1,460 @@
+use std::{
+    env::Args,
+    fs,
+    iter::Peekable,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use persister::{
+    DaySummary, Db, DbOptions, Filter, Location, SCHEMA_VERSION, SqliteStorage, Storage, Summary,
+};
+use serde_json::Value;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// the report of `goaccess --output=report.json`
+    Goaccess,
+    /// a monthly AWStats data file, `awstats012025.example.com.txt`
+    Awstats,
+    /// another kirinox SQLite database
+    Krx,
+}
+
+impl ImportFormat {
+    pub fn parse(format: &str) -> Result<ImportFormat, &'static str> {
+        match format {
+            "goaccess" => Ok(ImportFormat::Goaccess),
+            "awstats" => Ok(ImportFormat::Awstats),
+            "krx" => Ok(ImportFormat::Krx),
+            _ => Err("unknown import format, expected goaccess, awstats or krx"),
+        }
+    }
+}
+
+/// `kirinox import --format <goaccess|awstats|krx> <path> [--host <host>] [--config <path>]`
+#[derive(Debug)]
+pub struct ImportArgs {
+    pub format: ImportFormat,
+    pub path: PathBuf,
+    /// host the report is about, GoAccess doesn't say and AWStats only in its file name
+    pub host: Option<String>,
+    pub config: Config,
+}
+
+impl ImportArgs {
+    pub fn from_args(args: &mut Peekable<Args>) -> Result<ImportArgs, &'static str> {
+        let (mut format, mut path, mut host) = (None, None, None);
+        let mut config = Config::default();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or("import options expect a value");
+            match arg.as_str() {
+                "--format" => format = Some(ImportFormat::parse(&value()?)?),
+                "--host" => host = Some(value()?),
+                "--config" => config = Config::from_file(&PathBuf::from(value()?))?,
+                _ if !arg.starts_with("--") && path.is_none() => path = Some(PathBuf::from(arg)),
+                _ => return Err("unknown argument"),
+            }
+        }
+        Ok(ImportArgs {
+            format: format.ok_or("import needs --format goaccess, awstats or krx")?,
+            path: path.ok_or("import needs the path of the file to import")?,
+            host,
+            config,
+        })
+    }
+}
+
+/// Numbers are `{"count": 12, "percent": 1.5}` in recent GoAccess versions and plain numbers
+/// in older ones.
+fn count(value: &Value) -> i64 {
+    match value {
+        Value::Object(object) => object.get("count").and_then(Value::as_i64).unwrap_or(0),
+        value => value.as_i64().unwrap_or(0),
+    }
+}
+
+fn panel<'a>(report: &'a Value, name: &str) -> &'a [Value] {
+    report[name]["data"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+fn top_list(report: &Value, name: &str) -> Vec<(String, i64)> {
+    panel(report, name)
+        .iter()
+        .filter_map(|item| Some((item["data"].as_str()?.to_string(), count(&item["hits"]))))
+        .collect()
+}
+
+/// `DE Germany` -> `Germany`, how GoAccess labels countries.
+fn country_name(label: &str) -> &str {
+    match label.split_once(' ') {
+        Some((code, name)) if code.len() == 2 && code.chars().all(|c| c.is_ascii_uppercase()) => {
+            name
+        }
+        _ => label,
+    }
+}
+
+/// GoAccess only has daily totals for requests, visitors and bandwidth. It doesn't tell pages
+/// from other requests per day, so imported days have no pageviews; the top pages come from
+/// its requests panel, which leaves static files out.
+pub fn parse_goaccess(json: &str, host: &str) -> Result<Summary, &'static str> {
+    let report: Value = serde_json::from_str(json).map_err(|err| {
+        eprintln!("{err}");
+        "could not parse the GoAccess report"
+    })?;
+    let mut summary = Summary {
+        host: host.to_string(),
+        pages: top_list(&report, "requests"),
+        referrers: top_list(&report, "referrers"),
+        ..Summary::default()
+    };
+    for day in panel(&report, "visitors") {
+        let Some(date) = day["data"]
+            .as_str()
+            .and_then(|date| parse_day(date, "%Y%m%d"))
+        else {
+            continue;
+        };
+        summary.days.push(DaySummary {
+            day: date,
+            requests: count(&day["hits"]),
+            pageviews: 0,
+            visitors: count(&day["visitors"]),
+            bytes_sent: count(&day["bytes"]),
+        });
+    }
+    for continent in panel(&report, "geolocation") {
+        // countries are grouped by continent, older versions list them directly
+        let countries = continent["items"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or(std::slice::from_ref(continent));
+        for country in countries {
+            if let Some(label) = country["data"].as_str() {
+                summary
+                    .countries
+                    .push((country_name(label).to_string(), count(&country["hits"])));
+            }
+        }
+    }
+    for class in panel(&report, "status_codes") {
+        let digit = class["data"]
+            .as_str()
+            .and_then(|label| label.chars().next())
+            .and_then(|c| c.to_digit(10));
+        if let Some(digit @ 1..=5) = digit {
+            summary.status[digit as usize - 1] += count(&class["hits"]);
+        }
+    }
+    if summary.days.is_empty() {
+        return Err("the GoAccess report has no visitors panel to take the days from");
+    }
+    Ok(summary)
+}
+
+fn parse_day(date: &str, format: &str) -> Option<i64> {
+    let date = NaiveDate::parse_from_str(date, format).ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp_millis())
+}
+
+/// Lines between `BEGIN_<name>` and `END_<name>`, split on spaces.
+fn awstats_section<'a>(data: &'a str, name: &str) -> Vec<Vec<&'a str>> {
+    let begin = format!("BEGIN_{name} ");
+    let end = format!("END_{name}");
+    data.lines()
+        .skip_while(|line| !line.starts_with(&begin))
+        .skip(1)
+        .take_while(|line| !line.starts_with(&end))
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| line.split(' ').collect())
+        .collect()
+}
+
+fn number(field: Option<&&str>) -> i64 {
+    field.and_then(|field| field.parse().ok()).unwrap_or(0)
+}
+
+/// `awstats012025.example.com.txt` -> `example.com`, AWStats names its files after the
+/// config, which usually is the site.
+pub fn awstats_host(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?.strip_suffix(".txt")?;
+    let rest = name.strip_prefix("awstats")?;
+    let (month, host) = rest.split_at_checked(6)?;
+    if !month.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    host.strip_prefix('.').map(str::to_string)
+}
+
+/// AWStats keeps daily pages, hits, bandwidth and visits of human traffic. Its daily visits
+/// are sessions rather than distinct visitors, they are the closest it has. Countries are
+/// only known by domain there and are left out.
+pub fn parse_awstats(data: &str, host: &str) -> Result<Summary, &'static str> {
+    let mut summary = Summary {
+        host: host.to_string(),
+        ..Summary::default()
+    };
+    for day in awstats_section(data, "DAY") {
+        let Some(date) = day.first().and_then(|date| parse_day(date, "%Y%m%d")) else {
+            continue;
+        };
+        summary.days.push(DaySummary {
+            day: date,
+            pageviews: number(day.get(1)),
+            requests: number(day.get(2)),
+            bytes_sent: number(day.get(3)),
+            visitors: number(day.get(4)),
+        });
+    }
+    summary.pages = awstats_section(data, "SIDER")
+        .iter()
+        .map(|page| (page[0].to_string(), number(page.get(1))))
+        .collect();
+    summary.referrers = awstats_section(data, "PAGEREFS")
+        .iter()
+        .map(|referrer| (referrer[0].to_string(), number(referrer.get(2))))
+        .collect();
+    for code in awstats_section(data, "HTTPCODE") {
+        if let Some(class @ 1..=5) = code[0].parse::<usize>().ok().map(|code| code / 100) {
+            summary.status[class - 1] += number(code.get(1));
+        }
+    }
+    if summary.days.is_empty() {
+        return Err("the AWStats file has no days in it");
+    }
+    Ok(summary)
+}
+
+fn read(path: &Path) -> Result<String, &'static str> {
+    fs::read_to_string(path).map_err(|err| {
+        eprintln!("{}: {err}", path.display());
+        "could not read the file to import"
+    })
+}
+
+/// Opens the kirinox database to merge without writing to it. One of an older schema is
+/// migrated in a temporary copy, whose path is returned to be deleted once it's merged.
+fn open_source(path: &Path) -> Result<(Db, Option<PathBuf>), &'static str> {
+    if !path.is_file() {
+        eprintln!("{}: no such file", path.display());
+        return Err("no database was found at the path to import");
+    }
+    let mut storage = SqliteStorage::open_read_only(path).map_err(|err| {
+        eprintln!("{}: {err}", path.display());
+        "could not open the database to import"
+    })?;
+    let version = storage.schema_version().map_err(|err| {
+        eprintln!("{}: {err}", path.display());
+        "could not read the schema version of the database to import"
+    })?;
+    // a newer schema is refused when it's opened
+    if version >= SCHEMA_VERSION {
+        let source = Db::with_storage(Box::new(storage), DbOptions::default())?;
+        return Ok((source, None));
+    }
+    let copy = std::env::temp_dir().join(format!("kirinox-import-{}.db", std::process::id()));
+    let _ = fs::remove_file(&copy);
+    storage.copy_to(&copy).map_err(|err| {
+        eprintln!("{}: {err}", copy.display());
+        "could not copy the database to import"
+    })?;
+    let source = Db::new(DbOptions {
+        location: Location::Sqlite(copy.clone()),
+        ..DbOptions::default()
+    })
+    .inspect_err(|_| {
+        let _ = fs::remove_file(&copy);
+    })?;
+    Ok((source, Some(copy)))
+}
+
+/// Loads the file into the configured database and returns a line saying what happened.
+pub fn import(args: &ImportArgs) -> Result<String, &'static str> {
+    let persister = Db::new(args.config.db_options())?;
+    let summary = match args.format {
+        ImportFormat::Goaccess => {
+            let host = args.host.as_deref().ok_or("GoAccess reports need --host")?;
+            parse_goaccess(&read(&args.path)?, host)?
+        }
+        ImportFormat::Awstats => {
+            let host = args
+                .host
+                .clone()
+                .or_else(|| awstats_host(&args.path))
+                .ok_or("could not tell the host from the file name, pass --host")?;
+            parse_awstats(&read(&args.path)?, &host)?
+        }
+        ImportFormat::Krx => {
+            let (source, copy) = open_source(&args.path)?;
+            let (mut inserted, mut duplicates) = (0, 0);
+            let merged = source.export(&Filter::new(), |records| {
+                let ingested = persister.import_records(records)?;
+                inserted += ingested.inserted;
+                duplicates += ingested.duplicates;
+                Ok(())
+            });
+            drop(source);
+            if let Some(copy) = copy {
+                let _ = fs::remove_file(copy);
+            }
+            merged?;
+            return Ok(format!(
+                "merged {inserted} rows, {duplicates} were already there"
+            ));
+        }
+    };
+    let imported = persister.import_summary(&summary)?;
+    let mut outcome = format!(
+        "imported {} days of {}, skipped {} days it already had",
+        imported.days, summary.host, imported.skipped_days
+    );
+    if imported.days > 0 && !imported.totals {
+        outcome.push_str(", left out the status codes and top lists of the whole report");
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_goaccess_reports() {
+        let report = r#"{
+            "general": {"start_date": "01/Jan/2025", "end_date": "02/Jan/2025"},
+            "visitors": {"data": [
+                {"hits": {"count": 120}, "visitors": {"count": 30}, "bytes": {"count": 4096}, "data": "20250101"},
+                {"hits": 80, "visitors": 20, "bytes": 2048, "data": "20250102"}
+            ]},
+            "requests": {"data": [{"hits": {"count": 50}, "method": "GET", "data": "/"}]},
+            "referrers": {"data": [{"hits": {"count": 5}, "data": "https://news.example/"}]},
+            "geolocation": {"data": [
+                {"hits": {"count": 70}, "data": "EU Europe", "items": [
+                    {"hits": {"count": 60}, "data": "DE Germany"},
+                    {"hits": {"count": 10}, "data": "FR France"}
+                ]}
+            ]},
+            "status_codes": {"data": [
+                {"hits": {"count": 190}, "data": "2xx Success"},
+                {"hits": {"count": 10}, "data": "4xx Client Errors"}
+            ]}
+        }"#;
+        let summary = parse_goaccess(report, "example.com").unwrap();
+        assert_eq!(summary.days.len(), 2);
+        assert_eq!(summary.days[0].day, 1_735_689_600_000);
+        assert_eq!(
+            (summary.days[1].requests, summary.days[1].visitors),
+            (80, 20)
+        );
+        assert_eq!(summary.pages, vec![(String::from("/"), 50)]);
+        assert_eq!(summary.countries[0], (String::from("Germany"), 60));
+        assert_eq!(summary.status, [0, 190, 0, 10, 0]);
+    }
+
+    #[test]
+    fn parses_awstats_data_files() {
+        let data = "AWSTATS DATA FILE 7.8
+BEGIN_GENERAL 2
+FirstTime 20250101000012
+END_GENERAL
+BEGIN_DAY 2
+# Date - Pages - Hits - Bandwidth - Visits
+20250101 40 120 4096 12
+20250102 30 90 2048 9
+END_DAY
+BEGIN_SIDER 1
+# URL - Pages - Bandwidth - Entry - Exit
+/about 25 1024 5 3
+END_SIDER
+BEGIN_PAGEREFS 1
+# External page referers - Pages - Hits
+https://news.example/ 3 7
+END_PAGEREFS
+BEGIN_HTTPCODE 2
+# Code - Hits - Bandwidth
+404 6 0
+500 1 0
+END_HTTPCODE
+";
+        let summary = parse_awstats(data, "example.com").unwrap();
+        assert_eq!(summary.days.len(), 2);
+        assert_eq!(summary.days[0].pageviews, 40);
+        assert_eq!(summary.days[0].requests, 120);
+        assert_eq!(summary.pages, vec![(String::from("/about"), 25)]);
+        assert_eq!(
+            summary.referrers,
+            vec![(String::from("https://news.example/"), 7)]
+        );
+        assert_eq!(summary.status, [0, 0, 0, 6, 1]);
+    }
+
+    #[test]
+    fn takes_the_host_from_awstats_file_names() {
+        let path = PathBuf::from("/var/lib/awstats/awstats012025.example.com.txt");
+        assert_eq!(awstats_host(&path), Some(String::from("example.com")));
+        assert_eq!(awstats_host(&PathBuf::from("notes.txt")), None);
+    }
+
+    #[test]
+    fn leaves_the_database_to_merge_alone() {
+        let path = std::env::temp_dir().join(format!("kirinox-merge-{}.db", std::process::id()));
+        assert!(open_source(&path).is_err());
+        assert!(!path.exists());
+
+        let line = "203.0.113.7\t-\t2025-01-01T10:00:00+00:00\tGET\thttps\texample.com\t/\tHTTP/2.0\t200\t512\t0.020\t-\t-\tMozilla/5.0";
+        let db = Db::new(DbOptions {
+            location: Location::Sqlite(path.clone()),
+            ..DbOptions::default()
+        })
+        .unwrap();
+        let enriched = enricher::EnrichedLog {
+            is_bot: false,
+            country: String::from("Germany"),
+            city: String::from("Berlin"),
+            is_vpn: false,
+            kind: enricher::RequestKind::Pageview,
+        };
+        let position = persister::Position {
+            source: "access.log",
+            offset: 0,
+        };
+        db.insert_record(
+            &parser::LogStruct::from_line(line).unwrap(),
+            &enriched,
+            position,
+        )
+        .unwrap();
+        drop(db);
+        // as if it was written before the last migration
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        storage
+            .execute_batch("DROP TABLE retention_horizons;")
+            .unwrap();
+        storage.set_schema_version(SCHEMA_VERSION - 1).unwrap();
+        drop(storage);
+
+        let (source, copy) = open_source(&path).unwrap();
+        let mut rows = 0;
+        source
+            .export(&Filter::new(), |records| {
+                rows += records.len();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(rows, 1);
+        drop(source);
+        fs::remove_file(copy.unwrap()).unwrap();
+        let mut storage = SqliteStorage::open_read_only(&path).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION - 1);
+        drop(storage);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+}
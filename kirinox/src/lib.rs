@@ -1,81 +1,330 @@
-use std::{env::Args, fs::File};
-use chrono::{DateTime, TimeDelta, Utc};
-use std::io::{BufRead, BufReader, Error, Seek, SeekFrom};
-use std::path::PathBuf;
+use chrono::{DateTime, NaiveDate, Utc};
+use displayer::{Displayer, HostSummary, Pages, ReportPeriod};
+use enricher::{Classifier, EnrichedLog, Enricher};
 use parser::{self, LogStruct, Parser};
-use enricher::Enricher;
-use persister::Db;
-use displayer::Displayer;
+use persister::{
+    Db, Filter, Ingested, Position, Pruned, ReferrerSource, Traffic, UaFamily, log_source,
+};
+use std::io::{BufRead, BufReader, Error, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::{env::Args, fs::File, iter::Peekable};
+
+pub mod config;
+pub mod export;
+pub mod import;
+pub mod report;
+pub mod serve;
+pub mod tui;
+
+use config::Config;
+use export::ExportArgs;
+use import::ImportArgs;
+use report::{ReportArgs, load};
+use serve::ServeArgs;
 
 #[derive(Debug)]
 pub struct ArgsConfig {
     pub nginx_log_path: PathBuf,
-    pub analytics_output_html: PathBuf,
+    /// directory the report pages are written to, one directory per host
+    pub output_dir: PathBuf,
+    pub config: Config,
+}
+
+/// What the binary was asked to do.
+#[derive(Debug)]
+pub enum Command {
+    /// `kirinox <logs> <output dir> [--config <path>]`, ingests the logs and renders the reports
+    Run(ArgsConfig),
+    /// `kirinox prune [--config <path>]`, applies the retention settings
+    Prune(Config),
+    /// `kirinox export --format <format> ...`, see `ExportArgs`
+    Export(ExportArgs),
+    /// `kirinox import --format <format> <path> ...`, see `ImportArgs`
+    Import(ImportArgs),
+    /// `kirinox report [--tui] ...`, see `ReportArgs`
+    Report(ReportArgs),
+    /// `kirinox serve [--listen <address>] ...`, see `ServeArgs`
+    Serve(ServeArgs),
+}
+
+impl Command {
+    pub fn from_env(args: Args) -> Result<Command, &'static str> {
+        let mut args = args.peekable();
+        let _ = args.next();
+        match args.peek().map(String::as_str) {
+            Some("prune") => {
+                let _ = args.next();
+                Ok(Command::Prune(parse_options(&mut args)?))
+            }
+            Some("export") => {
+                let _ = args.next();
+                ExportArgs::from_args(&mut args).map(Command::Export)
+            }
+            Some("import") => {
+                let _ = args.next();
+                ImportArgs::from_args(&mut args).map(Command::Import)
+            }
+            Some("report") => {
+                let _ = args.next();
+                ReportArgs::from_args(&mut args).map(Command::Report)
+            }
+            Some("serve") => {
+                let _ = args.next();
+                ServeArgs::from_args(&mut args).map(Command::Serve)
+            }
+            _ => ArgsConfig::from_args(args).map(Command::Run),
+        }
+    }
+}
+
+/// Options shared by every command, what's left of the arguments after the positional ones.
+fn parse_options(args: &mut Peekable<Args>) -> Result<Config, &'static str> {
+    let mut config = Config::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args.next().ok_or("--config expects a path")?;
+                config = Config::from_file(&PathBuf::from(path))?;
+            }
+            _ => return Err("unknown argument"),
+        }
+    }
+    Ok(config)
+}
+
+/// Accepts `2025-01-31` (midnight UTC) or an RFC 3339 time, returns unix milliseconds.
+pub fn parse_time(time: &str) -> Result<i64, &'static str> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(time) {
+        return Ok(time.timestamp_millis());
+    }
+    NaiveDate::parse_from_str(time, "%Y-%m-%d")
+        .map(|date| {
+            date.and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc()
+                .timestamp_millis()
+        })
+        .map_err(|_| "times are expected as 2025-01-31 or 2025-01-31T12:00:00Z")
+}
+
+/// Options narrowing what a command looks at, each taking a value.
+pub const FILTER_OPTIONS: [&str; 9] = [
+    "--host",
+    "--since",
+    "--until",
+    "--path",
+    "--status",
+    "--country",
+    "--traffic",
+    "--referrer",
+    "--browser",
+];
+
+/// Applies one of `FILTER_OPTIONS` to `filter`:
+/// `--host example.com --since 2025-01-01 --until 2025-02-01 --path /blog/ --status 4xx
+/// --country Germany --traffic humans --referrer news.example --browser firefox`.
+pub fn filter_option(filter: Filter, option: &str, value: &str) -> Result<Filter, &'static str> {
+    Ok(match option {
+        "--host" => filter.host(value),
+        "--since" => filter.since(parse_time(value)?),
+        "--until" => filter.until(parse_time(value)?),
+        "--path" => filter.path_prefix(value),
+        "--status" => filter.status_class(Filter::parse_status_class(value)?),
+        "--country" => filter.country(value),
+        "--traffic" => filter.traffic(Traffic::parse(value)?),
+        "--referrer" => filter.referrer(ReferrerSource::parse(value)),
+        "--browser" => filter.user_agent(UaFamily::parse(value)?),
+        _ => return Err("unknown argument"),
+    })
 }
 
 impl ArgsConfig {
-    pub fn from_env(mut args: Args) -> Result<Self, &'static str> {
-        if args.len() < 3 {
+    pub fn from_args(mut args: Peekable<Args>) -> Result<Self, &'static str> {
+        let (Some(logs_path), Some(output_dir)) = (args.next(), args.next()) else {
             return Err("not enough arguments");
-        }
-        let _ = args.next();
-        let logs_path = PathBuf::from(args.next().unwrap());
+        };
+        let logs_path = PathBuf::from(logs_path);
         if !logs_path.exists() {
             return Err("no logs were found at the provided path");
         }
+        let output_dir = PathBuf::from(output_dir);
+        let config = parse_options(&mut args)?;
         Ok(ArgsConfig {
             nginx_log_path: logs_path,
-            analytics_output_html: PathBuf::from(args.next().unwrap()),
+            output_dir,
+            config,
         })
     }
 }
 
-pub fn read_logs(log_path: &PathBuf) -> Result<i32, Error> {
-    let enricher = Enricher::new();
-    let persister = Db::new();
-    let parser = Parser::new(log_path).unwrap();
-    let displayer = Displayer{};
-    let last_recorded_ts = persister.fetch_last_known_entry_date();
-    let files = parser.find_files(last_recorded_ts);
-    for file in files.iter().rev() {
-        let opened_file = File::open(&file.file_path)?;
-        let mut line_results: Vec<String> = vec![];
-        let mut reader = BufReader::new(opened_file);
-        let mut buf = String::new();
-        while reader.read_line(&mut buf)? != 0 {
-            if file.start_from != 0 {
-                if reader.stream_position()? >= file.start_from {
-                    break;
+/// Stores the lines of the log file at `path` from the byte offset `start_from` on, oldest
+/// first, so a committed batch never leaves older lines behind the checkpoint if the run dies
+/// half way. Lines are told apart by their offset in the file, whatever part of it is read.
+/// `enrich` resolves and enriches every parsed line.
+pub fn ingest_file<F>(
+    persister: &Db,
+    path: &Path,
+    start_from: u64,
+    mut enrich: F,
+) -> Result<Ingested, Error>
+where
+    F: FnMut(&mut LogStruct) -> EnrichedLog,
+{
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut first_line = String::new();
+    if reader.read_line(&mut first_line)? == 0 {
+        return Ok(Ingested::default());
+    }
+    let source = log_source(&first_line);
+    reader.seek(SeekFrom::Start(start_from))?;
+    let mut ingested = Ingested::default();
+    let mut offset = start_from;
+    // each line with where it starts in the file
+    let mut lines: Vec<(u64, String)> = vec![];
+    loop {
+        let mut line = String::new();
+        let read = reader.read_line(&mut line)?;
+        if read != 0 {
+            lines.push((offset, line));
+            offset += read as u64;
+        }
+        if lines.len() == persister.batch_size() || (read == 0 && !lines.is_empty()) {
+            let mut records = vec![];
+            for (offset, line) in &lines {
+                if let Ok(mut log_struct) = LogStruct::from_line(line) {
+                    let enriched_log = enrich(&mut log_struct);
+                    let position = Position {
+                        source: &source,
+                        offset: *offset,
+                    };
+                    records.push((log_struct, enriched_log, position));
                 }
             }
-            println!("current file: {:?}, \ncurrent line: {}", file.file_path, buf);
-            line_results.push(buf.clone());
-            buf.clear();
+            let batch = persister
+                .insert_batch(records.iter().map(|(log_struct, enriched_log, position)| {
+                    (log_struct, enriched_log, *position)
+                }))
+                .map_err(Error::other)?;
+            ingested.inserted += batch.inserted;
+            ingested.duplicates += batch.duplicates;
+            ingested.too_old += batch.too_old;
+            lines.clear();
         }
-        println!("line restults are {:?}", line_results);
-        for line in line_results.iter().rev() {
-            if let Ok(log_struct) = LogStruct::from_line(line) {
-                let enriched_log = enricher.enrich(&log_struct);
-                persister.insert_record(&log_struct, &enriched_log);
-            }
+        if read == 0 {
+            return Ok(ingested);
         }
     }
+}
+
+pub fn read_logs(args_config: &ArgsConfig) -> Result<i32, Error> {
+    let enricher = Enricher::new(Classifier::new(args_config.config.classification.clone()));
+    let persister = Db::new(args_config.config.db_options()).map_err(Error::other)?;
+    let parser = Parser::new(&args_config.nginx_log_path).unwrap();
+    let trusted_proxies = args_config
+        .config
+        .proxies
+        .trusted_proxies()
+        .map_err(Error::other)?;
+    let pages = Pages::new(args_config.config.pages.appearance()).map_err(Error::other)?;
+    let displayer = Displayer::new(&args_config.output_dir).pages(pages);
+    let last_recorded_ts = persister
+        .fetch_last_known_entry_date()
+        .map_err(Error::other)?;
+    let files = parser.find_files(last_recorded_ts)?;
+    let mut duplicates = 0;
+    let mut too_old = 0;
+    for file in files.iter().rev() {
+        let ingested = ingest_file(&persister, &file.file_path, file.start_from, |log_struct| {
+            log_struct.resolve_client_addr(&trusted_proxies);
+            enricher.enrich(log_struct)
+        })?;
+        duplicates += ingested.duplicates;
+        too_old += ingested.too_old;
+    }
+    if duplicates > 0 {
+        println!("skipped {duplicates} lines that were already stored");
+    }
+    if too_old > 0 {
+        println!("skipped {too_old} lines older than the pruned data");
+    }
     parser.clean_up(files)?;
-    let hosts = persister.get_hosts().unwrap();
-    let utc: DateTime<Utc> = Utc::now();
-    let delta_week = TimeDelta::days(7);
-    let delta_month = TimeDelta::days(31);
-
-    let week_ago = utc - delta_week;
-    let month_ago = utc - delta_month;
-    let dates = vec![week_ago.timestamp_millis(), month_ago.timestamp_millis(), 0];
+    let now = Utc::now();
+    persister
+        .expire_salts(now.timestamp_millis())
+        .map_err(Error::other)?;
+    let hosts = persister.get_hosts().map_err(Error::other)?;
+    let mut index = vec![];
     for host in hosts {
-        for date in &dates {
-            let stats = persister.get_stats(&host, *date);
-            displayer.get_template(stats, &host);
+        for period in ReportPeriod::ALL {
+            let report =
+                load(&persister, &Filter::new().host(&host), period, now).map_err(Error::other)?;
+            if period == ReportPeriod::Month {
+                index.push(HostSummary::new(&host, &report.stats));
+            }
+            displayer.write_report(report)?;
         }
     }
-
+    let index = displayer.write_index(&index, now)?;
+    println!("reports written to {}", index.display());
 
     Ok(10)
 }
+
+/// Deletes what the retention settings no longer keep.
+pub fn prune(config: &Config) -> Result<Pruned, Error> {
+    let persister = Db::new(config.db_options()).map_err(Error::other)?;
+    persister
+        .prune(&config.retention.retention(), Utc::now().timestamp_millis())
+        .map_err(Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enricher::RequestKind;
+    use persister::{DbOptions, SqliteStorage};
+    use std::fs;
+
+    fn line(minute: u32) -> String {
+        format!(
+            "203.0.113.7\t-\t2025-01-01T10:{minute:02}:00+00:00\tGET\thttps\texample.com\t/page/{minute}\tHTTP/2.0\t200\t512\t0.020\t0.010\thttps://news.example/\tMozilla/5.0\n"
+        )
+    }
+
+    #[test]
+    fn reads_the_unrecorded_tail_of_a_log() {
+        let dir = std::env::temp_dir().join(format!("kirinox-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        let db = Db::with_storage(
+            Box::new(SqliteStorage::in_memory().unwrap()),
+            DbOptions::default(),
+        )
+        .unwrap();
+        let enrich = |_: &mut LogStruct| EnrichedLog {
+            is_bot: false,
+            country: String::from("Germany"),
+            city: String::from("Berlin"),
+            is_vpn: false,
+            kind: RequestKind::Pageview,
+        };
+
+        // half of the lines were there on the last run
+        fs::write(&path, (0..6).map(line).collect::<String>()).unwrap();
+        let first = ingest_file(&db, &path, 0, enrich).unwrap();
+        assert_eq!(first.inserted, 6);
+        fs::write(&path, (0..12).map(line).collect::<String>()).unwrap();
+
+        let checkpoint = db.fetch_last_known_entry_date().unwrap();
+        let files = Parser::new(&dir).unwrap().find_files(checkpoint).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].start_from > 0);
+        let tail = ingest_file(&db, &path, files[0].start_from, enrich).unwrap();
+        // only the lines around the checkpoint are read again
+        assert_eq!(tail.inserted, 6);
+        assert!(tail.duplicates <= 2);
+        let stats = db.get_stats(&Filter::new().host("example.com")).unwrap();
+        assert_eq!(stats.total_requests, 12);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
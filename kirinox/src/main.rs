@@ -1,12 +1,59 @@
-use kirinox::ArgsConfig;
-use kirinox::read_logs;
+use kirinox::{Command, export::export, import::import, prune, read_logs, report, serve, tui};
 use std::{env, process};
 
-
 fn main() {
-    let config = ArgsConfig::from_env(env::args()).unwrap_or_else(|err| {
+    let command = Command::from_env(env::args()).unwrap_or_else(|err| {
         eprintln!("There was an error loading the config: {err}");
         process::exit(1);
     });
-    read_logs(&config.nginx_log_path).unwrap();
+    match command {
+        Command::Run(config) => {
+            read_logs(&config).unwrap();
+        }
+        Command::Prune(config) => {
+            let pruned = prune(&config).unwrap_or_else(|err| {
+                eprintln!("Pruning failed: {err}");
+                process::exit(1);
+            });
+            println!(
+                "pruned {} rows, {} bot rows, {} sessions, {} hourly and {} daily rollups",
+                pruned.raw_rows,
+                pruned.bot_rows,
+                pruned.sessions,
+                pruned.hourly_rollups,
+                pruned.daily_rollups
+            );
+        }
+        Command::Export(args) => {
+            let exported = export(&args).unwrap_or_else(|err| {
+                eprintln!("Export failed: {err}");
+                process::exit(1);
+            });
+            eprintln!("exported {exported} rows");
+        }
+        Command::Import(args) => {
+            let imported = import(&args).unwrap_or_else(|err| {
+                eprintln!("Import failed: {err}");
+                process::exit(1);
+            });
+            println!("{imported}");
+        }
+        Command::Report(args) => {
+            let shown = if args.tui {
+                tui::run(&args)
+            } else {
+                report::print(&args)
+            };
+            shown.unwrap_or_else(|err| {
+                eprintln!("Report failed: {err}");
+                process::exit(1);
+            });
+        }
+        Command::Serve(args) => {
+            serve::serve(&args).unwrap_or_else(|err| {
+                eprintln!("Serving failed: {err}");
+                process::exit(1);
+            });
+        }
+    }
 }
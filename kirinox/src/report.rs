@@ -0,0 +1,468 @@
+use std::{
+    env::Args,
+    io::{Error, Write, stdout},
+    iter::Peekable,
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use displayer::{
+    Report, ReportPeriod, format_bytes, format_count, format_duration, format_latency,
+    format_percent, percent,
+};
+use persister::{Db, Filter, Period, StorageResult};
+
+use crate::{FILTER_OPTIONS, config::Config, filter_option};
+
+/// Seconds between reloads when following without `--refresh`.
+pub const DEFAULT_REFRESH: u64 = 10;
+
+/// Columns a full bar takes.
+const BAR_WIDTH: usize = 20;
+
+/// Columns left for the labels of the tables, longer ones are cut.
+const LABEL_WIDTH: usize = 40;
+
+/// The last cell of a bar, by how many eighths of it are filled.
+const EIGHTHS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// `kirinox report [--period 7d|30d|12m|all] [filter options] [--tui] [--follow]
+/// [--refresh <seconds>] [--config <path>]`, the filter options being those of
+/// `filter_option`. Every host is shown when no `--host` is given.
+#[derive(Debug)]
+pub struct ReportArgs {
+    pub period: ReportPeriod,
+    pub filter: Filter,
+    /// the interactive dashboard instead of the tables
+    pub tui: bool,
+    /// seconds between reloads, `None` shows the numbers once
+    pub follow: Option<u64>,
+    pub config: Config,
+}
+
+impl ReportArgs {
+    pub fn from_args(args: &mut Peekable<Args>) -> Result<ReportArgs, &'static str> {
+        let mut report = ReportArgs {
+            period: ReportPeriod::Month,
+            filter: Default::default(),
+            tui: false,
+            follow: None,
+            config: Config::default(),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or("report options expect a value");
+            match arg.as_str() {
+                "--tui" => report.tui = true,
+                "--follow" => report.follow = report.follow.or(Some(DEFAULT_REFRESH)),
+                "--refresh" => {
+                    let seconds = value()?.parse().map_err(|_| "--refresh expects seconds")?;
+                    if seconds == 0 {
+                        return Err("--refresh expects seconds");
+                    }
+                    report.follow = Some(seconds);
+                }
+                "--period" => report.period = ReportPeriod::parse(&value()?)?,
+                option if FILTER_OPTIONS.contains(&option) => {
+                    report.filter = filter_option(report.filter, option, &value()?)?
+                }
+                "--config" => report.config = Config::from_file(&PathBuf::from(value()?))?,
+                _ => return Err("unknown argument"),
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Runs the queries of a report page for `filter`, which names the host, over `period` up to
+/// `now`. A range given in the filter wins over the period.
+pub fn load(
+    db: &Db,
+    filter: &Filter,
+    period: ReportPeriod,
+    now: DateTime<Utc>,
+) -> Result<Report, &'static str> {
+    let mut filter = filter.clone();
+    if filter.since.is_none() && filter.until.is_none() {
+        if let Some(since) = period.since(now.timestamp_millis()) {
+            filter = filter.since(since);
+        }
+        filter = filter.until(now.timestamp_millis());
+    }
+    query(db, &filter, period, now).map_err(|err| {
+        eprintln!("{err}");
+        "could not load the report"
+    })
+}
+
+fn query(
+    db: &Db,
+    filter: &Filter,
+    period: ReportPeriod,
+    now: DateTime<Utc>,
+) -> StorageResult<Report> {
+    let stats = db.get_stats(filter)?;
+    let step = step(filter);
+    Ok(Report {
+        host: filter.host.clone().unwrap_or_default(),
+        period,
+        changes: db.get_changes(filter, &stats)?,
+        step,
+        timeseries: db.get_timeseries(filter, step)?,
+        status: db.get_status_report(filter)?,
+        bandwidth: db.get_bandwidth(filter)?,
+        stats,
+        generated_at: now,
+    })
+}
+
+/// Bucket length of the charts over the range of `filter`, hours up to a week and days for
+/// longer or open ranges, so a range given in the query can't ask for years of hours.
+fn step(filter: &Filter) -> Period {
+    match (filter.since, filter.until) {
+        (Some(since), Some(until)) if until - since <= 7 * 24 * Period::Hour.length() => {
+            Period::Hour
+        }
+        _ => Period::Day,
+    }
+}
+
+/// `value` as a bar of up to `width` cells, full when it is `max`, padded to `width`.
+pub fn bar(value: f64, max: f64, width: usize) -> String {
+    let eighths = if max > 0.0 {
+        (value.clamp(0.0, max) / max * (width * 8) as f64).round() as usize
+    } else {
+        0
+    };
+    let mut bar = "█".repeat(eighths / 8);
+    bar.push_str(EIGHTHS[eighths % 8]);
+    let cells = eighths / 8 + usize::from(eighths % 8 > 0);
+    bar.push_str(&" ".repeat(width - cells));
+    bar
+}
+
+/// One block per value, as tall as the value against the largest.
+pub fn sparkline(values: &[f64]) -> String {
+    let max = values.iter().copied().fold(0.0, f64::max);
+    values
+        .iter()
+        .map(|value| {
+            let level = if max > 0.0 {
+                (value / max * 7.0).round() as usize
+            } else {
+                0
+            };
+            SPARKS[level]
+        })
+        .collect()
+}
+
+/// `label` cut to `width` characters, the cut marked with an ellipsis.
+pub fn truncate(label: &str, width: usize) -> String {
+    if label.chars().count() <= width {
+        return label.to_string();
+    }
+    let mut label: String = label.chars().take(width - 1).collect();
+    label.push('…');
+    label
+}
+
+fn change(change: Option<f64>) -> String {
+    change
+        .map(|change| format!(" ({change:+.1}%)"))
+        .unwrap_or_default()
+}
+
+fn count(value: f64) -> String {
+    format_count(value.round() as i64)
+}
+
+/// A titled table of labels with their value, the share of `total` they are and a bar scaled
+/// to the largest of them. Nothing when there are no rows.
+fn table(
+    out: &mut String,
+    title: &str,
+    rows: &[(String, f64)],
+    total: f64,
+    format: fn(f64) -> String,
+) {
+    if rows.is_empty() {
+        return;
+    }
+    let max = rows.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+    out.push_str(&format!("\n{title}\n"));
+    for (label, value) in rows {
+        out.push_str(&format!(
+            "  {:<LABEL_WIDTH$} {:>10} {:>7} {}\n",
+            truncate(label, LABEL_WIDTH),
+            format(*value),
+            format_percent(percent(*value, total)),
+            bar(*value, max, BAR_WIDTH).trim_end()
+        ));
+    }
+}
+
+fn counts(rows: &[(String, i32)]) -> Vec<(String, f64)> {
+    rows.iter()
+        .map(|(label, count)| (label.clone(), f64::from(*count)))
+        .collect()
+}
+
+fn bytes(rows: &[(String, i64)]) -> Vec<(String, f64)> {
+    rows.iter()
+        .map(|(label, bytes)| (label.clone(), *bytes as f64))
+        .collect()
+}
+
+/// The report as plain text tables for a terminal.
+pub fn render(report: &Report) -> String {
+    let stats = &report.stats;
+    let changes = &report.changes;
+    let requests = f64::from(stats.total_requests);
+    let mut out = format!("{} · {}\n\n", report.host, report.period.label());
+    let headline = [
+        (
+            "Requests",
+            format_count(stats.total_requests),
+            change(changes.total_requests),
+        ),
+        (
+            "Pageviews",
+            format_count(stats.pageviews),
+            change(changes.pageviews),
+        ),
+        (
+            "Unique visitors",
+            format_count(stats.unique_visitors),
+            change(changes.unique_visitors),
+        ),
+        (
+            "Bot share",
+            format_percent(stats.bot_share()),
+            String::new(),
+        ),
+        (
+            "Bandwidth",
+            format_bytes(stats.bytes_sent as f64),
+            change(changes.bytes_sent),
+        ),
+        (
+            "Avg response",
+            format_latency(f64::from(stats.avg_response_time)),
+            change(changes.avg_response_time),
+        ),
+        (
+            "Avg session",
+            format_duration(stats.sessions.avg_duration),
+            String::new(),
+        ),
+        (
+            "Error rate",
+            format_percent(report.status.error_rate()),
+            String::new(),
+        ),
+    ];
+    for (label, value, change) in headline {
+        out.push_str(&format!("  {label:<16} {value:>12}{change}\n"));
+    }
+    if !report.timeseries.is_empty() {
+        let requests: Vec<f64> = report
+            .timeseries
+            .iter()
+            .map(|point| f64::from(point.requests))
+            .collect();
+        out.push_str(&format!(
+            "\n  {:<16} {}\n",
+            "Requests trend",
+            sparkline(&requests)
+        ));
+    }
+    table(
+        &mut out,
+        "Top Pages",
+        &counts(&stats.pages),
+        f64::from(stats.pageviews),
+        count,
+    );
+    table(
+        &mut out,
+        "Countries",
+        &counts(&stats.countries),
+        requests,
+        count,
+    );
+    table(
+        &mut out,
+        "Referrers",
+        &counts(&stats.referrers),
+        requests,
+        count,
+    );
+    let classes: Vec<(String, f64)> = report
+        .status
+        .classes
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(class, count)| (format!("{}xx", class + 1), f64::from(*count)))
+        .collect();
+    let answered: i32 = report.status.classes.iter().sum();
+    table(&mut out, "Status", &classes, f64::from(answered), count);
+    let not_found: Vec<(String, f64)> = report
+        .status
+        .not_found
+        .iter()
+        .map(|link| (link.path.clone(), f64::from(link.hits)))
+        .collect();
+    table(
+        &mut out,
+        "Not Found",
+        &not_found,
+        f64::from(report.status.classes[3]),
+        count,
+    );
+    let bandwidth = &report.bandwidth;
+    let sent = stats.bytes_sent as f64;
+    table(
+        &mut out,
+        "Paths by Bandwidth",
+        &bytes(&bandwidth.paths),
+        sent,
+        format_bytes,
+    );
+    table(
+        &mut out,
+        "Bandwidth by Country",
+        &bytes(&bandwidth.countries),
+        sent,
+        format_bytes,
+    );
+    table(
+        &mut out,
+        "Bandwidth by Bot",
+        &bytes(&bandwidth.bots),
+        sent,
+        format_bytes,
+    );
+    out
+}
+
+/// Prints the report of every host the filter selects, again every `follow` seconds.
+pub fn print(args: &ReportArgs) -> Result<(), Error> {
+    let db = Db::new(args.config.db_options()).map_err(Error::other)?;
+    let hosts = match &args.filter.host {
+        Some(host) => vec![host.clone()],
+        None => db.get_hosts().map_err(Error::other)?,
+    };
+    loop {
+        let now = Utc::now();
+        let mut out = String::new();
+        if args.follow.is_some() {
+            // clear the screen and start at the top, like watch does
+            out.push_str("\x1b[2J\x1b[H");
+        }
+        for host in &hosts {
+            let filter = args.filter.clone().host(host);
+            let report = load(&db, &filter, args.period, now).map_err(Error::other)?;
+            out.push_str(&render(&report));
+            out.push('\n');
+        }
+        stdout().write_all(out.as_bytes())?;
+        match args.follow {
+            Some(seconds) => thread::sleep(Duration::from_secs(seconds)),
+            None => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persister::{Stats, StatusReport, TimePoint};
+
+    #[test]
+    fn draws_bars_in_eighths() {
+        assert_eq!(bar(10.0, 10.0, 4), "████");
+        assert_eq!(bar(5.0, 10.0, 4), "██  ");
+        assert_eq!(bar(1.0, 10.0, 4), "▍   ");
+        assert_eq!(bar(0.0, 10.0, 4), "    ");
+        assert_eq!(bar(3.0, 0.0, 4), "    ");
+    }
+
+    #[test]
+    fn draws_sparklines() {
+        assert_eq!(sparkline(&[0.0, 7.0, 14.0]), "▁▅█");
+        assert_eq!(sparkline(&[0.0, 0.0]), "▁▁");
+    }
+
+    #[test]
+    fn cuts_long_labels() {
+        assert_eq!(truncate("/about", 10), "/about");
+        assert_eq!(truncate("/blog/a-very-long-post", 10), "/blog/a-v…");
+    }
+
+    #[test]
+    fn steps_by_the_range_of_the_filter() {
+        let day = 24 * Period::Hour.length();
+        let now = 20_000 * day;
+        assert_eq!(
+            step(&Filter::new().since(now - 7 * day).until(now)),
+            Period::Hour
+        );
+        assert_eq!(step(&Filter::new().since(0).until(now)), Period::Day);
+        assert_eq!(step(&Filter::new().since(now - day)), Period::Day);
+        assert_eq!(step(&Filter::new()), Period::Day);
+    }
+
+    #[test]
+    fn renders_tables_with_shares() {
+        let report = Report {
+            host: String::from("example.com"),
+            period: ReportPeriod::Week,
+            stats: Stats {
+                total_requests: 8,
+                pageviews: 4,
+                bytes_sent: 2048,
+                pages: vec![(String::from("/"), 3), (String::from("/about"), 1)],
+                countries: vec![(String::from("Germany"), 2)],
+                ..Stats::default()
+            },
+            step: Period::Hour,
+            timeseries: vec![
+                TimePoint {
+                    requests: 1,
+                    ..TimePoint::default()
+                },
+                TimePoint {
+                    requests: 7,
+                    ..TimePoint::default()
+                },
+            ],
+            changes: Default::default(),
+            status: StatusReport {
+                classes: [0, 6, 0, 2, 0],
+                ..StatusReport::default()
+            },
+            bandwidth: Default::default(),
+            generated_at: DateTime::from_timestamp_millis(0).unwrap(),
+        };
+        let text = render(&report);
+        assert!(text.starts_with("example.com · Last 7 days\n"));
+        assert!(text.contains("  Bandwidth             2.0 KiB\n"));
+        assert!(text.contains("  Error rate              25.0%\n"));
+        assert!(text.contains("  Requests trend   ▂█\n"));
+        assert!(text.contains(&format!(
+            "\nTop Pages\n  {:<40} {:>10} {:>6}% {}\n",
+            "/",
+            3,
+            "75.0",
+            "█".repeat(20)
+        )));
+        assert!(text.contains(&format!("  {:<40} {:>10} {:>6}% ", "Germany", 2, "25.0")));
+        assert!(text.contains(&format!("  {:<40} {:>10} {:>6}% ", "4xx", 2, "25.0")));
+        assert!(!text.contains("Referrers"));
+    }
+}
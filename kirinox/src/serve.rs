@@ -0,0 +1,398 @@
+use std::{env::Args, io::Error, iter::Peekable, path::PathBuf};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Utc};
+use displayer::{
+    FilterForm, HostSummary, Links, Pages, ReportPeriod, index_json, report_json, url_encode,
+};
+use persister::{Db, Filter};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::{FILTER_OPTIONS, config::Config, filter_option, report::load};
+
+/// `kirinox serve [--listen <address>] [--config <path>]`, the address defaulting to the
+/// `[server]` section of the config
+#[derive(Debug)]
+pub struct ServeArgs {
+    pub listen: Option<String>,
+    pub config: Config,
+}
+
+impl ServeArgs {
+    pub fn from_args(args: &mut Peekable<Args>) -> Result<ServeArgs, &'static str> {
+        let mut serve = ServeArgs {
+            listen: None,
+            config: Config::default(),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or("serve options expect a value");
+            match arg.as_str() {
+                "--listen" => serve.listen = Some(value()?),
+                "--config" => serve.config = Config::from_file(&PathBuf::from(value()?))?,
+                _ => return Err("unknown argument"),
+            }
+        }
+        Ok(serve)
+    }
+}
+
+/// A response before it goes to `tiny_http`.
+#[derive(Debug, PartialEq)]
+struct Page {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Page {
+    fn html(body: String) -> Page {
+        Page {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body,
+        }
+    }
+
+    fn json(body: String) -> Page {
+        Page {
+            status: 200,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Page {
+        Page {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{message}\n"),
+        }
+    }
+}
+
+/// What the query string of a page asks for.
+#[derive(Debug, Default)]
+struct Query {
+    period: Option<ReportPeriod>,
+    filter: Filter,
+    form: FilterForm,
+    /// the filter again without host and period, for the links of the page
+    query: String,
+}
+
+impl Query {
+    fn period(&self) -> ReportPeriod {
+        self.period.unwrap_or(ReportPeriod::Month)
+    }
+
+    fn links(&self) -> Links {
+        Links::Server {
+            query: self.query.clone(),
+        }
+    }
+}
+
+/// `value` with `+` and percent encoded bytes decoded.
+fn url_decode(value: &str) -> String {
+    let value = value.as_bytes();
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < value.len() {
+        let hex = value
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok());
+        match (
+            value[i],
+            hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+        ) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                i += 2;
+            }
+            (b'+', _) => bytes.push(b' '),
+            (byte, _) => bytes.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Takes `host`, `period` and the `FILTER_OPTIONS` without their dashes:
+/// `?host=example.com&period=7d&since=2025-01-01&country=Germany&traffic=humans`. Empty values
+/// are left out, forms send every field.
+fn parse_query(query: &str) -> Result<Query, &'static str> {
+    let mut parsed = Query::default();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let (key, value) = (url_decode(key), url_decode(value));
+        if value.is_empty() {
+            continue;
+        }
+        let option = format!("--{key}");
+        match key.as_str() {
+            "period" => parsed.period = Some(ReportPeriod::parse(&value)?),
+            "host" => parsed.filter = parsed.filter.host(value.clone()),
+            _ if FILTER_OPTIONS.contains(&option.as_str()) => {
+                parsed.filter = filter_option(parsed.filter, &option, &value)?;
+                parsed
+                    .query
+                    .push_str(&format!("&{key}={}", url_encode(&value)));
+            }
+            _ => return Err("unknown filter"),
+        }
+        let field = match key.as_str() {
+            "host" => &mut parsed.form.host,
+            "since" => &mut parsed.form.since,
+            "until" => &mut parsed.form.until,
+            "country" => &mut parsed.form.country,
+            "path" => &mut parsed.form.path,
+            "traffic" => &mut parsed.form.traffic,
+            _ => continue,
+        };
+        *field = value;
+    }
+    parsed.form.period = parsed.period().key().to_string();
+    Ok(parsed)
+}
+
+/// Whether `authorization`, the header of a request, carries `credentials`. Anything goes
+/// without credentials.
+fn authorized(authorization: Option<&str>, credentials: Option<(&str, &str)>) -> bool {
+    let Some((username, password)) = credentials else {
+        return true;
+    };
+    authorization
+        .and_then(|header| header.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .is_some_and(|decoded| decoded == format!("{username}:{password}").as_bytes())
+}
+
+/// Every host with its numbers of the last 30 days, narrowed by `filter`.
+fn summaries(db: &Db, filter: &Filter, now: DateTime<Utc>) -> Result<Vec<HostSummary>, Error> {
+    let hosts = db.get_hosts().map_err(Error::other)?;
+    hosts
+        .iter()
+        .map(|host| {
+            let filter = filter.clone().host(host);
+            let report = load(db, &filter, ReportPeriod::Month, now).map_err(Error::other)?;
+            Ok(HostSummary::new(host, &report.stats))
+        })
+        .collect()
+}
+
+/// The page at `url`:
+/// - `/` the hosts, `/api/hosts` the same as JSON
+/// - `/stats` and `/status` the pages of a host, `/api/stats` its JSON report
+fn respond(db: &Db, pages: &Pages, url: &str, now: DateTime<Utc>) -> Page {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let query = match parse_query(query) {
+        Ok(query) => query,
+        Err(err) => return Page::error(400, err),
+    };
+    let links = query.links();
+    let page = match path {
+        "/" | "/api/hosts" => summaries(db, &query.filter, now).and_then(|hosts| match path {
+            "/" => pages.index(&hosts, now, &links).map(Page::html),
+            _ => Ok(Page::json(index_json(&hosts, now, &links))),
+        }),
+        "/stats" | "/status" | "/api/stats" => {
+            if query.filter.host.is_none() {
+                return Page::error(400, "host is missing");
+            }
+            let report = match load(db, &query.filter, query.period(), now) {
+                Ok(report) => report,
+                Err(err) => return Page::error(500, err),
+            };
+            match path {
+                "/stats" => pages
+                    .stats(&report, &links, Some(query.form))
+                    .map(Page::html),
+                "/status" => pages.status(&report, &links).map(Page::html),
+                _ => Ok(Page::json(report_json(&report))),
+            }
+        }
+        _ => return Page::error(404, "not found"),
+    };
+    page.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        Page::error(500, "the page could not be rendered")
+    })
+}
+
+/// Serves the dashboard until the process is stopped, one request at a time.
+pub fn serve(args: &ServeArgs) -> Result<(), Error> {
+    let db = Db::new(args.config.db_options()).map_err(Error::other)?;
+    let pages = Pages::new(args.config.pages.appearance()).map_err(Error::other)?;
+    let listen = args.listen.as_ref().unwrap_or(&args.config.server.listen);
+    let server = Server::http(listen).map_err(Error::other)?;
+    println!("serving the dashboard on http://{listen}/");
+    let credentials = args.config.server.credentials();
+    for request in server.incoming_requests() {
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .map(|header| header.value.as_str());
+        let page = if !authorized(authorization, credentials) {
+            Page::error(401, "unauthorized")
+        } else if *request.method() != Method::Get {
+            Page::error(405, "only GET is supported")
+        } else {
+            respond(&db, &pages, request.url(), Utc::now())
+        };
+        let mut response = Response::from_string(page.body)
+            .with_status_code(page.status)
+            .with_header(Header::from_bytes("Content-Type", page.content_type).unwrap());
+        if page.status == 401 {
+            response.add_header(
+                Header::from_bytes("WWW-Authenticate", r#"Basic realm="kirinox""#).unwrap(),
+            );
+        }
+        if let Err(err) = request.respond(response) {
+            eprintln!("{err}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persister::{AccessRecord, DbOptions, Location, SqliteStorage, Storage, Traffic};
+
+    #[test]
+    fn decodes_query_values() {
+        assert_eq!(url_decode("%2Fblog%2F+new"), "/blog/ new");
+        assert_eq!(url_decode("M%C3%BCnchen"), "München");
+        assert_eq!(url_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn turns_queries_into_filters() {
+        let query =
+            parse_query("host=example.com&period=7d&since=2025-01-01&until=&traffic=humans")
+                .unwrap();
+        assert_eq!(query.period(), ReportPeriod::Week);
+        assert_eq!(query.filter.host.as_deref(), Some("example.com"));
+        assert_eq!(query.filter.since, Some(1_735_689_600_000));
+        assert_eq!(query.filter.until, None);
+        assert_eq!(query.filter.traffic, Some(Traffic::Humans));
+        assert_eq!(query.query, "&since=2025-01-01&traffic=humans");
+        assert_eq!(query.form.since, "2025-01-01");
+        assert_eq!(query.form.period, "7d");
+        assert_eq!(parse_query("").unwrap().form.period, "30d");
+        assert!(parse_query("traffic=aliens").is_err());
+        assert!(parse_query("colour=blue").is_err());
+    }
+
+    #[test]
+    fn checks_basic_auth() {
+        let credentials = Some(("admin", "secret"));
+        assert!(authorized(None, None));
+        assert!(authorized(Some("Basic YWRtaW46c2VjcmV0"), credentials));
+        assert!(!authorized(Some("Basic YWRtaW46d3Jvbmc="), credentials));
+        assert!(!authorized(None, credentials));
+    }
+
+    #[test]
+    fn serves_pages_and_json() {
+        let path = std::env::temp_dir().join(format!("kirinox-serve-{}.db", std::process::id()));
+        let db = Db::new(DbOptions {
+            location: Location::Sqlite(path.clone()),
+            ..DbOptions::default()
+        })
+        .unwrap();
+        db.import_records(&[AccessRecord {
+            id: 1,
+            timestamp: 1_735_725_600_000,
+            remote_addr: String::from("203.0.113.7"),
+            remote_user: None,
+            method: String::from("GET"),
+            scheme: String::from("https"),
+            http_host: String::from("example.com"),
+            request_uri: String::from("/about"),
+            server_protocol: String::from("HTTP/2.0"),
+            status: 200,
+            body_bytes_sent: 512,
+            request_time: 0.02,
+            upstream_response_time: None,
+            http_referer: None,
+            http_user_agent: Some(String::from("Mozilla/5.0")),
+            visitor_hash: None,
+            is_bot: false,
+            country: Some(String::from("Germany")),
+            city: None,
+            is_vpn: false,
+            request_kind: String::from("pageview"),
+            fingerprint: None,
+        }])
+        .unwrap();
+        let now = DateTime::from_timestamp_millis(1_735_812_000_000).unwrap();
+        let pages = Pages::default();
+
+        let index = respond(&db, &pages, "/", now);
+        assert_eq!(index.status, 200);
+        assert!(
+            index
+                .body
+                .contains("/stats?host=example.com&#38;period=30d")
+        );
+        let hosts = respond(&db, &pages, "/api/hosts?country=Germany", now);
+        assert_eq!(hosts.content_type, "application/json");
+        assert!(
+            hosts
+                .body
+                .contains(r#""/api/stats?host=example.com&period=7d&country=Germany""#)
+        );
+
+        let stats = respond(&db, &pages, "/stats?host=example.com&period=7d", now);
+        assert!(stats.body.contains(r#"<form class="filters""#));
+        let json = respond(
+            &db,
+            &pages,
+            "/api/stats?host=example.com&country=Germany",
+            now,
+        );
+        let json: serde_json::Value = serde_json::from_str(&json.body).unwrap();
+        assert_eq!(json["totals"]["requests"], 1);
+        let elsewhere = respond(
+            &db,
+            &pages,
+            "/api/stats?host=example.com&country=France",
+            now,
+        );
+        let elsewhere: serde_json::Value = serde_json::from_str(&elsewhere.body).unwrap();
+        assert_eq!(elsewhere["totals"]["requests"], 0);
+
+        assert_eq!(respond(&db, &pages, "/status", now).status, 400);
+        assert_eq!(
+            respond(&db, &pages, "/stats?host=example.com&period=1w", now).status,
+            400
+        );
+        assert_eq!(respond(&db, &pages, "/favicon.ico", now).status, 404);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn answers_storage_errors_with_500() {
+        let path = std::env::temp_dir().join(format!("kirinox-broken-{}.db", std::process::id()));
+        let db = Db::new(DbOptions {
+            location: Location::Sqlite(path.clone()),
+            ..DbOptions::default()
+        })
+        .unwrap();
+        SqliteStorage::open(&path)
+            .unwrap()
+            .execute_batch("DROP TABLE retention_horizons;")
+            .unwrap();
+        let pages = Pages::default();
+        let now = DateTime::from_timestamp_millis(1_735_812_000_000).unwrap();
+        let stats = respond(&db, &pages, "/api/stats?host=example.com", now);
+        assert_eq!(stats.status, 500);
+        assert_eq!(
+            respond(&db, &pages, "/status?host=example.com", now).status,
+            500
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
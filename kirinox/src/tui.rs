@@ -0,0 +1,443 @@
+use std::{
+    io::Error,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use displayer::{Report, ReportPeriod, format_bytes, format_count, format_percent, percent};
+use persister::{Db, Filter};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Block, Paragraph, Row, Sparkline, Table, TableState, Tabs},
+};
+
+use crate::report::{ReportArgs, bar, load, truncate};
+
+/// Columns a full bar takes in the tables.
+const BAR_WIDTH: u16 = 16;
+
+const HELP: &str = "←/→ host · p period · tab list · ↑/↓ select · enter drill in · esc back · \
+    r reload · q quit";
+
+/// The list the cursor is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Pages,
+    Countries,
+}
+
+/// A row drilled into, narrowing everything shown to it.
+#[derive(Debug, Clone, PartialEq)]
+enum Drill {
+    /// the page and any path below it
+    Page(String),
+    Country(String),
+}
+
+/// What the dashboard shows and where its cursor is. Keys only change the selection and mark
+/// the report stale, the queries run in `run`.
+#[derive(Debug)]
+struct App {
+    hosts: Vec<String>,
+    host: usize,
+    period: ReportPeriod,
+    /// the filter of the command line, without its host
+    base: Filter,
+    drills: Vec<Drill>,
+    focus: Focus,
+    selected: usize,
+    report: Option<Report>,
+    /// why the last load failed, the report before it stays up
+    error: Option<&'static str>,
+    /// the report doesn't match the selection anymore
+    stale: bool,
+    follow: Option<Duration>,
+}
+
+impl App {
+    fn new(mut hosts: Vec<String>, args: &ReportArgs) -> App {
+        let mut base = args.filter.clone();
+        let host = match base.host.take() {
+            Some(host) => hosts
+                .iter()
+                .position(|known| *known == host)
+                .unwrap_or_else(|| {
+                    hosts.push(host);
+                    hosts.len() - 1
+                }),
+            None => 0,
+        };
+        App {
+            hosts,
+            host,
+            period: args.period,
+            base,
+            drills: Vec::new(),
+            focus: Focus::Pages,
+            selected: 0,
+            report: None,
+            error: None,
+            stale: true,
+            follow: args.follow.map(Duration::from_secs),
+        }
+    }
+
+    /// The command line filter narrowed to the host and whatever was drilled into.
+    fn filter(&self) -> Option<Filter> {
+        let host = self.hosts.get(self.host)?;
+        let mut filter = self.base.clone().host(host);
+        for drill in &self.drills {
+            filter = match drill {
+                Drill::Page(page) => filter.path_prefix(page),
+                Drill::Country(country) => filter.country(country),
+            };
+        }
+        Some(filter)
+    }
+
+    fn load(&mut self, db: &Db) {
+        let report = self
+            .filter()
+            .map(|filter| load(db, &filter, self.period, Utc::now()))
+            .transpose();
+        match report {
+            Ok(report) => {
+                self.report = report;
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err),
+        }
+        self.selected = self.selected.min(self.rows().len().saturating_sub(1));
+        self.stale = false;
+    }
+
+    /// The rows of the focused list.
+    fn rows(&self) -> &[(String, i32)] {
+        match (&self.report, self.focus) {
+            (Some(report), Focus::Pages) => &report.stats.pages,
+            (Some(report), Focus::Countries) => &report.stats.countries,
+            (None, _) => &[],
+        }
+    }
+
+    /// Starts over on another host or period, the drills of one host mean nothing on another.
+    fn reset(&mut self) {
+        self.drills.clear();
+        self.selected = 0;
+        self.stale = true;
+    }
+
+    /// Applies a key, `false` when it asks to quit.
+    fn handle(&mut self, key: KeyCode) -> bool {
+        let hosts = self.hosts.len().max(1);
+        match key {
+            KeyCode::Char('q') => return false,
+            KeyCode::Esc if self.drills.is_empty() => return false,
+            KeyCode::Esc | KeyCode::Backspace => {
+                self.drills.pop();
+                self.selected = 0;
+                self.stale = true;
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                self.host = (self.host + hosts - 1) % hosts;
+                self.reset();
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.host = (self.host + 1) % hosts;
+                self.reset();
+            }
+            KeyCode::Char('p') => {
+                let next = ReportPeriod::ALL
+                    .iter()
+                    .position(|period| *period == self.period);
+                let next = next.map_or(0, |index| (index + 1) % ReportPeriod::ALL.len());
+                self.period = ReportPeriod::ALL[next];
+                self.stale = true;
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Pages => Focus::Countries,
+                    Focus::Countries => Focus::Pages,
+                };
+                self.selected = 0;
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.rows().len().saturating_sub(1));
+            }
+            KeyCode::Enter => {
+                if let Some((label, _)) = self.rows().get(self.selected) {
+                    let drill = match self.focus {
+                        Focus::Pages => Drill::Page(label.clone()),
+                        Focus::Countries => Drill::Country(label.clone()),
+                    };
+                    self.drills.push(drill);
+                    self.selected = 0;
+                    self.stale = true;
+                }
+            }
+            KeyCode::Char('r') => self.stale = true,
+            _ => {}
+        }
+        true
+    }
+}
+
+/// A list of the report with shares of `total` and bars, the focused one shows the cursor.
+fn list<'a>(
+    title: &'a str,
+    rows: &'a [(String, i32)],
+    total: f64,
+    width: u16,
+    focused: bool,
+) -> Table<'a> {
+    let max = rows
+        .iter()
+        .map(|(_, count)| f64::from(*count))
+        .fold(0.0, f64::max);
+    let label = width.saturating_sub(BAR_WIDTH + 21) as usize;
+    let rows = rows.iter().map(|(name, count)| {
+        Row::new(vec![
+            truncate(name, label.max(1)),
+            format_count(*count),
+            format_percent(percent(f64::from(*count), total)),
+            bar(f64::from(*count), max, usize::from(BAR_WIDTH)),
+        ])
+    });
+    let block = Block::bordered().title(title);
+    let block = if focused {
+        block.border_style(Style::new().yellow())
+    } else {
+        block
+    };
+    Table::new(
+        rows,
+        [
+            Constraint::Min(1),
+            Constraint::Length(10),
+            Constraint::Length(6),
+            Constraint::Length(BAR_WIDTH),
+        ],
+    )
+    .block(block)
+    .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [header, overview, lists, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(6),
+        Constraint::Min(6),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [hosts, selection] =
+        Layout::horizontal([Constraint::Min(0), Constraint::Length(48)]).areas(header);
+    frame.render_widget(
+        Tabs::new(app.hosts.iter().map(String::as_str))
+            .select(app.host)
+            .highlight_style(Style::new().bold().reversed()),
+        hosts,
+    );
+    let mut scope = vec![app.period.label().to_string()];
+    scope.extend(app.drills.iter().map(|drill| match drill {
+        Drill::Page(page) => page.clone(),
+        Drill::Country(country) => country.clone(),
+    }));
+    if app.follow.is_some() {
+        scope.push(String::from("following"));
+    }
+    frame.render_widget(Line::from(scope.join(" › ")).right_aligned(), selection);
+    match app.error {
+        Some(err) => frame.render_widget(Line::from(err).red(), footer),
+        None => frame.render_widget(Line::from(HELP).dim(), footer),
+    }
+    let Some(report) = &app.report else {
+        frame.render_widget(Paragraph::new("no hosts recorded yet"), overview);
+        return;
+    };
+    draw_overview(frame, report, overview);
+    let [pages, countries] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(lists);
+    let stats = &report.stats;
+    for (focus, title, rows, total, area) in [
+        (
+            Focus::Pages,
+            "Top Pages",
+            &stats.pages,
+            stats.pageviews,
+            pages,
+        ),
+        (
+            Focus::Countries,
+            "Countries",
+            &stats.countries,
+            stats.total_requests,
+            countries,
+        ),
+    ] {
+        let focused = app.focus == focus;
+        let table = list(title, rows, f64::from(total), area.width, focused);
+        let mut state = TableState::default().with_selected(focused.then_some(app.selected));
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+}
+
+fn draw_overview(frame: &mut Frame, report: &Report, area: Rect) {
+    let [numbers, chart] =
+        Layout::horizontal([Constraint::Length(44), Constraint::Min(0)]).areas(area);
+    let stats = &report.stats;
+    let lines = vec![
+        Line::from(format!(
+            "Requests {:>10}   Pageviews {:>10}",
+            format_count(stats.total_requests),
+            format_count(stats.pageviews)
+        )),
+        Line::from(format!(
+            "Visitors {:>10}   Bot share {:>10}",
+            format_count(stats.unique_visitors),
+            format_percent(stats.bot_share())
+        )),
+        Line::from(format!(
+            "Sent     {:>10}   Errors    {:>10}",
+            format_bytes(stats.bytes_sent as f64),
+            format_percent(report.status.error_rate())
+        )),
+        Line::from(format!(
+            "Updated  {:>10}",
+            report.generated_at.format("%H:%M:%S")
+        )),
+    ];
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered()), numbers);
+    let requests: Vec<u64> = report
+        .timeseries
+        .iter()
+        .map(|point| point.requests.max(0) as u64)
+        .collect();
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title("Requests"))
+            .data(&requests),
+        chart,
+    );
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, db: &Db, app: &mut App) -> Result<(), Error> {
+    let mut loaded = Instant::now();
+    loop {
+        if app.stale || app.follow.is_some_and(|every| loaded.elapsed() >= every) {
+            app.load(db);
+            loaded = Instant::now();
+        }
+        terminal.draw(|frame| draw(frame, app))?;
+        // without following there's nothing to do until a key comes
+        let timeout = app.follow.map_or(Duration::MAX, |every| {
+            every.saturating_sub(loaded.elapsed())
+        });
+        if !event::poll(timeout)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && !app.handle(key.code)
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// The interactive dashboard, until `q` is pressed.
+pub fn run(args: &ReportArgs) -> Result<(), Error> {
+    let db = Db::new(args.config.db_options()).map_err(Error::other)?;
+    let hosts = db.get_hosts().map_err(Error::other)?;
+    let mut app = App::new(hosts, args);
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &db, &mut app);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use chrono::DateTime;
+    use persister::{Period, Stats};
+
+    fn app() -> App {
+        let args = ReportArgs {
+            period: ReportPeriod::Week,
+            filter: Filter::new()
+                .host("b.example")
+                .traffic(persister::Traffic::Humans),
+            tui: true,
+            follow: None,
+            config: Config::default(),
+        };
+        let mut app = App::new(
+            vec![String::from("a.example"), String::from("b.example")],
+            &args,
+        );
+        app.report = Some(Report {
+            host: String::from("b.example"),
+            period: ReportPeriod::Week,
+            stats: Stats {
+                pages: vec![(String::from("/"), 3), (String::from("/blog/"), 2)],
+                countries: vec![(String::from("Germany"), 4)],
+                ..Stats::default()
+            },
+            step: Period::Hour,
+            timeseries: Vec::new(),
+            changes: Default::default(),
+            status: Default::default(),
+            bandwidth: Default::default(),
+            generated_at: DateTime::from_timestamp_millis(0).unwrap(),
+        });
+        app.stale = false;
+        app
+    }
+
+    #[test]
+    fn starts_on_the_host_asked_for() {
+        let app = app();
+        assert_eq!(app.host, 1);
+        let filter = app.filter().unwrap();
+        assert_eq!(filter.host.as_deref(), Some("b.example"));
+        assert_eq!(filter.traffic, Some(persister::Traffic::Humans));
+    }
+
+    #[test]
+    fn drills_into_pages_and_countries() {
+        let mut app = app();
+        assert!(app.handle(KeyCode::Down));
+        assert!(app.handle(KeyCode::Down));
+        assert_eq!(app.selected, 1);
+        assert!(app.handle(KeyCode::Enter));
+        assert!(app.stale);
+        assert!(app.handle(KeyCode::Tab));
+        assert!(app.handle(KeyCode::Enter));
+        let filter = app.filter().unwrap();
+        assert_eq!(filter.path_prefix.as_deref(), Some("/blog/"));
+        assert_eq!(filter.country.as_deref(), Some("Germany"));
+        assert!(app.handle(KeyCode::Esc));
+        assert_eq!(app.filter().unwrap().country, None);
+        assert!(app.handle(KeyCode::Esc));
+        assert!(!app.handle(KeyCode::Esc));
+    }
+
+    #[test]
+    fn switching_hosts_drops_the_drills() {
+        let mut app = app();
+        app.handle(KeyCode::Enter);
+        app.handle(KeyCode::Right);
+        assert_eq!(app.host, 0);
+        assert!(app.drills.is_empty());
+        app.handle(KeyCode::Char('p'));
+        assert_eq!(app.period, ReportPeriod::Month);
+        assert!(!app.handle(KeyCode::Char('q')));
+    }
+}
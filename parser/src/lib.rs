@@ -1,11 +1,18 @@
 use flate2::read::GzDecoder;
 use std::{
-    fs::{self, File, remove_file}, io::{BufRead, BufReader, Error, Read, Seek, SeekFrom, copy}, num::{ParseFloatError, ParseIntError}, os, path::PathBuf
+    fs::{File, remove_file},
+    io::{BufRead, BufReader, Error, ErrorKind, Read, Seek, SeekFrom, copy},
+    net::IpAddr,
+    num::{ParseFloatError, ParseIntError},
+    path::{Path, PathBuf},
 };
-use tar::Archive;
 
 use chrono::DateTime;
 
+mod proxy;
+
+pub use proxy::TrustedProxies;
+
 #[derive(Debug)]
 pub struct LogFile {
     is_created: bool,
@@ -36,22 +43,33 @@ pub struct LogStruct<'a> {
     pub upstream_response_time: Option<f64>,
     pub http_refferer: Option<&'a str>,
     pub http_user_agent: &'a str,
+    pub http_x_forwarded_for: Option<&'a str>,
+    pub http_cf_connecting_ip: Option<&'a str>,
+    /// address of the proxy the request came through, set once `remote_addr` was replaced by
+    /// the real client address
+    pub proxy_addr: Option<&'a str>,
 }
 
 pub struct Parser {
     logs_path: PathBuf,
 }
 
+/// A line the search for the checkpoint has to understand, a broken one is an error rather
+/// than a guess.
+fn parse_line(line: &str) -> Result<LogStruct<'_>, Error> {
+    LogStruct::from_line(line).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
 impl Parser {
-    pub fn new(path: &PathBuf) -> Result<Parser, &'static str> {
+    pub fn new(path: &Path) -> Result<Parser, &'static str> {
         if !path.exists() || !path.is_dir() {
             return Err("logs path do not exist or the path is not a dir");
         }
         Ok(Parser {
-            logs_path: path.clone(),
+            logs_path: path.to_path_buf(),
         })
     }
-    fn find_number(&self, f: &PathBuf) -> i32 {
+    fn find_number(&self, f: &Path) -> i32 {
         let file_name = f.file_name().unwrap().to_str().unwrap();
         let splitted_line: Vec<&str> = file_name.split(".").collect();
         if file_name.ends_with("gz") {
@@ -64,45 +82,6 @@ impl Parser {
             .unwrap_or(0)
     }
 
-    fn find_end_and_begining_of_file(
-        &self,
-        opened_file: &mut File,
-        last_recorded_ts: i64,
-    ) -> Result<(Option<String>, Option<String>), Error> {
-        let first_line;
-        //let last_line;
-        let metadata = opened_file.metadata()?;
-        let file_len = metadata.len();
-        // small file read all of i
-        if file_len < 500 {
-            let mut file_contents = String::new();
-            opened_file.read_to_string(&mut file_contents).unwrap();
-            let mut splitted = file_contents.split('\n');
-            first_line = splitted.next().map(String::from);
-            let _ = splitted.next_back();
-            return Ok((None, None));
-            //last_line = splitted.next_back().map(String::from);
-        }
-        //} else {
-        //    let mut end_of_the_file = [0; 200];
-        //    let mut start_of_file = [0; 200];
-        //    opened_file.read(&mut start_of_file)?;
-        //
-        //    opened_file.seek(SeekFrom::End(0))?;
-        //    opened_file.read(&mut end_of_the_file).unwrap();
-        //
-        //    let eof_str = String::from_utf8_lossy(&end_of_the_file);
-        //    let sof_str = String::from_utf8_lossy(&start_of_file);
-        //    first_line = sof_str.split('\n').next().map(String::from);
-        //    let mut splitted_eof = eof_str.split('\n');
-        //    let _ = splitted_eof.next_back();
-        //    //last_line = splitted_eof.next_back().map(String::from);
-        //}
-        //let pos = self.find_exact_position(opened_file, file_len, last_recorded_ts);
-
-        Ok((None, None))
-    }
-
     fn search_small_file(
         &self,
         reader: &mut BufReader<File>,
@@ -110,41 +89,29 @@ impl Parser {
         last_recorded_ts: i64,
         is_created: bool,
     ) -> Result<FileRecordingInfo, Error> {
-        let mut first_line = String::new();
-        reader.read_line(&mut first_line)?;
-        let first_structured_line = LogStruct::from_line(&first_line).unwrap();
-        let mut first_dt = first_structured_line.dt;
-        for line in reader.lines() {
-            let next_line = line?;
-            let next_structured_line = LogStruct::from_line(&next_line).unwrap();
-            if (next_structured_line.dt >= last_recorded_ts)
-                && (last_recorded_ts >= first_structured_line.dt)
-            {
-                println!("FOUND IT in a small file");
+        // the first line the checkpoint may not cover, reading a stored line again only
+        // finds it stored while skipping one loses it
+        let mut start_from = 0;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? != 0 {
+            if parse_line(&line)?.dt >= last_recorded_ts {
                 return Ok(FileRecordingInfo {
                     is_recorded: false,
-                    start_from: reader.stream_position()?,
+                    start_from,
                     file_path: path,
-                    is_created: is_created,
+                    is_created,
                 });
             }
-            first_dt = next_structured_line.dt;
-        }
-        if first_dt <= last_recorded_ts {
-            return Ok(FileRecordingInfo {
-                is_recorded: true,
-                start_from: 0,
-                file_path: path,
-                is_created: is_created,
-            });
-        } else {
-            return Ok(FileRecordingInfo {
-                is_recorded: false,
-                start_from: 0,
-                file_path: path,
-                is_created: is_created,
-            });
+            start_from += line.len() as u64;
+            line.clear();
         }
+        // every line is older, or the log was just rotated and is empty
+        Ok(FileRecordingInfo {
+            is_recorded: true,
+            start_from: 0,
+            file_path: path,
+            is_created,
+        })
     }
 
     fn find_exact_position(
@@ -158,7 +125,12 @@ impl Parser {
         let mut reader = BufReader::new(opened_file);
         let file_len = metadata.len();
         if file_len < 500 {
-            return self.search_small_file(&mut reader, path.file_path, last_recorded_ts, path.is_created);
+            return self.search_small_file(
+                &mut reader,
+                path.file_path,
+                last_recorded_ts,
+                path.is_created,
+            );
         }
         let mut start = 0;
         let mut end = file_len;
@@ -172,7 +144,7 @@ impl Parser {
         let mut splitted_end = eof.split('\n');
         splitted_end.next_back();
         let last_line = splitted_end.next_back();
-        let within = self.is_log_within_file(&first_line, last_line, last_recorded_ts);
+        let within = self.is_log_within_file(&first_line, last_line, last_recorded_ts)?;
         if within == -1 {
             return Ok(FileRecordingInfo {
                 is_recorded: true,
@@ -196,30 +168,18 @@ impl Parser {
             // first line we can land in the middle of the line, so it would be incomplete log,
             // discard it;
             reader.read_line(&mut String::new())?;
+            let line_start = reader.stream_position()?;
             reader.read_line(&mut sof_str)?;
             reader.read_line(&mut next_line)?;
-            //reader.read_line(&mut next_line)?;
-
-            //let sof_str = String::from_utf8_lossy(&start_of_file);
-            //let mut splitted = sof_str.split('\n');
-            //splitted.next().unwrap();
-            //let new_start = splitted.next().map(String::from).unwrap();
-            println!("before");
-            println!("first: {}", sof_str);
-            println!("second: {}", next_line);
-            let first_parsed_line = LogStruct::from_line(&sof_str).unwrap();
-            let second_parsed_line = LogStruct::from_line(&next_line).unwrap();
-            println!(
-                "{} - {} - {}",
-                first_parsed_line.dt, last_recorded_ts, second_parsed_line.dt
-            );
+            let first_parsed_line = parse_line(&sof_str)?;
+            let second_parsed_line = parse_line(&next_line)?;
             if (second_parsed_line.dt >= last_recorded_ts)
                 && (last_recorded_ts >= first_parsed_line.dt)
             {
-                println!("FOUND IT");
+                // from the older line of the two, so the newer one is read too
                 return Ok(FileRecordingInfo {
                     is_recorded: false,
-                    start_from: reader.stream_position()?,
+                    start_from: line_start,
                     file_path: path.file_path,
                     is_created: path.is_created,
                 });
@@ -259,24 +219,22 @@ impl Parser {
         first_line: &str,
         last_line: Option<&str>,
         last_recorded_ts: i64,
-    ) -> i32 {
+    ) -> Result<i32, Error> {
         // 1 = log within this file
         // -1 = log is already recorded
         // 0 = log are older than this file
-        let first_parsed_line = LogStruct::from_line(first_line).unwrap();
+        let first_parsed_line = parse_line(first_line)?;
         let last_line = last_line.unwrap_or(first_line);
-        let last_parsed_line = LogStruct::from_line(&last_line).expect("second line failed");
-        println!(
-            "{} = {} = {}",
-            first_parsed_line.dt, last_recorded_ts, last_parsed_line.dt
-        );
-        if (first_parsed_line.dt <= last_recorded_ts && last_parsed_line.dt >= last_recorded_ts) {
-            return 1;
-        } else if last_parsed_line.dt < last_recorded_ts {
-            return -1;
-        } else {
-            return 0;
-        }
+        let last_parsed_line = parse_line(last_line)?;
+        Ok(
+            if first_parsed_line.dt <= last_recorded_ts && last_parsed_line.dt >= last_recorded_ts {
+                1
+            } else if last_parsed_line.dt < last_recorded_ts {
+                -1
+            } else {
+                0
+            },
+        )
     }
 
     fn get_unrecorded_files(
@@ -286,9 +244,7 @@ impl Parser {
     ) -> Result<Vec<FileRecordingInfo>, Error> {
         let mut unrecorded_files: Vec<FileRecordingInfo> = vec![];
         for f in files {
-            println!("file: {:?}", f);
             let file_recording_info = self.find_exact_position(f, last_recorded_ts)?;
-            println!("file_info: {:?}", file_recording_info);
             if !file_recording_info.is_recorded {
                 unrecorded_files.push(file_recording_info);
             }
@@ -296,24 +252,31 @@ impl Parser {
         Ok(unrecorded_files)
     }
 
-    pub fn find_files(&self, last_recorded_ts: Option<i64>) -> Vec<FileRecordingInfo> {
+    pub fn find_files(
+        &self,
+        last_recorded_ts: Option<i64>,
+    ) -> Result<Vec<FileRecordingInfo>, Error> {
         // [
         // "nginx-logs/paulefou/access.log.2.gz",
         // "nginx-logs/paulefou/access.log.1",
         // "nginx-logs/paulefou/access.log"
         // ]
-        let mut files_list: Vec<PathBuf> = self
-            .logs_path
-            .read_dir()
-            .unwrap()
-            .map(|f| f.unwrap().path())
-            .filter(|f| f.file_name().unwrap().to_str().unwrap().contains("access"))
-            .collect();
-        files_list.sort_by(|a, b| self.find_number(a).cmp(&self.find_number(b)));
+        let mut files_list = vec![];
+        for entry in self.logs_path.read_dir()? {
+            let path = entry?.path();
+            if path
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .contains("access")
+            {
+                files_list.push(path);
+            }
+        }
+        files_list.sort_by_key(|f| self.find_number(f));
         let last_recorded_ts = last_recorded_ts.unwrap_or(0);
-        return self
-            .get_unrecorded_files(&files_list, last_recorded_ts)
-            .unwrap();
+        self.get_unrecorded_files(&files_list, last_recorded_ts)
     }
     pub fn clean_up(&self, files: Vec<FileRecordingInfo>) -> Result<(), Error> {
         for file in files {
@@ -325,14 +288,51 @@ impl Parser {
     }
 }
 
+fn optional_field(field: &str) -> Option<&str> {
+    let field = field.trim();
+    if field.is_empty() || field == "-" {
+        return None;
+    }
+    Some(field)
+}
+
 impl<'a> LogStruct<'a> {
+    /// Parses a tab separated line. Two trailing fields are optional, so both formats work:
+    ///
+    /// `$remote_addr $remote_user $time_iso8601 $request_method $scheme $http_host $request_uri
+    /// $server_protocol $status $body_bytes_sent $request_time $upstream_response_time
+    /// $http_referer $http_user_agent [$http_x_forwarded_for $http_cf_connecting_ip]`
     pub fn from_line<'b>(line: &'b str) -> Result<LogStruct<'b>, &'static str> {
         let splitted_line: Vec<&str> = line.split("\t").collect();
-        if let [remote_addr, remote_user, time_iso8601, method, scheme, http_host, request_uri, server_protocol, status, body_bytes_sent, request_time, upstream_response_time, http_refferer, http_user_agent] =
-            splitted_line[..]
+        if let [
+            remote_addr,
+            remote_user,
+            time_iso8601,
+            method,
+            scheme,
+            http_host,
+            request_uri,
+            server_protocol,
+            status,
+            body_bytes_sent,
+            request_time,
+            upstream_response_time,
+            http_refferer,
+            http_user_agent,
+            ref forwarded @ ..,
+        ] = splitted_line[..]
         {
+            let (http_x_forwarded_for, http_cf_connecting_ip) = match forwarded {
+                [] => (None, None),
+                [forwarded_for] => (optional_field(forwarded_for), None),
+                [forwarded_for, cf_connecting_ip] => (
+                    optional_field(forwarded_for),
+                    optional_field(cf_connecting_ip),
+                ),
+                _ => return Err("could not parse the line"),
+            };
             let dt = DateTime::parse_from_str(time_iso8601, "%Y-%m-%dT%H:%M:%S%z");
-            if !dt.is_ok() {
+            if dt.is_err() {
                 return Err("could not parse date");
             }
             let dt = dt.unwrap().timestamp_millis();
@@ -357,7 +357,6 @@ impl<'a> LogStruct<'a> {
             }
             let request_time = request_time.unwrap();
 
-            println!("{}", upstream_response_time);
             let upstream_response_time: Result<f64, ParseFloatError> =
                 upstream_response_time.parse();
             let upstream_response_time = upstream_response_time.ok();
@@ -387,13 +386,33 @@ impl<'a> LogStruct<'a> {
                 upstream_response_time,
                 http_refferer: parsed_http_referer,
                 http_user_agent,
+                http_x_forwarded_for,
+                http_cf_connecting_ip,
+                proxy_addr: None,
             })
         } else {
-            println!(
-                "errors here splitted line collected: \n\n{:?}\n\n\n\n\n\n",
-                splitted_line
-            );
-            return Err("could not parse the line");
+            Err("could not parse the line")
+        }
+    }
+
+    /// Replaces `remote_addr` with the real client address when the request came through one
+    /// of the trusted proxies. `CF-Connecting-IP` wins over `X-Forwarded-For` since Cloudflare
+    /// sets it to the single address it saw, the latter is used otherwise.
+    pub fn resolve_client_addr(&mut self, trusted_proxies: &TrustedProxies) {
+        if !trusted_proxies.contains(self.remote_addr) {
+            return;
+        }
+        let client_addr = self
+            .http_cf_connecting_ip
+            .filter(|addr| addr.parse::<IpAddr>().is_ok())
+            .or_else(|| {
+                self.http_x_forwarded_for.and_then(|forwarded_for| {
+                    trusted_proxies.client_from_forwarded_for(forwarded_for)
+                })
+            });
+        if let Some(client_addr) = client_addr {
+            self.proxy_addr = Some(self.remote_addr);
+            self.remote_addr = client_addr;
         }
     }
 }
@@ -404,4 +423,38 @@ mod tests {
 
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn resolves_client_behind_trusted_proxy() {
+        let line = "173.245.48.10\t-\t2025-01-01T10:00:00+00:00\tGET\thttps\texample.com\t/\tHTTP/2.0\t200\t512\t0.010\t0.009\t-\tMozilla/5.0\t198.51.100.4, 173.245.48.10\t198.51.100.4\n";
+        let trusted_proxies = TrustedProxies::new(&["173.245.48.0/20"]).unwrap();
+        let mut log_struct = LogStruct::from_line(line).unwrap();
+        log_struct.resolve_client_addr(&trusted_proxies);
+        assert_eq!(log_struct.remote_addr, "198.51.100.4");
+        assert_eq!(log_struct.proxy_addr, Some("173.245.48.10"));
+
+        // without the forwarded fields nothing can be resolved
+        let line = "173.245.48.10\t-\t2025-01-01T10:00:00+00:00\tGET\thttps\texample.com\t/\tHTTP/2.0\t200\t512\t0.010\t-\t-\tMozilla/5.0";
+        let mut log_struct = LogStruct::from_line(line).unwrap();
+        log_struct.resolve_client_addr(&trusted_proxies);
+        assert_eq!(log_struct.remote_addr, "173.245.48.10");
+        assert_eq!(log_struct.upstream_response_time, None);
+    }
+
+    #[test]
+    fn reports_broken_lines_while_looking_for_the_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("kirinox-parser-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let line = "203.0.113.7\t-\t2025-01-01T10:00:00+00:00\tGET\thttps\texample.com\t/\tHTTP/2.0\t200\t512\t0.010\t-\t-\tMozilla/5.0\n";
+        std::fs::write(dir.join("access.log"), "").unwrap();
+        std::fs::write(dir.join("access.log.1"), format!("{line}not a log line\n")).unwrap();
+        let parser = Parser::new(&dir).unwrap();
+        let err = parser.find_files(Some(1_735_725_601_000)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        // a freshly rotated, empty log has nothing to read
+        std::fs::remove_file(dir.join("access.log.1")).unwrap();
+        assert!(parser.find_files(Some(0)).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,131 @@
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy)]
+struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    fn parse(cidr: &str) -> Result<Cidr, &'static str> {
+        let (addr, prefix) = match cidr.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (cidr, None),
+        };
+        let network: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| "could not parse the proxy address")?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse::<u8>()
+                .map_err(|_| "could not parse the proxy prefix length")?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err("proxy prefix length is too long");
+        }
+        Ok(Cidr { network, prefix })
+    }
+
+    fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.network, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(*addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(*addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// List of proxies (load balancers, CDN edges) whose `remote_addr` should not be taken as the
+/// client address. Entries are CIDR ranges (`173.245.48.0/20`) or single addresses.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<Cidr>,
+}
+
+impl TrustedProxies {
+    pub fn new<S: AsRef<str>>(ranges: &[S]) -> Result<TrustedProxies, &'static str> {
+        let ranges = ranges
+            .iter()
+            .map(|range| Cidr::parse(range.as_ref()))
+            .collect::<Result<Vec<Cidr>, &'static str>>()?;
+        Ok(TrustedProxies { ranges })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, addr: &str) -> bool {
+        match addr.trim().parse::<IpAddr>() {
+            Ok(addr) => self.ranges.iter().any(|range| range.contains(&addr)),
+            Err(_) => false,
+        }
+    }
+
+    /// Walks `X-Forwarded-For` from the right (the hop closest to us) and returns the first
+    /// address that is not one of our proxies. Everything left of it is client supplied and
+    /// can't be trusted. If every hop is a proxy the left-most one is the best we have.
+    pub fn client_from_forwarded_for<'a>(&self, forwarded_for: &'a str) -> Option<&'a str> {
+        let hops: Vec<&str> = forwarded_for
+            .split(',')
+            .map(str::trim)
+            .filter(|hop| hop.parse::<IpAddr>().is_ok())
+            .collect();
+        hops.iter()
+            .rev()
+            .find(|hop| !self.contains(hop))
+            .or(hops.first())
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_ranges() {
+        let proxies =
+            TrustedProxies::new(&["173.245.48.0/20", "10.0.0.1", "2400:cb00::/32"]).unwrap();
+        assert!(proxies.contains("173.245.63.255"));
+        assert!(!proxies.contains("173.245.64.0"));
+        assert!(proxies.contains("10.0.0.1"));
+        assert!(!proxies.contains("10.0.0.2"));
+        assert!(proxies.contains("2400:cb00:1::1"));
+        assert!(!proxies.contains("not an ip"));
+    }
+
+    #[test]
+    fn rejects_bad_ranges() {
+        assert!(TrustedProxies::new(&["10.0.0.0/33"]).is_err());
+        assert!(TrustedProxies::new(&["10.0.0/8"]).is_err());
+    }
+
+    #[test]
+    fn picks_right_most_untrusted_hop() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8"]).unwrap();
+        assert_eq!(
+            proxies.client_from_forwarded_for("1.1.1.1, 203.0.113.7, 10.0.0.3"),
+            Some("203.0.113.7")
+        );
+        assert_eq!(
+            proxies.client_from_forwarded_for("10.0.0.5, 10.0.0.3"),
+            Some("10.0.0.5")
+        );
+        assert_eq!(proxies.client_from_forwarded_for("-"), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Ingestion throughput, row by row against batched transactions.
+//!
+//! `cargo bench -p persister --bench ingest`, set `KIRINOX_BENCH_LINES` to change the number
+//! of lines (default 50000).
+
+use std::{env, fs, time::Instant};
+
+use enricher::{EnrichedLog, RequestKind};
+use parser::LogStruct;
+use persister::{Db, DbOptions, Location, Position};
+
+fn lines(count: usize, offset: usize) -> Vec<String> {
+    (offset..offset + count)
+        .map(|i| {
+            format!(
+                "203.0.{}.{}\t-\t2025-01-{:02}T{:02}:{:02}:{:02}+00:00\tGET\thttps\texample.com\t/page/{}\tHTTP/2.0\t200\t{}\t0.0{}\t0.0{}\thttps://news.example/\tMozilla/5.0 (X11; Linux x86_64) Firefox/{}.0",
+                (i / 256) % 256,
+                i % 256,
+                1 + (i / 86_400) % 28,
+                (i / 3600) % 24,
+                (i / 60) % 60,
+                i % 60,
+                i % 50,
+                100 + i % 5000,
+                i % 9,
+                i % 7,
+                100 + i % 30,
+            )
+        })
+        .collect()
+}
+
+fn enriched(i: usize) -> EnrichedLog {
+    EnrichedLog {
+        is_bot: i.is_multiple_of(5),
+        country: String::from("Germany"),
+        city: String::from("Berlin"),
+        is_vpn: false,
+        kind: if i.is_multiple_of(3) {
+            RequestKind::Asset
+        } else {
+            RequestKind::Pageview
+        },
+    }
+}
+
+fn main() {
+    let count: usize = env::var("KIRINOX_BENCH_LINES")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(50_000);
+    let dir = env::temp_dir().join(format!("kirinox-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let db = Db::new(DbOptions {
+        location: Location::Sqlite(dir.join("krx.db")),
+        ..DbOptions::default()
+    })
+    .unwrap();
+
+    // row by row gets a tenth of the lines, it's slow enough to make the point
+    let single = lines(count / 10, 0);
+    let start = Instant::now();
+    // the lines are one log file, each at its own offset
+    let position = |offset| Position {
+        source: "bench",
+        offset,
+    };
+    for (i, line) in single.iter().enumerate() {
+        db.insert_record(
+            &LogStruct::from_line(line).unwrap(),
+            &enriched(i),
+            position(i as u64),
+        )
+        .unwrap();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "insert_record: {} lines in {elapsed:.2}s, {:.0} lines/sec",
+        single.len(),
+        single.len() as f64 / elapsed
+    );
+
+    let batched = lines(count, count / 10);
+    let start = Instant::now();
+    for (n, chunk) in batched.chunks(db.batch_size()).enumerate() {
+        let first = single.len() + n * db.batch_size();
+        let records: Vec<(LogStruct, EnrichedLog)> = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, line)| (LogStruct::from_line(line).unwrap(), enriched(i)))
+            .collect();
+        db.insert_batch(
+            records
+                .iter()
+                .enumerate()
+                .map(|(i, (log_struct, enriched))| {
+                    (log_struct, enriched, position((first + i) as u64))
+                }),
+        )
+        .unwrap();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "insert_batch ({}): {} lines in {elapsed:.2}s, {:.0} lines/sec",
+        db.batch_size(),
+        batched.len(),
+        batched.len() as f64 / elapsed
+    );
+
+    drop(db);
+    fs::remove_dir_all(&dir).unwrap();
+}
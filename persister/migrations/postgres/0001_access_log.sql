@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS access_log (
+    id BIGSERIAL PRIMARY KEY,
+
+    -- request info
+    remote_addr TEXT NOT NULL,
+    remote_user TEXT,
+    timestamp BIGINT NOT NULL,
+    method TEXT NOT NULL,
+    scheme TEXT NOT NULL,
+    http_host TEXT NOT NULL,
+    request_uri TEXT NOT NULL,
+    server_protocol TEXT NOT NULL,
+    status BIGINT NOT NULL,
+    body_bytes_sent BIGINT NOT NULL,
+    request_time DOUBLE PRECISION NOT NULL,
+    upstream_response_time DOUBLE PRECISION,
+
+    http_referer TEXT,
+    http_user_agent TEXT,
+
+    -- enrichment
+    is_bot BIGINT NOT NULL CHECK (is_bot IN (0,1)),
+    country TEXT,
+    city TEXT,
+    is_vpn BIGINT NOT NULL CHECK (is_vpn IN (0,1)),
+
+    created_at TEXT DEFAULT (now()::text)
+);
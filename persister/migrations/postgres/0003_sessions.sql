@@ -0,0 +1,28 @@
+CREATE TABLE visitors (
+    http_host TEXT NOT NULL,
+    visitor_hash TEXT NOT NULL,
+    first_seen BIGINT NOT NULL,
+    last_seen BIGINT NOT NULL,
+    pageviews BIGINT NOT NULL,
+    sessions BIGINT NOT NULL,
+    is_bot BIGINT NOT NULL CHECK (is_bot IN (0,1)),
+    PRIMARY KEY (http_host, visitor_hash)
+);
+
+CREATE TABLE sessions (
+    id BIGSERIAL PRIMARY KEY,
+    http_host TEXT NOT NULL,
+    visitor_hash TEXT NOT NULL,
+    started_at BIGINT NOT NULL,
+    ended_at BIGINT NOT NULL,
+    entry_page TEXT NOT NULL,
+    exit_page TEXT NOT NULL,
+    page_count BIGINT NOT NULL,
+    -- milliseconds between the first and the last request
+    duration BIGINT NOT NULL,
+    is_bounce BIGINT NOT NULL CHECK (is_bounce IN (0,1)),
+    is_bot BIGINT NOT NULL CHECK (is_bot IN (0,1))
+);
+
+CREATE INDEX sessions_visitor ON sessions (http_host, visitor_hash, ended_at);
+CREATE INDEX sessions_started ON sessions (http_host, started_at);
@@ -0,0 +1,91 @@
+-- per host totals for every hour and every day, kept up to date by the ingestion so long
+-- ranges don't have to scan access_log; period is 'hour' or 'day' and bucket the unix
+-- milliseconds (UTC) it starts at
+CREATE TABLE rollups (
+    http_host TEXT NOT NULL,
+    period TEXT NOT NULL,
+    bucket BIGINT NOT NULL,
+    requests BIGINT NOT NULL,
+    pageviews BIGINT NOT NULL,
+    human_requests BIGINT NOT NULL,
+    bot_requests BIGINT NOT NULL,
+    vpn_requests BIGINT NOT NULL,
+    bytes_sent BIGINT NOT NULL,
+    status_1xx BIGINT NOT NULL,
+    status_2xx BIGINT NOT NULL,
+    status_3xx BIGINT NOT NULL,
+    status_4xx BIGINT NOT NULL,
+    status_5xx BIGINT NOT NULL,
+    -- seconds, divided by requests for the average
+    request_time_sum DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (http_host, period, bucket)
+);
+
+-- visitors can't be added up across buckets, so every bucket keeps who it saw; rows from
+-- before visitor hashes are hashed first, see `hash_missing_visitors`
+CREATE TABLE rollup_visitors (
+    http_host TEXT NOT NULL,
+    period TEXT NOT NULL,
+    bucket BIGINT NOT NULL,
+    visitor TEXT NOT NULL,
+    PRIMARY KEY (http_host, period, bucket, visitor)
+);
+
+-- hits per page, country, city and referrer, the top lists of longer ranges are summed from
+-- these
+CREATE TABLE rollup_dimensions (
+    http_host TEXT NOT NULL,
+    period TEXT NOT NULL,
+    bucket BIGINT NOT NULL,
+    dimension TEXT NOT NULL,
+    value TEXT NOT NULL,
+    hits BIGINT NOT NULL,
+    PRIMARY KEY (http_host, period, bucket, dimension, value)
+);
+
+WITH periods AS (SELECT 'hour' AS period, 3600000 AS length UNION ALL SELECT 'day', 86400000)
+INSERT INTO rollups (
+    http_host, period, bucket, requests, pageviews, human_requests, bot_requests, vpn_requests,
+    bytes_sent, status_1xx, status_2xx, status_3xx, status_4xx, status_5xx, request_time_sum
+)
+SELECT
+    http_host,
+    periods.period,
+    timestamp - timestamp % periods.length,
+    count(*),
+    sum(CASE WHEN request_kind = 'pageview' THEN 1 ELSE 0 END),
+    sum(1 - is_bot),
+    sum(is_bot),
+    sum(is_vpn),
+    sum(body_bytes_sent),
+    sum(CASE WHEN status / 100 = 1 THEN 1 ELSE 0 END),
+    sum(CASE WHEN status / 100 = 2 THEN 1 ELSE 0 END),
+    sum(CASE WHEN status / 100 = 3 THEN 1 ELSE 0 END),
+    sum(CASE WHEN status / 100 = 4 THEN 1 ELSE 0 END),
+    sum(CASE WHEN status / 100 = 5 THEN 1 ELSE 0 END),
+    sum(request_time)
+FROM access_log, periods
+GROUP BY http_host, periods.period, timestamp - timestamp % periods.length;
+
+WITH periods AS (SELECT 'hour' AS period, 3600000 AS length UNION ALL SELECT 'day', 86400000)
+INSERT INTO rollup_visitors (http_host, period, bucket, visitor)
+SELECT DISTINCT http_host, periods.period, timestamp - timestamp % periods.length, visitor_hash
+FROM access_log, periods;
+
+WITH
+    periods AS (SELECT 'hour' AS period, 3600000 AS length UNION ALL SELECT 'day', 86400000),
+    dimensions AS (
+        SELECT http_host, timestamp, 'page' AS dimension, request_uri AS value
+        FROM access_log WHERE request_kind = 'pageview'
+        UNION ALL
+        SELECT http_host, timestamp, 'country', country FROM access_log
+        UNION ALL
+        SELECT http_host, timestamp, 'city', city FROM access_log
+        UNION ALL
+        SELECT http_host, timestamp, 'referrer', http_referer FROM access_log
+    )
+INSERT INTO rollup_dimensions (http_host, period, bucket, dimension, value, hits)
+SELECT http_host, periods.period, timestamp - timestamp % periods.length, dimension, value, count(*)
+FROM dimensions, periods
+WHERE value IS NOT NULL AND value != ''
+GROUP BY http_host, periods.period, timestamp - timestamp % periods.length, dimension, value;
@@ -0,0 +1,3 @@
+-- visitor counts of days imported from other tools, which only know how many there were and
+-- not who
+ALTER TABLE rollups ADD COLUMN imported_visitors BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,104 @@
+-- fixed log-scale latency buckets, ten per decade from 1 ms to 100 s in seconds; a time
+-- falls in the bucket with lower_bound <= time < upper_bound
+CREATE TABLE latency_buckets (
+    latency_bucket BIGINT PRIMARY KEY,
+    lower_bound DOUBLE PRECISION NOT NULL,
+    upper_bound DOUBLE PRECISION NOT NULL
+);
+
+INSERT INTO latency_buckets (latency_bucket, lower_bound, upper_bound) VALUES
+    (0, 0.0, 0.001),
+    (1, 0.001, 0.00125),
+    (2, 0.00125, 0.0016),
+    (3, 0.0016, 0.002),
+    (4, 0.002, 0.0025),
+    (5, 0.0025, 0.0032),
+    (6, 0.0032, 0.004),
+    (7, 0.004, 0.005),
+    (8, 0.005, 0.0063),
+    (9, 0.0063, 0.008),
+    (10, 0.008, 0.01),
+    (11, 0.01, 0.0125),
+    (12, 0.0125, 0.016),
+    (13, 0.016, 0.02),
+    (14, 0.02, 0.025),
+    (15, 0.025, 0.032),
+    (16, 0.032, 0.04),
+    (17, 0.04, 0.05),
+    (18, 0.05, 0.063),
+    (19, 0.063, 0.08),
+    (20, 0.08, 0.1),
+    (21, 0.1, 0.125),
+    (22, 0.125, 0.16),
+    (23, 0.16, 0.2),
+    (24, 0.2, 0.25),
+    (25, 0.25, 0.32),
+    (26, 0.32, 0.4),
+    (27, 0.4, 0.5),
+    (28, 0.5, 0.63),
+    (29, 0.63, 0.8),
+    (30, 0.8, 1.0),
+    (31, 1.0, 1.25),
+    (32, 1.25, 1.6),
+    (33, 1.6, 2.0),
+    (34, 2.0, 2.5),
+    (35, 2.5, 3.2),
+    (36, 3.2, 4.0),
+    (37, 4.0, 5.0),
+    (38, 5.0, 6.3),
+    (39, 6.3, 8.0),
+    (40, 8.0, 10.0),
+    (41, 10.0, 12.5),
+    (42, 12.5, 16.0),
+    (43, 16.0, 20.0),
+    (44, 20.0, 25.0),
+    (45, 25.0, 32.0),
+    (46, 32.0, 40.0),
+    (47, 40.0, 50.0),
+    (48, 50.0, 63.0),
+    (49, 63.0, 80.0),
+    (50, 80.0, 100.0),
+    (51, 100.0, 1000000000.0);
+
+-- request and upstream time histograms per path (without the query string) of every hour
+-- and every day, metric is 'request' or 'upstream'; percentiles of long ranges are read from
+-- the summed histograms
+CREATE TABLE rollup_latency (
+    http_host TEXT NOT NULL,
+    period TEXT NOT NULL,
+    bucket BIGINT NOT NULL,
+    metric TEXT NOT NULL,
+    path TEXT NOT NULL,
+    latency_bucket BIGINT NOT NULL,
+    hits BIGINT NOT NULL,
+    PRIMARY KEY (http_host, period, bucket, metric, path, latency_bucket)
+);
+
+WITH
+    periods AS (SELECT 'hour' AS period, 3600000 AS length UNION ALL SELECT 'day', 86400000),
+    times AS (
+        SELECT http_host, timestamp, request_uri, 'request' AS metric, request_time AS seconds
+        FROM access_log
+        UNION ALL
+        SELECT http_host, timestamp, request_uri, 'upstream', upstream_response_time
+        FROM access_log WHERE upstream_response_time IS NOT NULL
+    )
+INSERT INTO rollup_latency (http_host, period, bucket, metric, path, latency_bucket, hits)
+SELECT
+    http_host,
+    periods.period,
+    timestamp - timestamp % periods.length,
+    metric,
+    split_part(request_uri, '?', 1),
+    latency_buckets.latency_bucket,
+    count(*)
+FROM times
+CROSS JOIN periods
+JOIN latency_buckets ON seconds >= lower_bound AND seconds < upper_bound
+GROUP BY
+    http_host,
+    periods.period,
+    timestamp - timestamp % periods.length,
+    metric,
+    split_part(request_uri, '?', 1),
+    latency_buckets.latency_bucket;
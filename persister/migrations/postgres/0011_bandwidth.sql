@@ -0,0 +1,120 @@
+-- bytes sent per dimension value next to the hits, and two more dimensions: 'path', every
+-- request by path without the query string, and 'bot', bot requests by the crawler or tool
+-- their user agent names
+ALTER TABLE rollup_dimensions ADD COLUMN bytes BIGINT NOT NULL DEFAULT 0;
+
+-- only as far back as access_log still has the requests
+UPDATE rollup_dimensions SET bytes = (
+    SELECT coalesce(sum(body_bytes_sent), 0) FROM access_log
+    WHERE access_log.http_host = rollup_dimensions.http_host
+        AND access_log.timestamp >= rollup_dimensions.bucket
+        AND access_log.timestamp < rollup_dimensions.bucket
+            + CASE rollup_dimensions.period WHEN 'hour' THEN 3600000 ELSE 86400000 END
+        AND CASE rollup_dimensions.dimension
+            WHEN 'page' THEN request_uri
+            WHEN 'country' THEN country
+            WHEN 'city' THEN city
+            WHEN 'referrer' THEN http_referer
+        END = rollup_dimensions.value
+        AND (rollup_dimensions.dimension != 'page' OR request_kind = 'pageview')
+);
+
+-- the enricher's bot user agents at the time, the first one a user agent contains names it;
+-- ingestion names them in code from here on
+CREATE TABLE bot_user_agents (
+    priority BIGINT PRIMARY KEY,
+    pattern TEXT NOT NULL
+);
+
+INSERT INTO bot_user_agents (priority, pattern) VALUES
+    (0, 'googlebot'),
+    (1, 'bingbot'),
+    (2, 'slurp'),
+    (3, 'duckduckbot'),
+    (4, 'baiduspider'),
+    (5, 'yandexbot'),
+    (6, 'applebot'),
+    (7, 'facebookexternalhit'),
+    (8, 'facebot'),
+    (9, 'twitterbot'),
+    (10, 'linkedinbot'),
+    (11, 'ahrefsbot'),
+    (12, 'semrushbot'),
+    (13, 'mj12bot'),
+    (14, 'dotbot'),
+    (15, 'rogerbot'),
+    (16, 'seokicks'),
+    (17, 'screaming frog'),
+    (18, 'petalbot'),
+    (19, 'ccbot'),
+    (20, 'censys'),
+    (21, 'shodan'),
+    (22, 'zgrab'),
+    (23, 'nmap'),
+    (24, 'masscan'),
+    (25, 'python-requests'),
+    (26, 'curl'),
+    (27, 'wget'),
+    (28, 'httpclient'),
+    (29, 'go-http-client'),
+    (30, 'java/'),
+    (31, 'libwww-perl'),
+    (32, 'scrapy'),
+    (33, 'axios'),
+    (34, 'node-fetch'),
+    (35, 'okhttp'),
+    (36, 'postmanruntime'),
+    (37, 'headlesschrome'),
+    (38, 'phantomjs'),
+    (39, 'puppeteer'),
+    (40, 'playwright'),
+    (41, 'selenium'),
+    (42, 'chrome-lighthouse'),
+    (43, 'uptimerobot'),
+    (44, 'statuscake'),
+    (45, 'pingdom'),
+    (46, 'newrelicpinger'),
+    (47, 'datadog'),
+    (48, 'elastic uptime'),
+    (49, 'monitoring'),
+    (50, 'bot'),
+    (51, 'crawler'),
+    (52, 'spider'),
+    (53, 'scanner');
+
+WITH
+    periods AS (SELECT 'hour' AS period, 3600000 AS length UNION ALL SELECT 'day', 86400000),
+    dimensions AS (
+        SELECT http_host, timestamp, 'path' AS dimension, split_part(request_uri, '?', 1) AS value, body_bytes_sent
+        FROM access_log
+        UNION ALL
+        SELECT
+            http_host,
+            timestamp,
+            'bot',
+            coalesce(
+                (
+                    SELECT pattern FROM bot_user_agents
+                    WHERE lower(coalesce(http_user_agent, '')) LIKE '%' || pattern || '%'
+                    ORDER BY priority
+                    LIMIT 1
+                ),
+                'other'
+            ),
+            body_bytes_sent
+        FROM access_log WHERE is_bot = 1
+    )
+INSERT INTO rollup_dimensions (http_host, period, bucket, dimension, value, hits, bytes)
+SELECT
+    http_host,
+    periods.period,
+    timestamp - timestamp % periods.length,
+    dimension,
+    value,
+    count(*),
+    sum(body_bytes_sent)
+FROM dimensions, periods
+WHERE value != ''
+GROUP BY http_host, periods.period, timestamp - timestamp % periods.length, dimension, value;
+
+DROP TABLE bot_user_agents;
@@ -0,0 +1,29 @@
+CREATE TABLE IF NOT EXISTS access_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+
+    -- request info
+    remote_addr TEXT NOT NULL,
+    remote_user TEXT,
+    timestamp INTEGER NOT NULL,
+    method TEXT NOT NULL,
+    scheme TEXT NOT NULL,
+    http_host TEXT NOT NULL,
+    request_uri TEXT NOT NULL,
+    server_protocol TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    body_bytes_sent INTEGER NOT NULL,
+    request_time REAL NOT NULL,
+    upstream_response_time REAL,
+
+    http_referer TEXT,
+    http_user_agent TEXT,
+
+    -- enrichment
+    is_bot INTEGER NOT NULL CHECK (is_bot IN (0,1)),
+    country TEXT,
+    city TEXT,
+    is_vpn INTEGER NOT NULL CHECK (is_vpn IN (0,1)),
+
+    -- useful indexes
+    created_at TEXT DEFAULT (datetime('now'))
+);
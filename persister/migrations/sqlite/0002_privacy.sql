@@ -0,0 +1,7 @@
+-- hash of address and user agent, salted daily in privacy mode
+ALTER TABLE access_log ADD COLUMN visitor_hash TEXT;
+
+CREATE TABLE daily_salts (
+    day INTEGER PRIMARY KEY,
+    salt BLOB NOT NULL
+);
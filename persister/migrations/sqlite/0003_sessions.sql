@@ -0,0 +1,28 @@
+CREATE TABLE visitors (
+    http_host TEXT NOT NULL,
+    visitor_hash TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    pageviews INTEGER NOT NULL,
+    sessions INTEGER NOT NULL,
+    is_bot INTEGER NOT NULL CHECK (is_bot IN (0,1)),
+    PRIMARY KEY (http_host, visitor_hash)
+);
+
+CREATE TABLE sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    http_host TEXT NOT NULL,
+    visitor_hash TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER NOT NULL,
+    entry_page TEXT NOT NULL,
+    exit_page TEXT NOT NULL,
+    page_count INTEGER NOT NULL,
+    -- milliseconds between the first and the last request
+    duration INTEGER NOT NULL,
+    is_bounce INTEGER NOT NULL CHECK (is_bounce IN (0,1)),
+    is_bot INTEGER NOT NULL CHECK (is_bot IN (0,1))
+);
+
+CREATE INDEX sessions_visitor ON sessions (http_host, visitor_hash, ended_at);
+CREATE INDEX sessions_started ON sessions (http_host, started_at);
@@ -0,0 +1,10 @@
+ALTER TABLE access_log ADD COLUMN request_kind TEXT NOT NULL DEFAULT 'other'
+    CHECK (request_kind IN ('pageview', 'asset', 'api', 'feed', 'other'));
+
+-- rows stored before classification existed: successful GETs of paths without a dot are the
+-- closest we can get to the default rules in plain SQL
+UPDATE access_log SET request_kind = 'pageview'
+WHERE method = 'GET'
+    AND (status BETWEEN 200 AND 299 OR status = 304)
+    AND instr(request_uri, '.') = 0
+    AND request_uri NOT LIKE '/api/%';
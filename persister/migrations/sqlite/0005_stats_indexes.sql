@@ -0,0 +1,2 @@
+CREATE INDEX access_log_host_timestamp ON access_log (http_host, timestamp);
+CREATE INDEX access_log_host_kind_timestamp ON access_log (http_host, request_kind, timestamp);
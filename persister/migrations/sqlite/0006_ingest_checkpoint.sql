@@ -0,0 +1,9 @@
+-- newest timestamp committed by the ingestion, written in the same transaction as the rows so
+-- it never runs ahead of what is stored
+CREATE TABLE ingest_checkpoint (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_timestamp INTEGER NOT NULL
+);
+
+INSERT INTO ingest_checkpoint (id, last_timestamp)
+SELECT 1, max(timestamp) FROM access_log HAVING count(*) > 0;
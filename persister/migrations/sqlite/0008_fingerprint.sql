@@ -0,0 +1,6 @@
+-- where the line was read from (see `Position`), so reading the same lines twice can't count
+-- them twice while identical requests in the same second are still two lines; rows stored
+-- before this migration have none and NULLs don't collide in a unique index
+ALTER TABLE access_log ADD COLUMN fingerprint TEXT;
+
+CREATE UNIQUE INDEX access_log_fingerprint ON access_log (fingerprint);
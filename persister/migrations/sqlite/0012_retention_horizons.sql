@@ -0,0 +1,6 @@
+-- how far back `prune` has dropped raw rows ('raw', bots or all of them) and hourly rollups
+-- ('hour'), so reports read older range edges from the rollups that still cover them
+CREATE TABLE retention_horizons (
+    source TEXT PRIMARY KEY,
+    pruned_before INTEGER NOT NULL
+);
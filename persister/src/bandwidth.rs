@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use crate::{
+    filter::Filter,
+    rollups::{Horizon, Segment, Source},
+    stats::union,
+    storage::{Storage, StorageResult, Value},
+    values,
+};
+
+const TOP_LIMIT: usize = 10;
+
+/// Where the response bytes went, most first. Bots are named by the crawler or tool their
+/// user agent names, `other` when it names none.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Bandwidth {
+    /// request uris without the query string
+    pub paths: Vec<(String, i64)>,
+    pub countries: Vec<(String, i64)>,
+    pub bots: Vec<(String, i64)>,
+}
+
+fn top_bytes(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+    segments: &[Segment],
+    dimension: &str,
+    column: &str,
+) -> StorageResult<Vec<(String, i64)>> {
+    let (parts, mut params) = union(filter, segments, |source, filter| match source {
+        Source::Raw => (
+            format!(
+                "SELECT {column} AS value, sum(body_bytes_sent) AS bytes FROM access_log
+                WHERE {filter} AND {column} IS NOT NULL AND {column} != ''
+                GROUP BY {column}"
+            ),
+            Vec::new(),
+        ),
+        Source::Rollup(_) => (
+            format!("SELECT value, bytes FROM rollup_dimensions WHERE {filter} AND dimension = ?"),
+            values![dimension].to_vec(),
+        ),
+    });
+    params.push(Value::from(TOP_LIMIT as i64));
+    storage
+        .query(
+            &format!(
+                "SELECT value, CAST(sum(bytes) AS BIGINT) AS total FROM ({parts}) AS parts
+                GROUP BY value
+                HAVING sum(bytes) > 0
+                ORDER BY total DESC, value
+                LIMIT ?;"
+            ),
+            &params,
+        )?
+        .iter()
+        .map(|row| Ok((row.get(0)?, row.get(1)?)))
+        .collect()
+}
+
+/// The rollups know the family of a bot, `access_log` only its user agent, so the raw rows are
+/// named here.
+fn bot_bytes(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+    segments: &[Segment],
+) -> StorageResult<Vec<(String, i64)>> {
+    let (parts, params) = union(filter, segments, |source, filter| match source {
+        Source::Raw => (
+            format!(
+                "SELECT http_user_agent AS value, 1 AS raw, sum(body_bytes_sent) AS bytes
+                FROM access_log WHERE {filter} AND is_bot = 1
+                GROUP BY http_user_agent"
+            ),
+            Vec::new(),
+        ),
+        Source::Rollup(_) => (
+            format!(
+                "SELECT value, 0 AS raw, bytes FROM rollup_dimensions
+                WHERE {filter} AND dimension = 'bot'"
+            ),
+            Vec::new(),
+        ),
+    });
+    let rows = storage.query(
+        &format!(
+            "SELECT value, raw, CAST(sum(bytes) AS BIGINT) FROM ({parts}) AS parts
+            GROUP BY value, raw;"
+        ),
+        &params,
+    )?;
+    let mut families: HashMap<String, i64> = HashMap::new();
+    for row in rows {
+        let value: Option<String> = row.get(0)?;
+        let value = value.unwrap_or_default();
+        let family = match row.get::<i64>(1)? {
+            1 => enricher::bot_family(&value).unwrap_or("other").to_string(),
+            _ => value,
+        };
+        *families.entry(family).or_default() += row.get::<i64>(2)?;
+    }
+    let mut bots: Vec<(String, i64)> = families
+        .into_iter()
+        .filter(|(_, bytes)| *bytes > 0)
+        .collect();
+    bots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    bots.truncate(TOP_LIMIT);
+    Ok(bots)
+}
+
+/// Bytes by path, country and bot of the requests `filter` selects.
+pub(crate) fn bandwidth(storage: &mut dyn Storage, filter: &Filter) -> StorageResult<Bandwidth> {
+    let segments = filter.segments(Horizon::load(storage)?);
+    let path = storage.dialect().path("request_uri");
+    Ok(Bandwidth {
+        paths: top_bytes(storage, filter, &segments, "path", &path)?,
+        countries: top_bytes(storage, filter, &segments, "country", "country")?,
+        bots: bot_bytes(storage, filter, &segments)?,
+    })
+}
@@ -0,0 +1,116 @@
+use serde::Serialize;
+
+use crate::{
+    filter::Filter,
+    storage::{Row, Storage, StorageResult, Value},
+    values,
+};
+
+/// A stored request with its enrichment, as handed out by `Db::export`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccessRecord {
+    pub id: i64,
+    /// unix milliseconds
+    pub timestamp: i64,
+    pub remote_addr: String,
+    pub remote_user: Option<String>,
+    pub method: String,
+    pub scheme: String,
+    pub http_host: String,
+    pub request_uri: String,
+    pub server_protocol: String,
+    pub status: i32,
+    pub body_bytes_sent: i64,
+    /// seconds
+    pub request_time: f64,
+    pub upstream_response_time: Option<f64>,
+    pub http_referer: Option<String>,
+    pub http_user_agent: Option<String>,
+    pub visitor_hash: Option<String>,
+    pub is_bot: bool,
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub is_vpn: bool,
+    pub request_kind: String,
+    /// identity of the line, not part of the exported files
+    #[serde(skip)]
+    pub fingerprint: Option<String>,
+}
+
+impl AccessRecord {
+    fn from_row(row: &Row) -> StorageResult<AccessRecord> {
+        Ok(AccessRecord {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            remote_addr: row.get(2)?,
+            remote_user: row.get(3)?,
+            method: row.get(4)?,
+            scheme: row.get(5)?,
+            http_host: row.get(6)?,
+            request_uri: row.get(7)?,
+            server_protocol: row.get(8)?,
+            status: row.get(9)?,
+            body_bytes_sent: row.get(10)?,
+            request_time: row.get(11)?,
+            upstream_response_time: row.get(12)?,
+            http_referer: row.get(13)?,
+            http_user_agent: row.get(14)?,
+            visitor_hash: row.get(15)?,
+            is_bot: row.get(16)?,
+            country: row.get(17)?,
+            city: row.get(18)?,
+            is_vpn: row.get(19)?,
+            request_kind: row.get(20)?,
+            fingerprint: row.get(21)?,
+        })
+    }
+}
+
+/// Hands the matching rows to `write` in id order, `batch_size` at a time, so an export of
+/// any size only ever holds one batch in memory.
+pub(crate) fn export(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+    batch_size: usize,
+    write: &mut dyn FnMut(&[AccessRecord]) -> Result<(), &'static str>,
+) -> Result<usize, &'static str> {
+    let (condition, params) = filter.raw_condition();
+    let sql = format!(
+        "SELECT
+            id, timestamp, remote_addr, remote_user, method, scheme, http_host, request_uri,
+            server_protocol, status, body_bytes_sent, request_time, upstream_response_time,
+            http_referer, http_user_agent, visitor_hash, is_bot, country, city, is_vpn,
+            request_kind, fingerprint
+        FROM access_log
+        WHERE id > ?{condition}
+        ORDER BY id
+        LIMIT ?;"
+    );
+    let mut exported = 0;
+    let mut last_id = 0;
+    loop {
+        let mut page_params = values![last_id].to_vec();
+        page_params.extend(params.iter().cloned());
+        page_params.push(Value::from(batch_size));
+        let records = storage
+            .query(&sql, &page_params)
+            .and_then(|rows| {
+                rows.iter()
+                    .map(AccessRecord::from_row)
+                    .collect::<StorageResult<Vec<_>>>()
+            })
+            .map_err(|err| {
+                eprintln!("{err}");
+                "could not read the rows to export"
+            })?;
+        let Some(last) = records.last() else {
+            return Ok(exported);
+        };
+        last_id = last.id;
+        exported += records.len();
+        write(&records)?;
+        if records.len() < batch_size {
+            return Ok(exported);
+        }
+    }
+}
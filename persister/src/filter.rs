@@ -0,0 +1,392 @@
+use crate::{
+    rollups::{Horizon, Segment, Source, segments},
+    storage::Value,
+};
+
+/// Human or bot traffic, as the enricher classified it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Traffic {
+    Humans,
+    Bots,
+}
+
+impl Traffic {
+    pub fn parse(traffic: &str) -> Result<Traffic, &'static str> {
+        match traffic {
+            "humans" | "human" => Ok(Traffic::Humans),
+            "bots" | "bot" => Ok(Traffic::Bots),
+            _ => Err("unknown traffic, expected humans or bots"),
+        }
+    }
+}
+
+/// Where visitors came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReferrerSource {
+    /// no referrer at all, typed in or bookmarked
+    Direct,
+    /// a link on this site or one of its subdomains, `news.example`
+    Site(String),
+}
+
+impl ReferrerSource {
+    /// `direct` or the site of the referrer.
+    pub fn parse(source: &str) -> ReferrerSource {
+        match source {
+            "direct" => ReferrerSource::Direct,
+            site => ReferrerSource::Site(site.to_lowercase()),
+        }
+    }
+}
+
+/// Browser or client family of a user agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UaFamily {
+    Edge,
+    Opera,
+    Chrome,
+    Firefox,
+    Safari,
+    Curl,
+    /// none of the above, or no user agent
+    Other,
+}
+
+/// Tokens a user agent of the family contains and ones it must not, checked in order since
+/// most browsers claim to be the ones before them.
+const UA_FAMILIES: [(UaFamily, &[&str], &[&str]); 6] = [
+    (UaFamily::Edge, &["Edg/", "EdgA/", "EdgiOS/"], &[]),
+    (UaFamily::Opera, &["OPR/", "Opera"], &[]),
+    (
+        UaFamily::Chrome,
+        &["Chrome/", "CriOS/"],
+        &["Edg/", "EdgA/", "OPR/"],
+    ),
+    (UaFamily::Firefox, &["Firefox/", "FxiOS/"], &[]),
+    (
+        UaFamily::Safari,
+        &["Safari/"],
+        &[
+            "Chrome/",
+            "Chromium/",
+            "CriOS/",
+            "FxiOS/",
+            "EdgiOS/",
+            "OPR/",
+        ],
+    ),
+    (UaFamily::Curl, &["curl/"], &[]),
+];
+
+impl UaFamily {
+    pub fn parse(family: &str) -> Result<UaFamily, &'static str> {
+        match family.to_lowercase().as_str() {
+            "edge" => Ok(UaFamily::Edge),
+            "opera" => Ok(UaFamily::Opera),
+            "chrome" => Ok(UaFamily::Chrome),
+            "firefox" => Ok(UaFamily::Firefox),
+            "safari" => Ok(UaFamily::Safari),
+            "curl" => Ok(UaFamily::Curl),
+            "other" => Ok(UaFamily::Other),
+            _ => {
+                Err("unknown browser, expected edge, opera, chrome, firefox, safari, curl or other")
+            }
+        }
+    }
+
+    /// The family `user_agent` belongs to, the same way the filter tells them apart.
+    pub fn of(user_agent: &str) -> UaFamily {
+        UA_FAMILIES
+            .iter()
+            .find(|(_, tokens, excluded)| {
+                tokens.iter().any(|token| user_agent.contains(token))
+                    && !excluded.iter().any(|token| user_agent.contains(token))
+            })
+            .map_or(UaFamily::Other, |(family, _, _)| *family)
+    }
+
+    /// Condition matching `column` against the family's tokens.
+    fn condition(&self, column: &str, params: &mut Vec<Value>) -> String {
+        let mut like = |tokens: &[&str], join: &str, negate: &str| {
+            tokens
+                .iter()
+                .map(|token| {
+                    params.push(Value::from(format!("%{token}%")));
+                    format!("coalesce({column}, '') {negate}LIKE ?")
+                })
+                .collect::<Vec<_>>()
+                .join(join)
+        };
+        let Some((_, tokens, excluded)) = UA_FAMILIES.iter().find(|(family, _, _)| family == self)
+        else {
+            // no family of the list matches
+            let families: Vec<String> = UA_FAMILIES
+                .iter()
+                .map(|(family, _, _)| format!("NOT ({})", family.condition(column, params)))
+                .collect();
+            return families.join(" AND ");
+        };
+        let mut condition = format!("({})", like(tokens, " OR ", ""));
+        if !excluded.is_empty() {
+            condition.push_str(&format!(" AND {}", like(excluded, " AND ", "NOT ")));
+        }
+        condition
+    }
+}
+
+/// Which requests a report or an export covers, all of them when left empty. Every report
+/// query takes one, so any part of a report can be sliced by any of these:
+///
+/// ```
+/// use persister::{Filter, Traffic};
+///
+/// // human requests that failed on the client side since the start of 2025
+/// let filter = Filter::new()
+///     .host("example.com")
+///     .since(1_735_689_600_000)
+///     .status_class(4)
+///     .traffic(Traffic::Humans);
+/// assert_eq!(filter.status_class, Some(4));
+/// ```
+///
+/// The rollups only know hosts and time, a filter on anything else is answered from the
+/// stored requests and so only covers what the retention settings still keep of them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Filter {
+    pub host: Option<String>,
+    /// unix milliseconds
+    pub since: Option<i64>,
+    /// unix milliseconds, exclusive
+    pub until: Option<i64>,
+    /// start of the request uri, `/blog/`
+    pub path_prefix: Option<String>,
+    /// first digit of the status, `4` for 4xx
+    pub status_class: Option<u16>,
+    pub country: Option<String>,
+    pub traffic: Option<Traffic>,
+    pub referrer: Option<ReferrerSource>,
+    pub user_agent: Option<UaFamily>,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Filter {
+        self.host = Some(host.into());
+        self
+    }
+
+    pub fn since(mut self, since: i64) -> Filter {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: i64) -> Filter {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn path_prefix(mut self, prefix: impl Into<String>) -> Filter {
+        self.path_prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn status_class(mut self, class: u16) -> Filter {
+        self.status_class = Some(class);
+        self
+    }
+
+    pub fn country(mut self, country: impl Into<String>) -> Filter {
+        self.country = Some(country.into());
+        self
+    }
+
+    pub fn traffic(mut self, traffic: Traffic) -> Filter {
+        self.traffic = Some(traffic);
+        self
+    }
+
+    pub fn referrer(mut self, source: ReferrerSource) -> Filter {
+        self.referrer = Some(source);
+        self
+    }
+
+    pub fn user_agent(mut self, family: UaFamily) -> Filter {
+        self.user_agent = Some(family);
+        self
+    }
+
+    /// The same filter over the equally long range right before this one, `None` unless both
+    /// ends of the range are set.
+    pub fn previous(&self) -> Option<Filter> {
+        let (since, until) = (self.since?, self.until?);
+        Some(Filter {
+            since: Some(since - (until - since)),
+            until: Some(since),
+            ..self.clone()
+        })
+    }
+
+    /// `4xx` or `4` -> 4
+    pub fn parse_status_class(class: &str) -> Result<u16, &'static str> {
+        let digit = class.strip_suffix("xx").unwrap_or(class);
+        match digit.parse() {
+            Ok(class @ 1..=5) => Ok(class),
+            _ => Err("status classes are expected as 1xx to 5xx"),
+        }
+    }
+
+    /// Whether the filter only narrows hosts and time, which the rollups can answer.
+    pub(crate) fn by_host_and_time(&self) -> bool {
+        let Filter {
+            host: _,
+            since: _,
+            until: _,
+            path_prefix,
+            status_class,
+            country,
+            traffic,
+            referrer,
+            user_agent,
+        } = self;
+        path_prefix.is_none()
+            && status_class.is_none()
+            && country.is_none()
+            && traffic.is_none()
+            && referrer.is_none()
+            && user_agent.is_none()
+    }
+
+    /// The parts of the filtered range and where each is read from, `horizon` telling what
+    /// `prune` left of them.
+    pub(crate) fn segments(&self, horizon: Horizon) -> Vec<Segment> {
+        let since = self.since.unwrap_or(0);
+        if self.by_host_and_time() {
+            segments(since, self.until, horizon)
+        } else {
+            vec![Segment {
+                source: Source::Raw,
+                from: since,
+                to: self.until,
+            }]
+        }
+    }
+
+    /// `AND ...` conditions on `access_log` for everything but host and time, which the
+    /// segments take care of.
+    pub(crate) fn dimensions(&self) -> (String, Vec<Value>) {
+        let mut condition = String::new();
+        let mut params = Vec::new();
+        if let Some(prefix) = &self.path_prefix {
+            condition.push_str(" AND substr(request_uri, 1, ?) = ?");
+            params.push(Value::from(prefix.chars().count()));
+            params.push(Value::from(prefix));
+        }
+        if let Some(class) = self.status_class {
+            condition.push_str(" AND status / 100 = ?");
+            params.push(Value::from(class));
+        }
+        if let Some(country) = &self.country {
+            condition.push_str(" AND country = ?");
+            params.push(Value::from(country));
+        }
+        if let Some(traffic) = self.traffic {
+            condition.push_str(" AND is_bot = ?");
+            params.push(Value::from(traffic == Traffic::Bots));
+        }
+        match &self.referrer {
+            Some(ReferrerSource::Direct) => condition
+                .push_str(" AND (http_referer IS NULL OR http_referer = '' OR http_referer = '-')"),
+            Some(ReferrerSource::Site(site)) => {
+                condition.push_str(
+                    " AND (lower(http_referer) LIKE ? ESCAPE '\\'
+                        OR lower(http_referer) LIKE ? ESCAPE '\\'
+                        OR lower(http_referer) LIKE ? ESCAPE '\\'
+                        OR lower(http_referer) LIKE ? ESCAPE '\\')",
+                );
+                // the site is matched as it's written, its `%` and `_` are no wildcards
+                let site = site
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                for pattern in ["%://{}", "%://{}/%", "%.{}", "%.{}/%"] {
+                    params.push(Value::from(pattern.replace("{}", &site)));
+                }
+            }
+            None => {}
+        }
+        if let Some(family) = self.user_agent {
+            condition.push_str(&format!(
+                " AND ({})",
+                family.condition("http_user_agent", &mut params)
+            ));
+        }
+        (condition, params)
+    }
+
+    /// The whole filter as `AND ...` conditions on `access_log`.
+    pub(crate) fn raw_condition(&self) -> (String, Vec<Value>) {
+        let mut condition = String::new();
+        let mut params = Vec::new();
+        if let Some(host) = &self.host {
+            condition.push_str(" AND http_host = ?");
+            params.push(Value::from(host));
+        }
+        if let Some(since) = self.since {
+            condition.push_str(" AND timestamp >= ?");
+            params.push(Value::from(since));
+        }
+        if let Some(until) = self.until {
+            condition.push_str(" AND timestamp < ?");
+            params.push(Value::from(until));
+        }
+        let (dimensions, dimension_params) = self.dimensions();
+        condition.push_str(&dimensions);
+        params.extend(dimension_params);
+        (condition, params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollups::Period;
+
+    #[test]
+    fn tells_user_agent_families_apart() {
+        let chrome = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+        let edge = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0";
+        let safari = "Mozilla/5.0 (Macintosh; Intel Mac OS X 14_2) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.2 Safari/605.1.15";
+        assert_eq!(UaFamily::of(chrome), UaFamily::Chrome);
+        assert_eq!(UaFamily::of(edge), UaFamily::Edge);
+        assert_eq!(UaFamily::of(safari), UaFamily::Safari);
+        assert_eq!(UaFamily::of("curl/8.5.0"), UaFamily::Curl);
+        assert_eq!(UaFamily::of(""), UaFamily::Other);
+    }
+
+    #[test]
+    fn uses_rollups_only_for_host_and_time() {
+        let filter = Filter::new().host("example.com").since(0);
+        let horizon = Horizon::default();
+        assert_eq!(
+            filter.segments(horizon)[0].source,
+            Source::Rollup(Period::Day)
+        );
+        assert_eq!(filter.dimensions().0, "");
+        let filter = filter.status_class(Filter::parse_status_class("4xx").unwrap());
+        assert_eq!(filter.segments(horizon)[0].source, Source::Raw);
+        assert_eq!(filter.segments(horizon).len(), 1);
+        assert!(Filter::parse_status_class("6xx").is_err());
+    }
+
+    #[test]
+    fn goes_back_by_the_length_of_the_range() {
+        let filter = Filter::new().host("example.com").since(100).until(150);
+        let previous = filter.previous().unwrap();
+        assert_eq!((previous.since, previous.until), (Some(50), Some(100)));
+        assert_eq!(previous.host, filter.host);
+        assert_eq!(Filter::new().since(100).previous(), None);
+    }
+}
@@ -0,0 +1,102 @@
+use parser::LogStruct;
+use sha2::{Digest, Sha256};
+
+/// Where a line was read from, its identity when it's stored. Identical requests in the
+/// same second are still two lines at two offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position<'a> {
+    /// `log_source` of the file the line is in
+    pub source: &'a str,
+    /// bytes into the file
+    pub offset: u64,
+}
+
+impl Position<'_> {
+    pub(crate) fn fingerprint(&self) -> String {
+        digest([self.source, &self.offset.to_string()])
+    }
+}
+
+/// Identity of the log file that starts with `first_line`. Unlike the file name it stays the
+/// same when nginx rotates and compresses the log, and lines are only ever appended so their
+/// offsets in it do too. It's of the line as it was read, so no setting such as privacy mode
+/// changes it.
+pub fn log_source(first_line: &str) -> String {
+    digest([first_line.trim_end()])
+}
+
+/// Identity of the fields of a line, for imported rows that were stored before fingerprints
+/// and don't know where they were read from.
+pub(crate) fn fingerprint(remote_addr: &str, log_struct: &LogStruct) -> String {
+    let fields = [
+        remote_addr,
+        log_struct.remote_user.unwrap_or(""),
+        &log_struct.dt.to_string(),
+        log_struct.method,
+        log_struct.scheme,
+        log_struct.http_host,
+        log_struct.request_uri,
+        log_struct.server_protocol,
+        &log_struct.status.to_string(),
+        &log_struct.body_bytes_sent.to_string(),
+        &log_struct.request_time.to_string(),
+        &log_struct
+            .upstream_response_time
+            .map(|time| time.to_string())
+            .unwrap_or_default(),
+        log_struct.http_refferer.unwrap_or(""),
+        log_struct.http_user_agent,
+    ];
+    digest(fields)
+}
+
+fn digest<'a>(fields: impl IntoIterator<Item = &'a str>) -> String {
+    let mut hasher = Sha256::new();
+    for field in fields {
+        hasher.update(field.as_bytes());
+        hasher.update([0]);
+    }
+    // half of the digest is plenty to tell log lines apart
+    hasher.finalize()[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "203.0.113.7\t-\t2025-01-01T10:00:00+00:00\tGET\thttps\texample.com\t/\tHTTP/2.0\t200\t512\t0.020\t0.010\t-\tMozilla/5.0";
+
+    #[test]
+    fn fingerprints_are_stable_and_cover_every_field() {
+        let log_struct = LogStruct::from_line(LINE).unwrap();
+        let first = fingerprint(log_struct.remote_addr, &log_struct);
+        assert_eq!(first, fingerprint(log_struct.remote_addr, &log_struct));
+        assert_eq!(first.len(), 32);
+
+        let other = LINE.replace("\t200\t", "\t404\t");
+        let other = LogStruct::from_line(&other).unwrap();
+        assert_ne!(first, fingerprint(other.remote_addr, &other));
+        assert_ne!(first, fingerprint("203.0.113.0", &log_struct));
+    }
+
+    #[test]
+    fn positions_tell_identical_lines_apart() {
+        let source = log_source(LINE);
+        assert_eq!(source, log_source(&format!("{LINE}\n")));
+        assert_ne!(source, log_source(&LINE.replace("10:00:00", "10:00:01")));
+        let at = |offset| Position {
+            source: &source,
+            offset,
+        };
+        assert_eq!(at(0).fingerprint(), at(0).fingerprint());
+        assert_ne!(at(0).fingerprint(), at(120).fingerprint());
+        let other = Position {
+            source: "other",
+            offset: 0,
+        };
+        assert_ne!(at(0).fingerprint(), other.fingerprint());
+    }
+}
@@ -0,0 +1,156 @@
+use enricher::{EnrichedLog, RequestKind};
+use parser::LogStruct;
+
+use crate::{
+    export::AccessRecord,
+    rollups::Period,
+    storage::{Storage, StorageResult},
+    values,
+};
+
+/// Totals of one day as another analytics tool reports them. Those tools don't tell humans
+/// from bots the way kirinox does, so an imported day counts its requests as neither.
+#[derive(Debug, Default, Clone)]
+pub struct DaySummary {
+    /// any time of the day, unix milliseconds (UTC)
+    pub day: i64,
+    pub requests: i64,
+    pub pageviews: i64,
+    pub visitors: i64,
+    pub bytes_sent: i64,
+}
+
+/// What another analytics tool knows about one host. Those tools only keep daily totals,
+/// the top lists and status codes cover their whole report and are stored on the first day
+/// that gets imported, unless some of its days were already there and would count twice.
+#[derive(Debug, Default, Clone)]
+pub struct Summary {
+    pub host: String,
+    pub days: Vec<DaySummary>,
+    /// 1xx to 5xx
+    pub status: [i64; 5],
+    pub pages: Vec<(String, i64)>,
+    pub countries: Vec<(String, i64)>,
+    pub referrers: Vec<(String, i64)>,
+}
+
+/// Outcome of `Db::import_summary`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SummaryImport {
+    pub days: usize,
+    /// days the host already had data for, imported before or ingested from the logs
+    pub skipped_days: usize,
+    /// whether the status codes and top lists of the whole report were stored
+    pub totals: bool,
+}
+
+/// Writes the summary into the daily rollups, there are no requests to store. Days the host
+/// already has are left alone, so importing the same report twice or a report overlapping
+/// the logs doesn't count anything twice.
+pub(crate) fn import_summary(
+    storage: &mut dyn Storage,
+    summary: &Summary,
+) -> StorageResult<SummaryImport> {
+    let mut days: Vec<&DaySummary> = summary.days.iter().collect();
+    days.sort_by_key(|day| day.day);
+    let mut imported = SummaryImport::default();
+    let mut first_bucket = None;
+    for day in days {
+        let bucket = Period::Day.floor(day.day);
+        let existing = storage.query_row(
+            "SELECT 1 FROM rollups WHERE http_host = ? AND period = 'day' AND bucket = ?;",
+            values![&summary.host, bucket],
+        )?;
+        if existing.is_some() {
+            imported.skipped_days += 1;
+            continue;
+        }
+        storage.execute(
+            "INSERT INTO rollups (
+                http_host, period, bucket, requests, pageviews, human_requests, bot_requests,
+                vpn_requests, bytes_sent, status_1xx, status_2xx, status_3xx, status_4xx,
+                status_5xx, request_time_sum, imported_visitors
+            ) VALUES (?, 'day', ?, ?, ?, 0, 0, 0, ?, 0, 0, 0, 0, 0, 0, ?);",
+            values![
+                &summary.host,
+                bucket,
+                day.requests,
+                day.pageviews,
+                day.bytes_sent,
+                day.visitors
+            ],
+        )?;
+        imported.days += 1;
+        first_bucket = first_bucket.or(Some(bucket));
+    }
+    // part of the report is counted already, its totals can't be split by day
+    let Some(bucket) = first_bucket.filter(|_| imported.skipped_days == 0) else {
+        return Ok(imported);
+    };
+    imported.totals = true;
+    let [status_1xx, status_2xx, status_3xx, status_4xx, status_5xx] = summary.status;
+    storage.execute(
+        "UPDATE rollups SET
+            status_1xx = ?, status_2xx = ?, status_3xx = ?, status_4xx = ?, status_5xx = ?
+        WHERE http_host = ? AND period = 'day' AND bucket = ?;",
+        values![
+            status_1xx,
+            status_2xx,
+            status_3xx,
+            status_4xx,
+            status_5xx,
+            &summary.host,
+            bucket
+        ],
+    )?;
+    let dimensions = [
+        ("page", &summary.pages),
+        ("country", &summary.countries),
+        ("referrer", &summary.referrers),
+    ];
+    for (dimension, values) in dimensions {
+        for (value, hits) in values.iter().filter(|(value, _)| !value.is_empty()) {
+            storage.execute(
+                "INSERT INTO rollup_dimensions (http_host, period, bucket, dimension, value, hits)
+                VALUES (?, 'day', ?, ?, ?, ?)
+                ON CONFLICT (http_host, period, bucket, dimension, value) DO UPDATE SET
+                    hits = rollup_dimensions.hits + excluded.hits;",
+                values![&summary.host, bucket, dimension, value, *hits],
+            )?;
+        }
+    }
+    Ok(imported)
+}
+
+impl AccessRecord {
+    /// The record as if it had just been read from a log, for merging another database.
+    pub(crate) fn to_log(&self) -> (LogStruct<'_>, EnrichedLog) {
+        let log_struct = LogStruct {
+            remote_addr: &self.remote_addr,
+            remote_user: self.remote_user.as_deref(),
+            dt: self.timestamp,
+            method: &self.method,
+            scheme: &self.scheme,
+            http_host: &self.http_host,
+            request_uri: &self.request_uri,
+            server_protocol: &self.server_protocol,
+            status: self.status as u16,
+            body_bytes_sent: self.body_bytes_sent as u64,
+            request_time: self.request_time,
+            upstream_response_time: self.upstream_response_time,
+            http_refferer: self.http_referer.as_deref(),
+            http_user_agent: self.http_user_agent.as_deref().unwrap_or(""),
+            http_x_forwarded_for: None,
+            http_cf_connecting_ip: None,
+            proxy_addr: None,
+        };
+        let enriched = EnrichedLog {
+            is_bot: self.is_bot,
+            country: self.country.clone().unwrap_or_default(),
+            city: self.city.clone().unwrap_or_default(),
+            is_vpn: self.is_vpn,
+            kind: RequestKind::parse(&self.request_kind),
+        };
+        (log_struct, enriched)
+    }
+}
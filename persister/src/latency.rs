@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use crate::{
+    filter::Filter,
+    rollups::{Horizon, Source},
+    stats::union,
+    storage::{Storage, StorageResult},
+    values,
+};
+
+/// Upper bounds of the latency buckets in seconds, ten per decade from 1 ms to 100 s. The
+/// `latency_buckets` table holds the same bounds so raw rows are bucketed the same way in SQL.
+const LATENCY_BOUNDS: [f64; 51] = [
+    0.001, 0.00125, 0.0016, 0.002, 0.0025, 0.0032, 0.004, 0.005, 0.0063, 0.008, 0.01, 0.0125,
+    0.016, 0.02, 0.025, 0.032, 0.04, 0.05, 0.063, 0.08, 0.1, 0.125, 0.16, 0.2, 0.25, 0.32, 0.4,
+    0.5, 0.63, 0.8, 1.0, 1.25, 1.6, 2.0, 2.5, 3.2, 4.0, 5.0, 6.3, 8.0, 10.0, 12.5, 16.0, 20.0,
+    25.0, 32.0, 40.0, 50.0, 63.0, 80.0, 100.0,
+];
+
+const BUCKETS: usize = LATENCY_BOUNDS.len() + 1;
+
+/// Endpoints with fewer requests say little about their tail and are left out of the slowest.
+const SLOWEST_MIN_REQUESTS: i64 = 5;
+
+const SLOWEST_LIMIT: usize = 10;
+
+/// Bucket `seconds` falls in, the last one holds everything from 100 s up.
+pub(crate) fn latency_bucket(seconds: f64) -> i64 {
+    LATENCY_BOUNDS.partition_point(|bound| *bound <= seconds) as i64
+}
+
+/// The path of a request uri, what latency is broken down by.
+pub(crate) fn path(uri: &str) -> &str {
+    uri.split('?').next().unwrap_or_default()
+}
+
+/// What is timed, nginx's `request_time` or `upstream_response_time`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Metric {
+    Request,
+    Upstream,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Request => "request",
+            Metric::Upstream => "upstream",
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            Metric::Request => "request_time",
+            Metric::Upstream => "upstream_response_time",
+        }
+    }
+}
+
+/// Milliseconds, estimated from the histogram so within a quarter of the true value.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EndpointLatency {
+    /// request uri without the query string
+    pub path: String,
+    pub requests: i32,
+    pub request_time: Percentiles,
+    /// `None` when nginx served the path itself
+    pub upstream_time: Option<Percentiles>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Latency {
+    pub request_time: Percentiles,
+    /// `None` when no request went to an upstream
+    pub upstream_time: Option<Percentiles>,
+    /// slowest paths by p95 request time
+    pub slowest: Vec<EndpointLatency>,
+}
+
+#[derive(Debug, Clone)]
+struct Histogram([i64; BUCKETS]);
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram([0; BUCKETS])
+    }
+}
+
+impl Histogram {
+    fn total(&self) -> i64 {
+        self.0.iter().sum()
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        for (hits, other) in self.0.iter_mut().zip(other.0) {
+            *hits += other;
+        }
+    }
+
+    /// Interpolates within the bucket the `quantile` falls in, milliseconds.
+    fn quantile(&self, quantile: f64) -> f64 {
+        let rank = quantile * self.total() as f64;
+        let mut below = 0;
+        for (bucket, hits) in self.0.iter().enumerate().filter(|(_, hits)| **hits > 0) {
+            if ((below + hits) as f64) < rank {
+                below += hits;
+                continue;
+            }
+            let lower = bucket
+                .checked_sub(1)
+                .map_or(0.0, |bucket| LATENCY_BOUNDS[bucket]);
+            let Some(upper) = LATENCY_BOUNDS.get(bucket) else {
+                // nothing to interpolate towards past the last bound
+                return lower * 1000.0;
+            };
+            let within = (rank - below as f64) / *hits as f64;
+            return (lower + (upper - lower) * within) * 1000.0;
+        }
+        0.0
+    }
+
+    fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            p50: self.quantile(0.5),
+            p90: self.quantile(0.9),
+            p95: self.quantile(0.95),
+            p99: self.quantile(0.99),
+        }
+    }
+}
+
+/// Histograms of `metric` per path for the requests `filter` selects.
+fn histograms(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+    metric: Metric,
+) -> StorageResult<HashMap<String, Histogram>> {
+    let path = storage.dialect().path("request_uri");
+    let column = metric.column();
+    let (parts, params) = union(
+        filter,
+        &filter.segments(Horizon::load(storage)?),
+        |source, condition| match source {
+            Source::Raw => (
+                format!(
+                    "SELECT {path} AS path, latency_bucket, count(*) AS hits
+                FROM access_log
+                JOIN latency_buckets ON {column} >= lower_bound AND {column} < upper_bound
+                WHERE {condition}
+                GROUP BY {path}, latency_bucket"
+                ),
+                Vec::new(),
+            ),
+            Source::Rollup(_) => (
+                format!(
+                    "SELECT path, latency_bucket, hits FROM rollup_latency
+                WHERE {condition} AND metric = ?"
+                ),
+                values![metric.as_str()].to_vec(),
+            ),
+        },
+    );
+    let rows = storage.query(
+        &format!(
+            "SELECT path, latency_bucket, CAST(sum(hits) AS BIGINT) FROM ({parts}) AS parts
+            GROUP BY path, latency_bucket;"
+        ),
+        &params,
+    )?;
+    let mut histograms: HashMap<String, Histogram> = HashMap::new();
+    for row in rows {
+        let bucket: i64 = row.get(1)?;
+        let histogram = histograms.entry(row.get(0)?).or_default();
+        histogram.0[bucket as usize] += row.get::<i64>(2)?;
+    }
+    Ok(histograms)
+}
+
+fn overall(histograms: &HashMap<String, Histogram>) -> Histogram {
+    let mut overall = Histogram::default();
+    for histogram in histograms.values() {
+        overall.merge(histogram);
+    }
+    overall
+}
+
+fn upstream_time(histogram: Option<&Histogram>) -> Option<Percentiles> {
+    histogram
+        .filter(|histogram| histogram.total() > 0)
+        .map(Histogram::percentiles)
+}
+
+fn endpoints(
+    request: &HashMap<String, Histogram>,
+    upstream: &HashMap<String, Histogram>,
+    min_requests: i64,
+) -> Vec<EndpointLatency> {
+    request
+        .iter()
+        .filter(|(_, histogram)| histogram.total() >= min_requests)
+        .map(|(path, histogram)| EndpointLatency {
+            path: path.clone(),
+            requests: histogram.total() as i32,
+            request_time: histogram.percentiles(),
+            upstream_time: upstream_time(upstream.get(path)),
+        })
+        .collect()
+}
+
+/// Request and upstream time percentiles of the requests `filter` selects, overall and for
+/// the slowest paths.
+pub(crate) fn latency(storage: &mut dyn Storage, filter: &Filter) -> StorageResult<Latency> {
+    let request = histograms(storage, filter, Metric::Request)?;
+    let upstream = histograms(storage, filter, Metric::Upstream)?;
+    let mut slowest = endpoints(&request, &upstream, SLOWEST_MIN_REQUESTS);
+    slowest.sort_by(|a, b| {
+        b.request_time
+            .p95
+            .total_cmp(&a.request_time.p95)
+            .then(b.requests.cmp(&a.requests))
+            .then(a.path.cmp(&b.path))
+    });
+    slowest.truncate(SLOWEST_LIMIT);
+    Ok(Latency {
+        request_time: overall(&request).percentiles(),
+        upstream_time: upstream_time(Some(&overall(&upstream))),
+        slowest,
+    })
+}
+
+/// The `limit` paths `filter` selects the most requests of, with their latency.
+pub(crate) fn busiest(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+    limit: usize,
+) -> StorageResult<Vec<EndpointLatency>> {
+    let request = histograms(storage, filter, Metric::Request)?;
+    let upstream = histograms(storage, filter, Metric::Upstream)?;
+    let mut busiest = endpoints(&request, &upstream, 1);
+    busiest.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.path.cmp(&b.path)));
+    busiest.truncate(limit);
+    Ok(busiest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_like_the_latency_buckets_table() {
+        assert_eq!(latency_bucket(0.0), 0);
+        assert_eq!(latency_bucket(0.001), 1);
+        assert_eq!(latency_bucket(0.1), 21);
+        assert_eq!(latency_bucket(0.0999), 20);
+        assert_eq!(latency_bucket(250.0), LATENCY_BOUNDS.len() as i64);
+        assert_eq!(path("/search?q=kirinox"), "/search");
+    }
+
+    #[test]
+    fn interpolates_percentiles() {
+        let mut histogram = Histogram::default();
+        // 90 fast requests and 10 slow ones
+        histogram.0[latency_bucket(0.02) as usize] = 90;
+        histogram.0[latency_bucket(1.5) as usize] = 10;
+        let percentiles = histogram.percentiles();
+        assert!((20.0..=25.0).contains(&percentiles.p50));
+        assert!((20.0..=25.0).contains(&percentiles.p90));
+        assert!((1250.0..=1600.0).contains(&percentiles.p95));
+        assert!((1250.0..=1600.0).contains(&percentiles.p99));
+        assert_eq!(Histogram::default().percentiles(), Percentiles::default());
+    }
+}
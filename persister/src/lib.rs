@@ -1,71 +1,368 @@
-use std::path::PathBuf;
+use std::{cell::RefCell, path::PathBuf};
 
-use enricher::EnrichedLog;
+use enricher::{EnrichedLog, RequestKind};
 use parser::LogStruct;
 
-use rusqlite::{Connection, OptionalExtension, Params, Result, params};
+mod bandwidth;
+mod export;
+mod filter;
+mod fingerprint;
+mod import;
+mod latency;
+mod migrations;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod privacy;
+mod retention;
+mod rollups;
+mod sessions;
+mod sqlite;
+mod stats;
+mod status;
+mod storage;
+mod timeseries;
+
+pub use bandwidth::Bandwidth;
+pub use export::AccessRecord;
+pub use filter::{Filter, ReferrerSource, Traffic, UaFamily};
+pub use import::{DaySummary, Summary, SummaryImport};
+pub use latency::{EndpointLatency, Latency, Percentiles};
+pub use migrations::SCHEMA_VERSION;
+
+use fingerprint::fingerprint;
+pub use fingerprint::{Position, log_source};
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStorage;
+pub use privacy::Privacy;
+use privacy::{DailySalt, hash_visitor};
+pub use retention::{Pruned, Retention};
+pub use rollups::Period;
+use rollups::{Entry, Horizon, Pending};
+pub use sessions::{DEFAULT_SESSION_GAP_MS, SessionStats};
+use sessions::{Hit, record_hit, session_stats};
+pub use sqlite::SqliteStorage;
+pub use stats::{Changes, Stats};
+pub use status::{BrokenLink, StatusReport};
+pub use storage::{Dialect, FromValue, Row, Storage, StorageError, StorageResult, Value};
+pub use timeseries::TimePoint;
+
+/// Where the data lives.
+#[derive(Debug, Clone)]
+pub enum Location {
+    /// path of the SQLite file
+    Sqlite(PathBuf),
+    /// Postgres connection string, needs the `postgres` feature
+    Postgres(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct DbOptions {
+    pub location: Location,
+    /// when set only a truncated address and a salted visitor hash are stored
+    pub privacy: Option<Privacy>,
+    /// inactivity in milliseconds after which a visitor starts a new session
+    pub session_gap: i64,
+    /// number of rows committed together by `insert_batch` callers
+    pub batch_size: usize,
+}
+
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+impl Default for DbOptions {
+    fn default() -> DbOptions {
+        DbOptions {
+            location: Location::Sqlite(PathBuf::from("krx.db")),
+            privacy: None,
+            session_gap: DEFAULT_SESSION_GAP_MS,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+/// Outcome of `insert_batch`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Ingested {
+    pub inserted: usize,
+    /// lines that were already stored and were skipped
+    pub duplicates: usize,
+    /// lines older than what `prune` dropped, refused as the rollups may count them already
+    pub too_old: usize,
+}
+
+/// What a row to insert is known by.
+struct Identity<'a> {
+    fingerprint: &'a str,
+    /// as the row was stored before, when it's imported
+    visitor_hash: Option<&'a str>,
+}
 
 pub struct Db {
-    connection: Connection,
+    storage: RefCell<Box<dyn Storage>>,
+    options: DbOptions,
+    salt: RefCell<Option<DailySalt>>,
+}
+
+fn open_storage(location: &Location) -> Result<Box<dyn Storage>, &'static str> {
+    match location {
+        Location::Sqlite(path) => SqliteStorage::open(path)
+            .map(|storage| Box::new(storage) as Box<dyn Storage>)
+            .map_err(|err| {
+                eprintln!("{}: {err}", path.display());
+                "could not open the database"
+            }),
+        #[cfg(feature = "postgres")]
+        Location::Postgres(url) => PostgresStorage::connect(url)
+            .map(|storage| Box::new(storage) as Box<dyn Storage>)
+            .map_err(|err| {
+                eprintln!("{err}");
+                "could not connect to the database"
+            }),
+        #[cfg(not(feature = "postgres"))]
+        Location::Postgres(_) => Err("kirinox was built without PostgreSQL support"),
+    }
 }
 
 impl Db {
-    pub fn new() -> Db {
-        let path = PathBuf::from("./krx.db");
-        if path.exists() {
-            return Db {
-                connection: Connection::open("krx.db").unwrap(),
-            };
-        };
-        let con = Connection::open("krx.db").unwrap();
-        con.execute(
-            "CREATE TABLE IF NOT EXISTS access_log (
-    id INTEGER PRIMARY KEY AUTOINCREMENT,
-
-    -- request info
-    remote_addr TEXT NOT NULL,
-    remote_user TEXT,
-    timestamp INTEGER NOT NULL,
-    method TEXT NOT NULL,
-    scheme TEXT NOT NULL,
-    http_host TEXT NOT NULL,
-    request_uri TEXT NOT NULL,
-    server_protocol TEXT NOT NULL,
-    status INTEGER NOT NULL,
-    body_bytes_sent INTEGER NOT NULL,
-    request_time REAL NOT NULL,
-    upstream_response_time REAL,
-
-    http_referer TEXT,
-    http_user_agent TEXT,
-
-    -- enrichment
-    is_bot INTEGER NOT NULL CHECK (is_bot IN (0,1)),
-    country TEXT,
-    city TEXT,
-    is_vpn INTEGER NOT NULL CHECK (is_vpn IN (0,1)),
-
-    -- useful indexes
-    created_at TEXT DEFAULT (datetime('now'))
-);",
-            (),
+    pub fn new(options: DbOptions) -> Result<Db, &'static str> {
+        let storage = open_storage(&options.location)?;
+        Db::with_storage(storage, options)
+    }
+
+    /// Uses an already opened storage, migrating it first.
+    pub fn with_storage(
+        mut storage: Box<dyn Storage>,
+        options: DbOptions,
+    ) -> Result<Db, &'static str> {
+        migrations::migrate(storage.as_mut())?;
+        Ok(Db {
+            storage: RefCell::new(storage),
+            options,
+            salt: RefCell::new(None),
+        })
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.options.batch_size.max(1)
+    }
+
+    fn daily_salt(&self, storage: &mut dyn Storage, timestamp: i64) -> StorageResult<[u8; 32]> {
+        let mut cached = self.salt.borrow_mut();
+        match cached.as_ref() {
+            Some(salt) if salt.day == timestamp.div_euclid(privacy::DAY_MS) => Ok(salt.salt),
+            _ => {
+                let salt = DailySalt::for_timestamp(storage, timestamp)?;
+                let bytes = salt.salt;
+                *cached = Some(salt);
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Forgets the salts of the days before yesterday, relative to `now`. Called once the logs
+    /// of a run are stored, so every file of the run hashes a visitor the same way.
+    pub fn expire_salts(&self, now: i64) -> Result<usize, &'static str> {
+        *self.salt.borrow_mut() = None;
+        DailySalt::expire(self.storage.borrow_mut().as_mut(), now).map_err(|err| {
+            eprintln!("{err}");
+            "could not expire the daily salts"
+        })
+    }
+
+    pub fn insert_record(
+        &self,
+        log_struct: &LogStruct,
+        enriched_log_struct: &EnrichedLog,
+        position: Position,
+    ) -> Result<Ingested, &'static str> {
+        self.insert_batch([(log_struct, enriched_log_struct, position)])
+    }
+
+    /// Stores the records in one transaction together with the ingestion checkpoint, so after
+    /// a crash either the whole batch and its checkpoint are there or none of it is. Callers
+    /// feed lines oldest first and cut batches of `batch_size()`. Lines that are already
+    /// stored, say after an archive was read a second time, are told by their `Position` and
+    /// skipped.
+    pub fn insert_batch<'r, 'l: 'r, I>(&self, records: I) -> Result<Ingested, &'static str>
+    where
+        I: IntoIterator<Item = (&'r LogStruct<'l>, &'r EnrichedLog, Position<'r>)>,
+    {
+        self.insert(
+            records.into_iter().map(|(log_struct, enriched, position)| {
+                (log_struct, enriched, position.fingerprint(), None)
+            }),
+            true,
         )
-        .unwrap();
-        Db { connection: con }
     }
 
-    pub fn insert_record(&self, log_struct: &LogStruct, enriched_log_struct: &EnrichedLog) {
-        self.connection.execute(
+    /// Merges rows exported from another database. Rows both databases have are skipped like
+    /// any duplicate line, and the ingestion checkpoint stays where the logs left it. Rows keep
+    /// the fingerprints they were stored with, so lines both databases read are found. Visitors
+    /// keep their hashes too, the addresses of a privacy mode database are truncated and can't
+    /// tell them apart again.
+    pub fn import_records(&self, records: &[AccessRecord]) -> Result<Ingested, &'static str> {
+        let logs: Vec<(LogStruct, EnrichedLog)> =
+            records.iter().map(AccessRecord::to_log).collect();
+        self.insert(
+            logs.iter()
+                .zip(records)
+                .map(|((log_struct, enriched), record)| {
+                    let fingerprint = record
+                        .fingerprint
+                        .clone()
+                        .unwrap_or_else(|| fingerprint(&record.remote_addr, log_struct));
+                    (
+                        log_struct,
+                        enriched,
+                        fingerprint,
+                        record.visitor_hash.as_deref(),
+                    )
+                }),
+            false,
+        )
+    }
+
+    /// Stores the daily totals of another analytics tool, see `Summary`.
+    pub fn import_summary(&self, summary: &Summary) -> Result<SummaryImport, &'static str> {
+        let mut storage = self.storage.borrow_mut();
+        let storage = storage.as_mut();
+        storage.begin().map_err(|err| {
+            eprintln!("{err}");
+            "could not start the import transaction"
+        })?;
+        let imported = match import::import_summary(storage, summary) {
+            Ok(imported) => imported,
+            Err(err) => {
+                eprintln!("{err}");
+                let _ = storage.rollback();
+                return Err("could not import the summary");
+            }
+        };
+        storage.commit().map_err(|err| {
+            eprintln!("{err}");
+            "could not commit the import"
+        })?;
+        Ok(imported)
+    }
+
+    /// `records` come with their fingerprint and the visitor hash they already have, if any.
+    fn insert<'r, 'l: 'r, I>(
+        &self,
+        records: I,
+        move_checkpoint: bool,
+    ) -> Result<Ingested, &'static str>
+    where
+        I: IntoIterator<Item = (&'r LogStruct<'l>, &'r EnrichedLog, String, Option<&'r str>)>,
+    {
+        let mut storage = self.storage.borrow_mut();
+        let storage = storage.as_mut();
+        storage.begin().map_err(|err| {
+            eprintln!("{err}");
+            "could not start the insert transaction"
+        })?;
+        let horizon = match Horizon::load(storage) {
+            Ok(horizon) => horizon,
+            Err(err) => {
+                eprintln!("{err}");
+                let _ = storage.rollback();
+                return Err("could not read how far the data was pruned");
+            }
+        };
+        let mut ingested = Ingested::default();
+        let mut last_timestamp: Option<i64> = None;
+        let mut rollups = Pending::default();
+        for (log_struct, enriched_log_struct, fingerprint, visitor_hash) in records {
+            if horizon.dropped(log_struct.dt, enriched_log_struct.is_bot) {
+                ingested.too_old += 1;
+                continue;
+            }
+            let identity = Identity {
+                fingerprint: &fingerprint,
+                visitor_hash,
+            };
+            match self.insert_row(
+                storage,
+                &mut rollups,
+                log_struct,
+                enriched_log_struct,
+                identity,
+            ) {
+                Ok(true) => ingested.inserted += 1,
+                Ok(false) => ingested.duplicates += 1,
+                Err(err) => {
+                    eprintln!("{err}");
+                    let _ = storage.rollback();
+                    return Err("could not insert the record");
+                }
+            }
+            last_timestamp = last_timestamp.max(Some(log_struct.dt));
+        }
+        if let Err(err) = rollups.flush(storage) {
+            eprintln!("{err}");
+            let _ = storage.rollback();
+            return Err("could not update the rollups");
+        }
+        if let Some(last_timestamp) = last_timestamp.filter(|_| move_checkpoint) {
+            let checkpoint = storage.execute(
+                &format!(
+                    "INSERT INTO ingest_checkpoint (id, last_timestamp) VALUES (1, ?)
+                    ON CONFLICT (id) DO UPDATE SET
+                        last_timestamp = {}(ingest_checkpoint.last_timestamp, excluded.last_timestamp);",
+                    storage.dialect().greatest()
+                ),
+                values![last_timestamp],
+            );
+            if let Err(err) = checkpoint {
+                eprintln!("{err}");
+                let _ = storage.rollback();
+                return Err("could not update the ingestion checkpoint");
+            }
+        }
+        storage.commit().map_err(|err| {
+            eprintln!("{err}");
+            "could not commit the batch"
+        })?;
+        Ok(ingested)
+    }
+
+    /// enrichment already happened on the full address, from here on only the truncated one
+    /// is kept
+    fn stored_addr(&self, remote_addr: &str) -> String {
+        match &self.options.privacy {
+            Some(privacy) => privacy.truncate_ip(remote_addr),
+            None => remote_addr.to_string(),
+        }
+    }
+
+    fn insert_row(
+        &self,
+        storage: &mut dyn Storage,
+        rollups: &mut Pending,
+        log_struct: &LogStruct,
+        enriched_log_struct: &EnrichedLog,
+        identity: Identity,
+    ) -> StorageResult<bool> {
+        let remote_addr = self.stored_addr(log_struct.remote_addr);
+        let visitor_hash = match (identity.visitor_hash, &self.options.privacy) {
+            (Some(visitor_hash), _) => visitor_hash.to_string(),
+            (None, Some(_)) => hash_visitor(
+                &self.daily_salt(storage, log_struct.dt)?,
+                log_struct.remote_addr,
+                log_struct.http_user_agent,
+            ),
+            (None, None) => hash_visitor(&[], log_struct.remote_addr, log_struct.http_user_agent),
+        };
+        let inserted = storage.execute(
             "INSERT INTO access_log (
             remote_addr, timestamp, method, scheme,
             http_host, request_uri, server_protocol, status,
             body_bytes_sent, request_time, upstream_response_time,
-            http_referer, http_user_agent,
-            is_bot, country, city, is_vpn
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
+            http_referer, http_user_agent, visitor_hash,
+            is_bot, country, city, is_vpn, request_kind, fingerprint
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (fingerprint) DO NOTHING;
                 ",
-            params![
-                log_struct.remote_addr,
+            values![
+                &remote_addr,
                 log_struct.dt,
                 log_struct.method,
                 log_struct.scheme,
@@ -78,23 +375,1006 @@ impl Db {
                 log_struct.upstream_response_time,
                 log_struct.http_refferer,
                 log_struct.http_user_agent,
+                &visitor_hash,
                 enriched_log_struct.is_bot,
-                enriched_log_struct.country,
-                enriched_log_struct.city,
+                &enriched_log_struct.country,
+                &enriched_log_struct.city,
                 enriched_log_struct.is_vpn,
+                enriched_log_struct.kind.as_str(),
+                identity.fingerprint,
             ],
-        ).unwrap();
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        rollups.add(&Entry {
+            http_host: log_struct.http_host,
+            timestamp: log_struct.dt,
+            visitor: &visitor_hash,
+            is_pageview: enriched_log_struct.kind == RequestKind::Pageview,
+            page: log_struct.request_uri,
+            country: &enriched_log_struct.country,
+            city: &enriched_log_struct.city,
+            referrer: log_struct.http_refferer,
+            status: log_struct.status,
+            bytes_sent: log_struct.body_bytes_sent,
+            user_agent: log_struct.http_user_agent,
+            request_time: log_struct.request_time,
+            upstream_time: log_struct.upstream_response_time,
+            is_bot: enriched_log_struct.is_bot,
+            is_vpn: enriched_log_struct.is_vpn,
+        });
+        // sessions are made of pages, the assets and api calls they trigger don't count
+        if enriched_log_struct.kind != RequestKind::Pageview {
+            return Ok(true);
+        }
+        record_hit(
+            storage,
+            &Hit {
+                http_host: log_struct.http_host,
+                visitor_hash: &visitor_hash,
+                timestamp: log_struct.dt,
+                page: log_struct.request_uri,
+                is_bot: enriched_log_struct.is_bot,
+            },
+            self.options.session_gap,
+        )?;
+        Ok(true)
+    }
+
+    pub fn get_session_stats(&self, filter: &Filter) -> StorageResult<SessionStats> {
+        session_stats(self.storage.borrow_mut().as_mut(), filter)
+    }
+
+    pub fn get_hosts(&self) -> StorageResult<Vec<String>> {
+        stats::hosts(self.storage.borrow_mut().as_mut())
+    }
+
+    /// How `current`, the stats of `filter`, moved against the same filter over the previous
+    /// equally long range. Nothing moved when the range isn't closed on both ends.
+    pub fn get_changes(&self, filter: &Filter, current: &Stats) -> StorageResult<Changes> {
+        Ok(match filter.previous() {
+            Some(previous) => Changes::between(current, &self.get_stats(&previous)?),
+            None => Changes::default(),
+        })
+    }
+
+    /// Totals of the requests `filter` selects per hour or day, for charts.
+    pub fn get_timeseries(&self, filter: &Filter, step: Period) -> StorageResult<Vec<TimePoint>> {
+        timeseries::timeseries(self.storage.borrow_mut().as_mut(), filter, step)
+    }
+
+    /// Bytes sent by path, country and bot of the requests `filter` selects.
+    pub fn get_bandwidth(&self, filter: &Filter) -> StorageResult<Bandwidth> {
+        bandwidth::bandwidth(self.storage.borrow_mut().as_mut(), filter)
+    }
+
+    /// Status classes, broken links and server errors of the requests `filter` selects.
+    pub fn get_status_report(&self, filter: &Filter) -> StorageResult<StatusReport> {
+        status::status_report(self.storage.borrow_mut().as_mut(), filter)
+    }
+
+    /// The report of the requests `filter` selects.
+    pub fn get_stats(&self, filter: &Filter) -> StorageResult<Stats> {
+        stats::stats(self.storage.borrow_mut().as_mut(), filter)
+    }
+
+    /// Deletes what `retention` no longer keeps, as of `now` (unix milliseconds), in batches
+    /// of `batch_size()`, and compacts the database afterwards.
+    pub fn prune(&self, retention: &Retention, now: i64) -> Result<Pruned, &'static str> {
+        let mut storage = self.storage.borrow_mut();
+        let pruned = retention::prune(storage.as_mut(), retention, now, self.batch_size())
+            .map_err(|err| {
+                eprintln!("{err}");
+                "could not prune the database"
+            })?;
+        storage.vacuum().map_err(|err| {
+            eprintln!("{err}");
+            "could not vacuum the database"
+        })?;
+        Ok(pruned)
     }
 
-    pub fn fetch_last_known_entry_date(&self) -> Option<i64> {
-        let mut query = self.connection.prepare(
-            "SELECT max(timestamp) from access_log;"
-            ).unwrap();
-        query.query_one([], |x| x.get(0)).optional().unwrap()
+    /// Streams the stored rows matching `filter` to `write`, `batch_size()` rows at a time,
+    /// and returns how many there were.
+    pub fn export<F>(&self, filter: &Filter, mut write: F) -> Result<usize, &'static str>
+    where
+        F: FnMut(&[AccessRecord]) -> Result<(), &'static str>,
+    {
+        export::export(
+            self.storage.borrow_mut().as_mut(),
+            filter,
+            self.batch_size(),
+            &mut write,
+        )
+    }
+
+    pub fn fetch_last_known_entry_date(&self) -> StorageResult<Option<i64>> {
+        self.storage
+            .borrow_mut()
+            .query_row(
+                "SELECT last_timestamp FROM ingest_checkpoint WHERE id = 1;",
+                values![],
+            )?
+            .map(|row| row.get(0))
+            .transpose()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    pub(crate) fn test_db(options: DbOptions) -> Db {
+        Db::with_storage(Box::new(SqliteStorage::in_memory().unwrap()), options).unwrap()
+    }
+
+    pub(crate) fn insert(db: &Db, line: &str, is_bot: bool, kind: RequestKind) {
+        let log_struct = LogStruct::from_line(line).unwrap();
+        let enriched = EnrichedLog {
+            is_bot,
+            country: String::from("Germany"),
+            city: String::from("Berlin"),
+            is_vpn: false,
+            kind,
+        };
+        // every line is its own log file, the same line twice is read twice
+        let position = Position {
+            source: line,
+            offset: 0,
+        };
+        db.insert_record(&log_struct, &enriched, position).unwrap();
+    }
+
+    fn line(addr: &str, time: &str, uri: &str) -> String {
+        format!(
+            "{addr}\t-\t2025-01-01T{time}+00:00\tGET\thttps\texample.com\t{uri}\tHTTP/2.0\t200\t512\t0.020\t0.010\thttps://news.example/\tMozilla/5.0"
+        )
+    }
+
+    #[test]
+    fn inserts_batches_with_their_checkpoint() {
+        let db = test_db(DbOptions::default());
+        assert_eq!(db.fetch_last_known_entry_date().unwrap(), None);
+        // two requests for the same page in the same second are two lines
+        let lines = [
+            line("203.0.113.7", "10:00:00", "/"),
+            line("203.0.113.7", "10:00:00", "/"),
+            line("203.0.113.7", "10:05:00", "/about"),
+        ];
+        let records = read(&lines, false);
+        let source = log_source(&lines[0]);
+        let positions = positions(&source, &lines);
+        let batch = || {
+            records
+                .iter()
+                .zip(&positions)
+                .map(|((log_struct, enriched), position)| (log_struct, enriched, *position))
+        };
+        let inserted = db.insert_batch(batch()).unwrap();
+        assert_eq!(
+            inserted,
+            Ingested {
+                inserted: 3,
+                ..Ingested::default()
+            }
+        );
+        assert_eq!(
+            db.fetch_last_known_entry_date().unwrap(),
+            Some(1_735_725_900_000)
+        );
+        assert_eq!(
+            db.get_stats(&Filter::new().host("example.com"))
+                .unwrap()
+                .sessions
+                .sessions,
+            1
+        );
+
+        // the same lines again, say from an archive read twice
+        let again = db.insert_batch(batch()).unwrap();
+        assert_eq!(
+            again,
+            Ingested {
+                duplicates: 3,
+                ..Ingested::default()
+            }
+        );
+        let stats = db.get_stats(&Filter::new().host("example.com")).unwrap();
+        assert_eq!(stats.total_requests, 3);
+        assert_eq!(stats.sessions.avg_pages, 3.0);
+    }
+
+    /// `lines` parsed, as pageviews of humans or bots.
+    fn read(lines: &[String], is_bot: bool) -> Vec<(LogStruct<'_>, EnrichedLog)> {
+        lines
+            .iter()
+            .map(|line| {
+                let enriched = EnrichedLog {
+                    is_bot,
+                    country: String::from("Germany"),
+                    city: String::from("Berlin"),
+                    is_vpn: false,
+                    kind: RequestKind::Pageview,
+                };
+                (LogStruct::from_line(line).unwrap(), enriched)
+            })
+            .collect()
+    }
+
+    /// Where `lines` are in a log file of them, one after the other.
+    fn positions<'s>(source: &'s str, lines: &[String]) -> Vec<Position<'s>> {
+        let mut offset = 0;
+        lines
+            .iter()
+            .map(|line| {
+                let position = Position { source, offset };
+                offset += line.len() as u64 + 1;
+                position
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_identical_lines_of_neighbours_in_privacy_mode() {
+        let db = test_db(DbOptions {
+            privacy: Some(Privacy::default()),
+            ..DbOptions::default()
+        });
+        // the same /24, the addresses are the same once truncated
+        let lines = [
+            line("203.0.113.7", "10:00:00", "/"),
+            line("203.0.113.9", "10:00:00", "/"),
+        ];
+        let records = read(&lines, false);
+        let source = log_source(&lines[0]);
+        let ingested = db
+            .insert_batch(
+                records
+                    .iter()
+                    .zip(positions(&source, &lines))
+                    .map(|((log_struct, enriched), position)| (log_struct, enriched, position)),
+            )
+            .unwrap();
+        assert_eq!(ingested.inserted, 2);
+        let stats = db.get_stats(&Filter::new().host("example.com")).unwrap();
+        assert_eq!((stats.total_requests, stats.unique_visitors), (2, 2));
+    }
+
+    #[test]
+    fn finds_stored_lines_after_privacy_mode_is_turned_on() {
+        let mut db = test_db(DbOptions::default());
+        let lines = [
+            line("203.0.113.7", "10:00:00", "/"),
+            line("203.0.113.7", "10:05:00", "/about"),
+        ];
+        let records = read(&lines, false);
+        let source = log_source(&lines[0]);
+        let batch = || {
+            records
+                .iter()
+                .zip(positions(&source, &lines))
+                .map(|((log_struct, enriched), position)| (log_struct, enriched, position))
+        };
+        assert_eq!(db.insert_batch(batch()).unwrap().inserted, 2);
+        db.options.privacy = Some(Privacy::default());
+        assert_eq!(db.insert_batch(batch()).unwrap().duplicates, 2);
+    }
+
+    #[test]
+    fn keeps_the_salt_of_a_day_across_files() {
+        let db = test_db(DbOptions {
+            privacy: Some(Privacy::default()),
+            ..DbOptions::default()
+        });
+        let on = |day: &str, time: &str| {
+            format!(
+                "203.0.113.7\t-\t2025-01-{day}T{time}+00:00\tGET\thttps\texample.com\t/\tHTTP/2.0\t200\t512\t0.020\t0.010\t-\tMozilla/5.0"
+            )
+        };
+        // a rotated archive up to the 3rd, then another vhost's log with the 1st again
+        for line in [
+            on("01", "10:00:00"),
+            on("03", "10:00:00"),
+            on("01", "18:00:00"),
+        ] {
+            insert(&db, &line, false, RequestKind::Pageview);
+        }
+        let first_day = Filter::new()
+            .host("example.com")
+            .since(1_735_689_600_000)
+            .until(1_735_776_000_000);
+        assert_eq!(db.get_stats(&first_day).unwrap().unique_visitors, 1);
+
+        // on the 3rd only the salt of the 1st is too old
+        assert_eq!(
+            db.expire_salts(1_735_689_600_000 + 2 * privacy::DAY_MS),
+            Ok(1)
+        );
+    }
+
+    fn check_host_stats(db: &Db) {
+        insert(
+            db,
+            &line("203.0.113.7", "10:00:00", "/"),
+            false,
+            RequestKind::Pageview,
+        );
+        insert(
+            db,
+            &line("203.0.113.7", "10:00:01", "/app.css"),
+            false,
+            RequestKind::Asset,
+        );
+        insert(
+            db,
+            &line("203.0.113.7", "10:02:00", "/about"),
+            false,
+            RequestKind::Pageview,
+        );
+        insert(
+            db,
+            &line("198.51.100.4", "11:00:00", "/"),
+            true,
+            RequestKind::Pageview,
+        );
+
+        assert_eq!(db.get_hosts().unwrap(), vec![String::from("example.com")]);
+        let stats = db.get_stats(&Filter::new().host("example.com")).unwrap();
+        assert_eq!(stats.total_requests, 4);
+        assert_eq!(stats.pageviews, 3);
+        assert_eq!(stats.unique_visitors, 2);
+        assert_eq!(stats.human_requests, 3);
+        assert_eq!(stats.bot_requests, 1);
+        assert!((stats.avg_response_time - 20.0).abs() < 0.001);
+        assert_eq!(stats.bytes_sent, 2048);
+        assert_eq!(stats.pages[0], (String::from("/"), 2));
+        assert_eq!(stats.countries, vec![(String::from("Germany"), 4)]);
+        assert_eq!(stats.sessions.sessions, 1);
+        assert_eq!(stats.sessions.bounces, 0);
+        // every line took 20 ms, 10 of them upstream
+        assert!((20.0..=25.0).contains(&stats.latency.request_time.p99));
+        assert!((10.0..=12.5).contains(&stats.latency.upstream_time.unwrap().p50));
+
+        assert_eq!(
+            db.fetch_last_known_entry_date().unwrap(),
+            Some(1_735_729_200_000)
+        );
+
+        let later = db
+            .get_stats(&Filter::new().host("example.com").since(1_735_729_200_000))
+            .unwrap();
+        assert_eq!(later.total_requests, 1);
+        // mid hour, the start of the range comes from access_log and the rest from rollups
+        let partial = db
+            .get_stats(&Filter::new().host("example.com").since(1_735_725_660_000))
+            .unwrap();
+        assert_eq!(partial.total_requests, 2);
+        assert_eq!(partial.unique_visitors, 2);
+        assert_eq!(partial.bot_requests, 1);
+        assert_eq!(
+            partial.pages,
+            vec![(String::from("/"), 1), (String::from("/about"), 1)]
+        );
+        assert!((20.0..=25.0).contains(&partial.latency.request_time.p50));
+        assert_eq!(
+            db.get_stats(&Filter::new().host("other.example"))
+                .unwrap()
+                .total_requests,
+            0
+        );
+
+        // anything beyond host and time is read from access_log
+        let host = Filter::new().host("example.com");
+        let total = |filter: Filter| db.get_stats(&filter).unwrap().total_requests;
+        assert_eq!(total(host.clone().until(1_735_729_200_000)), 3);
+        assert_eq!(total(host.clone().path_prefix("/ab")), 1);
+        assert_eq!(total(host.clone().status_class(2)), 4);
+        assert_eq!(total(host.clone().status_class(4)), 0);
+        assert_eq!(total(host.clone().country("France")), 0);
+        assert_eq!(
+            total(host.clone().referrer(ReferrerSource::parse("news.example"))),
+            4
+        );
+        assert_eq!(total(host.clone().referrer(ReferrerSource::Direct)), 0);
+        // the site is no pattern
+        assert_eq!(
+            total(host.clone().referrer(ReferrerSource::parse("news_example"))),
+            0
+        );
+        assert_eq!(total(host.clone().user_agent(UaFamily::Other)), 4);
+        assert_eq!(total(host.clone().user_agent(UaFamily::Firefox)), 0);
+        let bots = db.get_stats(&host.clone().traffic(Traffic::Bots)).unwrap();
+        assert_eq!((bots.total_requests, bots.unique_visitors), (1, 1));
+        assert_eq!(bots.pages, vec![(String::from("/"), 1)]);
+        assert_eq!(bots.sessions.sessions, 1);
+        // sessions with a request that matches
+        let sessions = |filter: Filter| db.get_session_stats(&filter).unwrap().sessions;
+        assert_eq!(sessions(host.clone().country("Germany")), 1);
+        assert_eq!(sessions(host.clone().country("France")), 0);
+        assert_eq!(sessions(host.clone().path_prefix("/ab")), 1);
+        assert_eq!(
+            sessions(host.clone().traffic(Traffic::Bots).country("Germany")),
+            1
+        );
+        let humans = db
+            .get_stats(&Filter::new().traffic(Traffic::Humans).path_prefix("/"))
+            .unwrap();
+        assert_eq!((humans.total_requests, humans.pageviews), (3, 2));
+
+        let hourly = db.get_timeseries(&host, Period::Hour).unwrap();
+        let requests: Vec<(i32, i32)> = hourly
+            .iter()
+            .map(|point| (point.requests, point.unique_visitors))
+            .collect();
+        assert_eq!(requests, vec![(3, 1), (1, 1)]);
+        let human_hours = db
+            .get_timeseries(&host.clone().traffic(Traffic::Humans), Period::Hour)
+            .unwrap();
+        assert_eq!(human_hours[0].pageviews, 2);
+        assert_eq!(human_hours.len(), 1);
+        let daily = db
+            .get_timeseries(&host.clone().since(1_735_689_600_000), Period::Day)
+            .unwrap();
+        assert_eq!(
+            (daily.len(), daily[0].requests, daily[0].bot_requests),
+            (1, 4, 1)
+        );
+        assert!((daily[0].avg_response_time - 20.0).abs() < 0.001);
+
+        // the bot's hour against the hour before it
+        let eleven = host
+            .clone()
+            .since(1_735_729_200_000)
+            .until(1_735_732_800_000);
+        let changes = db
+            .get_changes(&eleven, &db.get_stats(&eleven).unwrap())
+            .unwrap();
+        assert_eq!(changes.pageviews, Some(-50.0));
+        assert_eq!(changes.bot_share, Some(100.0));
+        assert!((changes.total_requests.unwrap() + 66.667).abs() < 0.001);
+        assert_eq!(db.get_changes(&host, &stats).unwrap(), Changes::default());
+
+        let status = db.get_status_report(&host).unwrap();
+        assert_eq!(status.classes, [0, 4, 0, 0, 0]);
+        assert_eq!((status.human_errors, status.bot_errors), (0, 0));
+        assert!(status.not_found.is_empty() && status.server_errors.is_empty());
+        assert_eq!(hourly[0].status, [0, 3, 0, 0, 0]);
+        assert_eq!(hourly[1].bytes_sent, 512);
+
+        // every response was 512 bytes, the bot's user agent names no crawler
+        let bandwidth = db.get_bandwidth(&host).unwrap();
+        assert_eq!(bandwidth.paths[0], (String::from("/"), 1024));
+        assert_eq!(bandwidth.paths.len(), 3);
+        assert_eq!(bandwidth.countries, vec![(String::from("Germany"), 2048)]);
+        assert_eq!(bandwidth.bots, vec![(String::from("other"), 512)]);
+        let raw = db
+            .get_bandwidth(&host.clone().since(1_735_725_660_000))
+            .unwrap();
+        assert_eq!(
+            raw.paths,
+            vec![(String::from("/"), 512), (String::from("/about"), 512)]
+        );
+        assert_eq!(raw.bots, vec![(String::from("other"), 512)]);
+        assert!(
+            db.get_bandwidth(&host.clone().traffic(Traffic::Humans))
+                .unwrap()
+                .bots
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn computes_host_stats() {
+        check_host_stats(&test_db(DbOptions::default()));
+    }
+
+    #[test]
+    fn reports_the_slowest_endpoints() {
+        let db = test_db(DbOptions::default());
+        for (i, seconds) in ["0.900", "1.100", "1.200", "1.300", "2.400"]
+            .iter()
+            .enumerate()
+        {
+            let slow = format!(
+                "203.0.113.7\t-\t2025-01-01T10:0{i}:00+00:00\tGET\thttps\texample.com\t/search?q={i}\tHTTP/2.0\t200\t512\t{seconds}\t-\t-\tMozilla/5.0"
+            );
+            insert(&db, &slow, false, RequestKind::Pageview);
+            insert(
+                &db,
+                &line("203.0.113.7", &format!("11:0{i}:00"), "/"),
+                false,
+                RequestKind::Pageview,
+            );
+        }
+        insert(
+            &db,
+            &line("203.0.113.7", "12:00:00", "/about"),
+            false,
+            RequestKind::Pageview,
+        );
+
+        let latency = db
+            .get_stats(&Filter::new().host("example.com"))
+            .unwrap()
+            .latency;
+        let paths: Vec<&str> = latency
+            .slowest
+            .iter()
+            .map(|endpoint| endpoint.path.as_str())
+            .collect();
+        // /about has too few requests to tell
+        assert_eq!(paths, vec!["/search", "/"]);
+        let search = &latency.slowest[0];
+        assert_eq!(search.requests, 5);
+        assert!((1000.0..=1250.0).contains(&search.request_time.p50));
+        assert!((2000.0..=2500.0).contains(&search.request_time.p99));
+        assert_eq!(search.upstream_time, None);
+        assert!(latency.slowest[1].upstream_time.is_some());
+    }
+
+    #[test]
+    fn reports_broken_links_and_server_errors() {
+        let db = test_db(DbOptions::default());
+        let request = |time: &str, uri: &str, status: u16, referrer: &str, is_bot: bool| {
+            let line = format!(
+                "203.0.113.7\t-\t2025-01-01T{time}+00:00\tGET\thttps\texample.com\t{uri}\tHTTP/2.0\t{status}\t512\t0.020\t0.010\t{referrer}\tMozilla/5.0"
+            );
+            insert(&db, &line, is_bot, RequestKind::Pageview);
+        };
+        request(
+            "10:00:00",
+            "/old?page=2",
+            404,
+            "https://news.example/",
+            false,
+        );
+        request("10:01:00", "/old", 404, "https://news.example/", false);
+        request("10:02:00", "/old", 404, "-", false);
+        request("10:03:00", "/wp-login.php", 404, "-", true);
+        request("10:04:00", "/api", 500, "-", false);
+        request("10:05:00", "/api", 502, "-", true);
+        request("10:06:00", "/", 200, "-", false);
+
+        let host = Filter::new().host("example.com");
+        let status = db.get_status_report(&host).unwrap();
+        assert_eq!(status.classes, [0, 1, 0, 4, 2]);
+        assert_eq!((status.human_errors, status.bot_errors), (4, 2));
+        let old = &status.not_found[0];
+        assert_eq!((old.path.as_str(), old.hits), ("/old", 3));
+        assert_eq!(
+            old.referrers,
+            vec![(String::from("https://news.example/"), 2)]
+        );
+        assert_eq!(status.not_found[1].referrers, vec![]);
+        let api = &status.server_errors[0];
+        assert_eq!(
+            (status.server_errors.len(), api.path.as_str(), api.requests),
+            (1, "/api", 2)
+        );
+        assert!((20.0..=25.0).contains(&api.request_time.p50));
+
+        let humans = db
+            .get_status_report(&host.clone().traffic(Traffic::Humans))
+            .unwrap();
+        assert_eq!(humans.classes, [0, 1, 0, 3, 1]);
+        let client_errors = db.get_status_report(&host.clone().status_class(4)).unwrap();
+        assert!(client_errors.server_errors.is_empty());
+        assert_eq!(client_errors.not_found.len(), 2);
+    }
+
+    #[test]
+    fn names_the_bots_bandwidth_goes_to() {
+        let db = test_db(DbOptions::default());
+        let crawl = |time: &str, bytes: u32, user_agent: &str| {
+            let line = format!(
+                "66.249.66.1\t-\t2025-01-01T{time}+00:00\tGET\thttps\texample.com\t/feed?page=1\tHTTP/2.0\t200\t{bytes}\t0.020\t-\t-\t{user_agent}"
+            );
+            insert(&db, &line, true, RequestKind::Asset);
+        };
+        crawl("10:00:00", 4000, "Mozilla/5.0 (compatible; Googlebot/2.1)");
+        crawl("10:30:00", 1000, "Mozilla/5.0 (compatible; bingbot/2.0)");
+        crawl("11:30:00", 3000, "Mozilla/5.0 (compatible; Googlebot/2.1)");
+
+        let host = Filter::new().host("example.com");
+        let bandwidth = db.get_bandwidth(&host).unwrap();
+        let googlebot = (String::from("googlebot"), 7000);
+        assert_eq!(
+            bandwidth.bots,
+            vec![googlebot.clone(), (String::from("bingbot"), 1000)]
+        );
+        assert_eq!(bandwidth.paths, vec![(String::from("/feed"), 8000)]);
+        // the first hour from the rollups, the rest from access_log
+        let partial = host
+            .clone()
+            .since(1_735_725_600_000)
+            .until(1_735_731_900_000);
+        assert_eq!(db.get_bandwidth(&partial).unwrap().bots, bandwidth.bots);
+        let google = db
+            .get_bandwidth(&host.clone().user_agent(UaFamily::Other))
+            .unwrap();
+        assert_eq!(google.bots[0], googlebot);
+    }
+
+    #[test]
+    fn prunes_raw_rows_and_keeps_rollups() {
+        let db = test_db(DbOptions::default());
+        insert(
+            &db,
+            &line("203.0.113.7", "10:00:00", "/"),
+            false,
+            RequestKind::Pageview,
+        );
+        insert(
+            &db,
+            &line("198.51.100.4", "11:00:00", "/"),
+            true,
+            RequestKind::Pageview,
+        );
+        let now = 1_735_725_600_000 + 10 * privacy::DAY_MS;
+
+        let bots = Retention {
+            bot_days: Some(7),
+            ..Retention::default()
+        };
+        let pruned = db.prune(&bots, now).unwrap();
+        assert_eq!(
+            (pruned.bot_rows, pruned.raw_rows, pruned.sessions),
+            (1, 0, 1)
+        );
+
+        let raw = Retention {
+            raw_days: Some(7),
+            hourly_days: Some(7),
+            ..Retention::default()
+        };
+        let pruned = db.prune(&raw, now).unwrap();
+        assert_eq!((pruned.raw_rows, pruned.sessions), (1, 1));
+        assert_eq!((pruned.hourly_rollups, pruned.daily_rollups), (2, 0));
+        // reading the pruned lines again from a log that was rotated since
+        let lines = [
+            line("203.0.113.7", "10:00:00", "/"),
+            line("198.51.100.4", "11:00:00", "/"),
+        ];
+        let again = db
+            .insert_batch(
+                read(&lines, false)
+                    .iter()
+                    .zip(positions("rotated", &lines))
+                    .map(|((log_struct, enriched), position)| (log_struct, enriched, position)),
+            )
+            .unwrap();
+        assert_eq!(
+            again,
+            Ingested {
+                too_old: 2,
+                ..Ingested::default()
+            }
+        );
+        let stats = db.get_stats(&Filter::new().host("example.com")).unwrap();
+        assert_eq!(stats.total_requests, 2);
+        assert_eq!(stats.unique_visitors, 2);
+        assert_eq!(stats.pages, vec![(String::from("/"), 2)]);
+        assert_eq!(
+            db.fetch_last_known_entry_date().unwrap(),
+            Some(1_735_729_200_000)
+        );
+
+        let everything = Retention {
+            daily_days: Some(7),
+            ..Retention::default()
+        };
+        assert_eq!(db.prune(&everything, now).unwrap().daily_rollups, 1);
+        assert!(db.get_hosts().unwrap().is_empty());
+        let leftovers: i64 = db
+            .storage
+            .borrow_mut()
+            .query_row("SELECT count(*) FROM rollup_dimensions;", values![])
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn reads_pruned_edges_from_the_rollups() {
+        let db = test_db(DbOptions::default());
+        for time in ["10:00:00", "10:02:00", "11:00:00"] {
+            insert(
+                &db,
+                &line("203.0.113.7", time, "/"),
+                false,
+                RequestKind::Pageview,
+            );
+        }
+        // 12 months back from a year later, starting half an hour before the first request
+        let since = 1_735_723_800_000;
+        let now = since + 365 * privacy::DAY_MS;
+        let filter = Filter::new().host("example.com").since(since).until(now);
+        let before = db.get_stats(&filter).unwrap();
+        assert_eq!(before.total_requests, 3);
+
+        let daily = Retention {
+            daily_days: Some(90),
+            ..Retention::default()
+        };
+        db.prune(&daily, now).unwrap();
+        assert_eq!(db.get_hosts().unwrap(), vec![String::from("example.com")]);
+        assert_eq!(db.get_stats(&filter).unwrap().total_requests, 3);
+
+        let db = test_db(DbOptions::default());
+        for time in ["10:00:00", "10:02:00", "11:00:00"] {
+            insert(
+                &db,
+                &line("203.0.113.7", time, "/"),
+                false,
+                RequestKind::Pageview,
+            );
+        }
+        let raw = Retention {
+            raw_days: Some(90),
+            hourly_days: Some(90),
+            ..Retention::default()
+        };
+        db.prune(&raw, now).unwrap();
+        let after = db.get_stats(&filter).unwrap();
+        assert_eq!(after.total_requests, before.total_requests);
+        assert_eq!(after.unique_visitors, before.unique_visitors);
+        assert_eq!(after.pages, before.pages);
+    }
+
+    #[test]
+    fn exports_filtered_rows_in_batches() {
+        let db = test_db(DbOptions {
+            batch_size: 2,
+            ..DbOptions::default()
+        });
+        insert(
+            &db,
+            &line("203.0.113.7", "10:00:00", "/"),
+            false,
+            RequestKind::Pageview,
+        );
+        insert(
+            &db,
+            &line("203.0.113.7", "10:00:01", "/app.css"),
+            false,
+            RequestKind::Asset,
+        );
+        insert(
+            &db,
+            &line("203.0.113.7", "10:02:00", "/about"),
+            false,
+            RequestKind::Pageview,
+        );
+        insert(
+            &db,
+            &line("198.51.100.4", "11:00:00", "/"),
+            true,
+            RequestKind::Pageview,
+        );
+
+        let mut batches = vec![];
+        let filter = Filter::new().host("example.com").since(1_735_725_601_000);
+        let exported = db
+            .export(&filter, |records| {
+                batches.push(records.to_vec());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(exported, 3);
+        assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 1]);
+        let first = &batches[0][0];
+        assert_eq!(first.request_uri, "/app.css");
+        assert_eq!(first.request_kind, "asset");
+        assert_eq!(first.status, 200);
+        assert_eq!(first.http_referer.as_deref(), Some("https://news.example/"));
+        assert!(batches[1][0].is_bot);
+
+        let other = Filter::new().host("other.example");
+        assert_eq!(db.export(&other, |_| Ok(())).unwrap(), 0);
+    }
+
+    #[test]
+    fn merges_rows_of_another_database() {
+        let source = test_db(DbOptions::default());
+        insert(
+            &source,
+            &line("203.0.113.7", "10:00:00", "/"),
+            false,
+            RequestKind::Pageview,
+        );
+        insert(
+            &source,
+            &line("203.0.113.7", "10:02:00", "/about"),
+            false,
+            RequestKind::Pageview,
+        );
+        let target = test_db(DbOptions::default());
+        insert(
+            &target,
+            &line("203.0.113.7", "09:00:00", "/"),
+            false,
+            RequestKind::Pageview,
+        );
+        insert(
+            &target,
+            &line("203.0.113.7", "10:00:00", "/"),
+            false,
+            RequestKind::Pageview,
+        );
+
+        let mut ingested = Ingested::default();
+        source
+            .export(&Filter::new(), |records| {
+                ingested = target.import_records(records)?;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            ingested,
+            Ingested {
+                inserted: 1,
+                duplicates: 1,
+                ..Ingested::default()
+            }
+        );
+        assert_eq!(
+            target
+                .get_stats(&Filter::new().host("example.com"))
+                .unwrap()
+                .total_requests,
+            3
+        );
+        assert_eq!(
+            target
+                .get_stats(&Filter::new().host("example.com"))
+                .unwrap()
+                .sessions
+                .sessions,
+            2
+        );
+        // the logs of the target still pick up after its own last line
+        assert_eq!(
+            target.fetch_last_known_entry_date().unwrap(),
+            Some(1_735_725_600_000)
+        );
+    }
+
+    #[test]
+    fn merges_visitors_of_a_privacy_mode_database() {
+        let options = DbOptions {
+            privacy: Some(Privacy::default()),
+            ..DbOptions::default()
+        };
+        let source = test_db(options.clone());
+        // two visitors of the same /24, only told apart by the hashes
+        for (addr, time) in [("203.0.113.7", "10:00:00"), ("203.0.113.9", "10:05:00")] {
+            insert(
+                &source,
+                &line(addr, time, "/"),
+                false,
+                RequestKind::Pageview,
+            );
+        }
+        let host = Filter::new().host("example.com");
+        assert_eq!(source.get_stats(&host).unwrap().unique_visitors, 2);
+
+        let target = test_db(options);
+        source
+            .export(&Filter::new(), |records| {
+                target.import_records(records)?;
+                Ok(())
+            })
+            .unwrap();
+        let stats = target.get_stats(&host).unwrap();
+        assert_eq!((stats.total_requests, stats.unique_visitors), (2, 2));
+        assert_eq!(stats.sessions.sessions, 2);
+    }
+
+    #[test]
+    fn imports_summaries_into_daily_rollups() {
+        let db = test_db(DbOptions::default());
+        insert(
+            &db,
+            &line("203.0.113.7", "10:00:00", "/"),
+            false,
+            RequestKind::Pageview,
+        );
+        let day = |day: i64, requests: i64| DaySummary {
+            day: 1_735_689_600_000 + day * privacy::DAY_MS,
+            requests,
+            pageviews: requests / 2,
+            visitors: 3,
+            bytes_sent: 1000,
+        };
+        let summary = Summary {
+            host: String::from("example.com"),
+            days: vec![day(-1, 10), day(-2, 20)],
+            status: [0, 25, 0, 5, 0],
+            pages: vec![(String::from("/about"), 7)],
+            countries: vec![(String::from("France"), 4)],
+            referrers: vec![],
+        };
+        let imported = db.import_summary(&summary).unwrap();
+        assert_eq!(
+            imported,
+            SummaryImport {
+                days: 2,
+                skipped_days: 0,
+                totals: true
+            }
+        );
+
+        let stats = db.get_stats(&Filter::new().host("example.com")).unwrap();
+        assert_eq!(stats.total_requests, 31);
+        assert_eq!(stats.pageviews, 16);
+        assert_eq!(stats.unique_visitors, 7);
+        // only the logged line is known to come from a human
+        assert_eq!((stats.human_requests, stats.bot_requests), (1, 0));
+        assert_eq!(stats.pages[0], (String::from("/about"), 7));
+        assert_eq!(stats.countries[0], (String::from("France"), 4));
+
+        // a longer report that overlaps what's there, the first day is covered by the logs
+        let longer = Summary {
+            days: vec![day(-3, 5), day(-2, 20), day(0, 99)],
+            ..summary
+        };
+        assert_eq!(
+            db.import_summary(&longer).unwrap(),
+            SummaryImport {
+                days: 1,
+                skipped_days: 2,
+                totals: false
+            }
+        );
+        let stats = db.get_stats(&Filter::new().host("example.com")).unwrap();
+        assert_eq!(stats.total_requests, 36);
+        assert_eq!(stats.pages[0], (String::from("/about"), 7));
+        assert_eq!(stats.countries[0], (String::from("France"), 4));
+    }
+
+    /// Runs against the database in `KIRINOX_TEST_POSTGRES`, for example
+    /// `KIRINOX_TEST_POSTGRES="host=localhost user=postgres dbname=kirinox_test"`, inside a
+    /// schema of its own that is dropped afterwards.
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_backend_matches_sqlite() {
+        let Ok(url) = std::env::var("KIRINOX_TEST_POSTGRES") else {
+            eprintln!("KIRINOX_TEST_POSTGRES is not set, skipping");
+            return;
+        };
+        let schema = format!("kirinox_test_{}", std::process::id());
+        let mut storage = PostgresStorage::connect(&url).unwrap();
+        storage
+            .execute_batch(&format!(
+                "CREATE SCHEMA {schema}; SET search_path TO {schema};"
+            ))
+            .unwrap();
+        let options = DbOptions {
+            privacy: Some(Privacy::default()),
+            ..DbOptions::default()
+        };
+        let db = Db::with_storage(Box::new(storage), options).unwrap();
+        check_host_stats(&db);
+        let everything = Retention {
+            raw_days: Some(0),
+            bot_days: Some(0),
+            hourly_days: Some(0),
+            daily_days: Some(0),
+        };
+        let pruned = db
+            .prune(&everything, 1_735_725_600_000 + privacy::DAY_MS)
+            .unwrap();
+        assert_eq!(
+            (pruned.raw_rows, pruned.bot_rows, pruned.daily_rollups),
+            (3, 1, 1)
+        );
+        assert!(db.get_hosts().unwrap().is_empty());
+        db.storage
+            .borrow_mut()
+            .execute_batch(&format!("DROP SCHEMA {schema} CASCADE;"))
+            .unwrap();
+    }
 }
@@ -0,0 +1,320 @@
+use crate::{
+    privacy::hash_visitor,
+    storage::{Dialect, Storage, StorageResult, Value},
+    values,
+};
+
+struct Migration {
+    version: i32,
+    name: &'static str,
+    sqlite: &'static str,
+    postgres: &'static str,
+    /// work SQL can't do on both backends, run before the SQL
+    prepare: Option<fn(&mut dyn Storage) -> StorageResult<()>>,
+}
+
+impl Migration {
+    fn sql(&self, dialect: Dialect) -> &'static str {
+        match dialect {
+            Dialect::Sqlite => self.sqlite,
+            Dialect::Postgres => self.postgres,
+        }
+    }
+}
+
+macro_rules! migration {
+    ($version:expr, $name:expr, $file:expr) => {
+        migration!($version, $name, $file, None)
+    };
+    ($version:expr, $name:expr, $file:expr, $prepare:expr) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sqlite: include_str!(concat!("../migrations/sqlite/", $file)),
+            postgres: include_str!(concat!("../migrations/postgres/", $file)),
+            prepare: $prepare,
+        }
+    };
+}
+
+/// Rows hashed by one `UPDATE` of `hash_missing_visitors`.
+const HASH_BATCH: usize = 1000;
+
+/// Gives the rows stored before visitor hashes the unsalted hash new rows outside privacy
+/// mode get, so the rollups count no raw address. A batch at a time, a large database is
+/// never read in one go.
+fn hash_missing_visitors(storage: &mut dyn Storage) -> StorageResult<()> {
+    loop {
+        let rows = storage.query(
+            "SELECT id, remote_addr, coalesce(http_user_agent, '') FROM access_log
+            WHERE visitor_hash IS NULL LIMIT ?;",
+            values![HASH_BATCH],
+        )?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut params = Vec::with_capacity(rows.len() * 2);
+        for row in &rows {
+            let remote_addr: String = row.get(1)?;
+            let user_agent: String = row.get(2)?;
+            params.push(Value::from(row.get::<i64>(0)?));
+            params.push(Value::from(hash_visitor(&[], &remote_addr, &user_agent)));
+        }
+        storage.execute(
+            &format!(
+                "UPDATE access_log SET visitor_hash = hashes.column2
+                FROM (VALUES {}) AS hashes WHERE access_log.id = hashes.column1;",
+                vec!["(CAST(? AS BIGINT), ?)"; rows.len()].join(", ")
+            ),
+            &params,
+        )?;
+    }
+}
+
+/// Every schema change, in order, written once per backend so both end up with the same
+/// tables. The database remembers the last applied one (`PRAGMA user_version` in SQLite, the
+/// `schema_version` table in Postgres), so a migration must never be edited once released,
+/// add a new one.
+const MIGRATIONS: &[Migration] = &[
+    migration!(1, "access_log", "0001_access_log.sql"),
+    migration!(2, "privacy", "0002_privacy.sql"),
+    migration!(3, "sessions", "0003_sessions.sql"),
+    migration!(4, "request_kind", "0004_request_kind.sql"),
+    migration!(5, "stats_indexes", "0005_stats_indexes.sql"),
+    migration!(6, "ingest_checkpoint", "0006_ingest_checkpoint.sql"),
+    migration!(
+        7,
+        "rollups",
+        "0007_rollups.sql",
+        Some(hash_missing_visitors)
+    ),
+    migration!(8, "fingerprint", "0008_fingerprint.sql"),
+    migration!(9, "imported_visitors", "0009_imported_visitors.sql"),
+    migration!(10, "latency", "0010_latency.sql"),
+    migration!(11, "bandwidth", "0011_bandwidth.sql"),
+    migration!(12, "retention_horizons", "0012_retention_horizons.sql"),
+];
+
+pub const SCHEMA_VERSION: i32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// Brings the database up to `SCHEMA_VERSION`. All pending migrations run in one transaction,
+/// a failure leaves the database as it was. A database written by a newer kirinox is refused
+/// rather than guessed at.
+pub(crate) fn migrate(storage: &mut dyn Storage) -> Result<(), &'static str> {
+    let current = storage.schema_version().map_err(|err| {
+        eprintln!("{err}");
+        "could not read the schema version"
+    })?;
+    if current > SCHEMA_VERSION {
+        eprintln!(
+            "database schema is at version {current}, this build knows up to {SCHEMA_VERSION}"
+        );
+        return Err("the database was created by a newer version of kirinox");
+    }
+    if current == SCHEMA_VERSION {
+        return Ok(());
+    }
+    storage.begin().map_err(|err| {
+        eprintln!("{err}");
+        "could not start the migration transaction"
+    })?;
+    let dialect = storage.dialect();
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current)
+    {
+        let applied = migration
+            .prepare
+            .map_or(Ok(()), |prepare| prepare(storage))
+            .and_then(|_| storage.execute_batch(migration.sql(dialect)))
+            .and_then(|_| storage.set_schema_version(migration.version));
+        if let Err(err) = applied {
+            eprintln!(
+                "migration {} ({}) failed: {err}",
+                migration.version, migration.name
+            );
+            let _ = storage.rollback();
+            return Err("could not migrate the database");
+        }
+    }
+    storage.commit().map_err(|err| {
+        eprintln!("{err}");
+        "could not commit the migrations"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sqlite::SqliteStorage;
+
+    #[test]
+    fn migrates_from_scratch_and_is_idempotent() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        migrate(&mut storage).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+        migrate(&mut storage).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn upgrades_databases_created_before_versioning() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        storage.execute_batch(MIGRATIONS[0].sqlite).unwrap();
+        storage
+            .execute(
+                "INSERT INTO access_log (
+                    remote_addr, timestamp, method, scheme, http_host, request_uri,
+                    server_protocol, status, body_bytes_sent, request_time, is_bot, is_vpn
+                ) VALUES ('203.0.113.7', 0, 'GET', 'https', 'example.com', '/about', 'HTTP/2.0', 200, 10, 0.1, 0, 0);",
+                &[],
+            )
+            .unwrap();
+        migrate(&mut storage).unwrap();
+        let kind: String = storage
+            .query_row("SELECT request_kind FROM access_log;", &[])
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(kind, "pageview");
+        let checkpoint: i64 = storage
+            .query_row("SELECT last_timestamp FROM ingest_checkpoint;", &[])
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(checkpoint, 0);
+        let requests: i64 = storage
+            .query_row("SELECT requests FROM rollups WHERE period = 'day';", &[])
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(requests, 1);
+        let page: String = storage
+            .query_row(
+                "SELECT value FROM rollup_dimensions WHERE dimension = 'page';",
+                &[],
+            )
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(page, "/about");
+        let latency = storage
+            .query_row(
+                "SELECT path, latency_bucket FROM rollup_latency WHERE period = 'day';",
+                &[],
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(latency.get::<String>(0).unwrap(), "/about");
+        assert_eq!(
+            latency.get::<i64>(1).unwrap(),
+            crate::latency::latency_bucket(0.1)
+        );
+        let bytes: i64 = storage
+            .query_row(
+                "SELECT bytes FROM rollup_dimensions WHERE dimension = 'path' AND period = 'day';",
+                &[],
+            )
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(bytes, 10);
+        let page: i64 = storage
+            .query_row(
+                "SELECT bytes FROM rollup_dimensions WHERE dimension = 'page';",
+                &[],
+            )
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(page, 10);
+        // hashed, the raw address must not outlive the row in the rollups
+        let visitors: Vec<String> = storage
+            .query("SELECT visitor FROM rollup_visitors;", &[])
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0).unwrap())
+            .collect();
+        assert_eq!(visitors, vec![hash_visitor(&[], "203.0.113.7", ""); 2]);
+    }
+
+    /// A database from before versioning with more rows than one batch of hashes.
+    fn check_hashes_old_rows_in_batches(storage: &mut dyn Storage, first: &str) {
+        storage.execute_batch(first).unwrap();
+        storage
+            .execute(
+                "WITH RECURSIVE n (i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i <= ?)
+                INSERT INTO access_log (
+                    remote_addr, timestamp, method, scheme, http_host, request_uri,
+                    server_protocol, status, body_bytes_sent, request_time, is_bot, is_vpn
+                )
+                SELECT '203.0.113.' || (i % 200), i * 1000, 'GET', 'https', 'example.com', '/',
+                    'HTTP/2.0', 200, 10, 0.1, 0, 0
+                FROM n;",
+                values![HASH_BATCH],
+            )
+            .unwrap();
+        migrate(storage).unwrap();
+        let unhashed: i64 = storage
+            .query_row(
+                "SELECT count(*) FROM access_log WHERE visitor_hash IS NULL;",
+                &[],
+            )
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(unhashed, 0);
+        let visitors: Vec<String> = storage
+            .query(
+                "SELECT visitor FROM rollup_visitors WHERE period = 'day' ORDER BY visitor;",
+                &[],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0).unwrap())
+            .collect();
+        assert_eq!(visitors.len(), 200);
+        assert!(visitors.contains(&hash_visitor(&[], "203.0.113.7", "")));
+    }
+
+    #[test]
+    fn hashes_old_rows_in_batches() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        check_hashes_old_rows_in_batches(&mut storage, MIGRATIONS[0].sqlite);
+    }
+
+    /// Needs `KIRINOX_TEST_POSTGRES`, see `postgres_backend_matches_sqlite`.
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn hashes_old_postgres_rows_in_batches() {
+        let Ok(url) = std::env::var("KIRINOX_TEST_POSTGRES") else {
+            eprintln!("KIRINOX_TEST_POSTGRES is not set, skipping");
+            return;
+        };
+        let schema = format!("kirinox_migrations_{}", std::process::id());
+        let mut storage = crate::PostgresStorage::connect(&url).unwrap();
+        storage
+            .execute_batch(&format!(
+                "CREATE SCHEMA {schema}; SET search_path TO {schema};"
+            ))
+            .unwrap();
+        check_hashes_old_rows_in_batches(&mut storage, MIGRATIONS[0].postgres);
+        storage
+            .execute_batch(&format!("DROP SCHEMA {schema} CASCADE;"))
+            .unwrap();
+    }
+
+    #[test]
+    fn refuses_newer_schemas() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        storage.set_schema_version(SCHEMA_VERSION + 1).unwrap();
+        assert!(migrate(&mut storage).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use std::{collections::HashMap, error::Error};
+
+use bytes::BytesMut;
+use postgres::{
+    Client, NoTls, Statement,
+    types::{IsNull, ToSql, Type, to_sql_checked},
+};
+
+use crate::storage::{Dialect, Row, Storage, StorageError, StorageResult, Value};
+
+impl From<postgres::Error> for StorageError {
+    fn from(err: postgres::Error) -> StorageError {
+        StorageError(err.to_string())
+    }
+}
+
+/// Postgres is strict about parameter types, so values are converted to whatever the prepared
+/// statement expects for that parameter.
+impl ToSql for Value {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        match (self, ty) {
+            (Value::Null, _) => Ok(IsNull::Yes),
+            (Value::Integer(value), &Type::INT2) => i16::try_from(*value)?.to_sql(ty, out),
+            (Value::Integer(value), &Type::INT4) => i32::try_from(*value)?.to_sql(ty, out),
+            (Value::Integer(value), &Type::INT8) => value.to_sql(ty, out),
+            (Value::Integer(value), &Type::FLOAT8) => (*value as f64).to_sql(ty, out),
+            (Value::Integer(value), &Type::BOOL) => (*value != 0).to_sql(ty, out),
+            (Value::Integer(value), &Type::TEXT | &Type::VARCHAR) => {
+                value.to_string().to_sql(ty, out)
+            }
+            (Value::Real(value), &Type::FLOAT4) => (*value as f32).to_sql(ty, out),
+            (Value::Real(value), &Type::FLOAT8) => value.to_sql(ty, out),
+            (Value::Text(value), _) => value.as_str().to_sql(ty, out),
+            (Value::Blob(value), _) => value.as_slice().to_sql(ty, out),
+            (value, ty) => Err(format!("can't send {value:?} as {ty}").into()),
+        }
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+
+    to_sql_checked!();
+}
+
+fn to_value(row: &postgres::Row, column: usize) -> StorageResult<Value> {
+    let ty = row.columns()[column].type_();
+    let value = match *ty {
+        Type::INT2 => row
+            .try_get::<_, Option<i16>>(column)?
+            .map(|value| Value::Integer(i64::from(value))),
+        Type::INT4 => row
+            .try_get::<_, Option<i32>>(column)?
+            .map(|value| Value::Integer(i64::from(value))),
+        Type::INT8 => row.try_get::<_, Option<i64>>(column)?.map(Value::Integer),
+        Type::BOOL => row
+            .try_get::<_, Option<bool>>(column)?
+            .map(|value| Value::Integer(i64::from(value))),
+        Type::FLOAT4 => row
+            .try_get::<_, Option<f32>>(column)?
+            .map(|value| Value::Real(f64::from(value))),
+        Type::FLOAT8 => row.try_get::<_, Option<f64>>(column)?.map(Value::Real),
+        Type::TEXT | Type::VARCHAR | Type::NAME | Type::BPCHAR => {
+            row.try_get::<_, Option<String>>(column)?.map(Value::Text)
+        }
+        Type::BYTEA => row.try_get::<_, Option<Vec<u8>>>(column)?.map(Value::Blob),
+        _ => {
+            return Err(StorageError(format!(
+                "unsupported column type {ty} for {}, cast it in the query",
+                row.columns()[column].name()
+            )));
+        }
+    };
+    Ok(value.unwrap_or(Value::Null))
+}
+
+/// Turns `?` placeholders into Postgres' `$1, $2, ...`, leaving quoted text alone.
+fn number_placeholders(sql: &str) -> String {
+    let mut numbered = String::with_capacity(sql.len() + 16);
+    let mut in_quotes = false;
+    let mut index = 0;
+    for char in sql.chars() {
+        match char {
+            '\'' => {
+                in_quotes = !in_quotes;
+                numbered.push(char);
+            }
+            '?' if !in_quotes => {
+                index += 1;
+                numbered.push('$');
+                numbered.push_str(&index.to_string());
+            }
+            _ => numbered.push(char),
+        }
+    }
+    numbered
+}
+
+pub struct PostgresStorage {
+    client: Client,
+    statements: HashMap<String, Statement>,
+}
+
+impl PostgresStorage {
+    /// `url` is a libpq style connection string, `host=localhost user=kirinox dbname=kirinox`
+    /// or `postgres://kirinox@localhost/kirinox`.
+    pub fn connect(url: &str) -> StorageResult<PostgresStorage> {
+        Ok(PostgresStorage {
+            client: Client::connect(url, NoTls)?,
+            statements: HashMap::new(),
+        })
+    }
+
+    fn statement(&mut self, sql: &str) -> StorageResult<Statement> {
+        if let Some(statement) = self.statements.get(sql) {
+            return Ok(statement.clone());
+        }
+        let statement = self.client.prepare(&number_placeholders(sql))?;
+        self.statements.insert(sql.to_string(), statement.clone());
+        Ok(statement)
+    }
+}
+
+fn params(values: &[Value]) -> Vec<&(dyn ToSql + Sync)> {
+    values
+        .iter()
+        .map(|value| value as &(dyn ToSql + Sync))
+        .collect()
+}
+
+impl Storage for PostgresStorage {
+    fn dialect(&self) -> Dialect {
+        Dialect::Postgres
+    }
+
+    fn execute(&mut self, sql: &str, values: &[Value]) -> StorageResult<usize> {
+        let statement = self.statement(sql)?;
+        Ok(self.client.execute(&statement, &params(values))? as usize)
+    }
+
+    fn execute_batch(&mut self, sql: &str) -> StorageResult<()> {
+        // the schema may have changed under the cached statements
+        self.statements.clear();
+        Ok(self.client.batch_execute(sql)?)
+    }
+
+    // transactions leave the schema alone, so they keep the cached statements
+
+    fn begin(&mut self) -> StorageResult<()> {
+        Ok(self.client.batch_execute("BEGIN;")?)
+    }
+
+    fn commit(&mut self) -> StorageResult<()> {
+        Ok(self.client.batch_execute("COMMIT;")?)
+    }
+
+    fn rollback(&mut self) -> StorageResult<()> {
+        Ok(self.client.batch_execute("ROLLBACK;")?)
+    }
+
+    fn vacuum(&mut self) -> StorageResult<()> {
+        // VACUUM refuses to run inside a transaction, so on its own
+        Ok(self.client.batch_execute("VACUUM ANALYZE;")?)
+    }
+
+    fn query(&mut self, sql: &str, values: &[Value]) -> StorageResult<Vec<Row>> {
+        let statement = self.statement(sql)?;
+        self.client
+            .query(&statement, &params(values))?
+            .iter()
+            .map(|row| (0..row.len()).map(|column| to_value(row, column)).collect())
+            .map(|values: StorageResult<Vec<Value>>| values.map(Row))
+            .collect()
+    }
+
+    fn schema_version(&mut self) -> StorageResult<i32> {
+        self.client.batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);",
+        )?;
+        let row = self
+            .client
+            .query_opt("SELECT max(version) FROM schema_version;", &[])?;
+        Ok(row
+            .and_then(|row| row.get::<_, Option<i32>>(0))
+            .unwrap_or(0))
+    }
+
+    fn set_schema_version(&mut self, version: i32) -> StorageResult<()> {
+        self.client.execute("DELETE FROM schema_version;", &[])?;
+        self.client.execute(
+            "INSERT INTO schema_version (version) VALUES ($1);",
+            &[&version],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_placeholders_outside_quotes() {
+        assert_eq!(
+            number_placeholders("SELECT * FROM t WHERE a = ? AND b = '?' AND c IN (?, ?);"),
+            "SELECT * FROM t WHERE a = $1 AND b = '?' AND c IN ($2, $3);"
+        );
+    }
+
+    /// Needs `KIRINOX_TEST_POSTGRES`, see `postgres_backend_matches_sqlite`.
+    #[test]
+    fn keeps_prepared_statements_across_transactions() {
+        let Ok(url) = std::env::var("KIRINOX_TEST_POSTGRES") else {
+            eprintln!("KIRINOX_TEST_POSTGRES is not set, skipping");
+            return;
+        };
+        let mut storage = PostgresStorage::connect(&url).unwrap();
+        storage.begin().unwrap();
+        storage
+            .query("SELECT ?::int8;", &[Value::Integer(1)])
+            .unwrap();
+        storage.commit().unwrap();
+        storage.begin().unwrap();
+        storage.rollback().unwrap();
+        assert_eq!(storage.statements.len(), 1);
+        storage.execute_batch("SELECT 1;").unwrap();
+        assert!(storage.statements.is_empty());
+    }
+}
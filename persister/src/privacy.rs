@@ -0,0 +1,127 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    storage::{Storage, StorageError, StorageResult},
+    values,
+};
+
+pub(crate) const DAY_MS: i64 = 86_400_000;
+
+/// How much of the visitor address is kept once privacy mode is on. The prefixes are the
+/// number of leading bits that survive, the rest is zeroed (`203.0.113.57` -> `203.0.113.0`).
+#[derive(Debug, Clone, Copy)]
+pub struct Privacy {
+    pub ipv4_prefix: u8,
+    pub ipv6_prefix: u8,
+}
+
+impl Default for Privacy {
+    fn default() -> Privacy {
+        Privacy {
+            ipv4_prefix: 24,
+            ipv6_prefix: 48,
+        }
+    }
+}
+
+impl Privacy {
+    pub fn truncate_ip(&self, addr: &str) -> String {
+        match addr.trim().parse::<IpAddr>() {
+            Ok(IpAddr::V4(addr)) => {
+                let prefix = u32::from(self.ipv4_prefix.min(32));
+                let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                Ipv4Addr::from(u32::from(addr) & mask).to_string()
+            }
+            Ok(IpAddr::V6(addr)) => {
+                let prefix = u32::from(self.ipv6_prefix.min(128));
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                Ipv6Addr::from(u128::from(addr) & mask).to_string()
+            }
+            // not an address we can truncate, so don't keep anything of it
+            Err(_) => String::from("-"),
+        }
+    }
+}
+
+pub(crate) fn hash_visitor(salt: &[u8], remote_addr: &str, user_agent: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(remote_addr.as_bytes());
+    hasher.update([0]);
+    hasher.update(user_agent.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Salt used for the visitor hashes of one UTC day. A fresh random salt is created the first
+/// time a day is seen and kept for the rest of the run, whatever order the log files come in.
+/// `expire` drops the ones older than yesterday once the run is over, so a hash can't be
+/// recomputed from an address once its day is over.
+#[derive(Debug, Clone)]
+pub(crate) struct DailySalt {
+    pub day: i64,
+    pub salt: [u8; 32],
+}
+
+impl DailySalt {
+    pub fn for_timestamp(storage: &mut dyn Storage, timestamp: i64) -> StorageResult<DailySalt> {
+        let day = timestamp.div_euclid(DAY_MS);
+        let stored: Option<Vec<u8>> =
+            match storage.query_row("SELECT salt FROM daily_salts WHERE day = ?;", values![day])? {
+                Some(row) => row.get(0)?,
+                None => None,
+            };
+        if let Some(stored) = stored
+            && let Ok(salt) = <[u8; 32]>::try_from(stored.as_slice())
+        {
+            return Ok(DailySalt { day, salt });
+        }
+        let mut salt = [0; 32];
+        getrandom::fill(&mut salt)
+            .map_err(|err| StorageError(format!("could not generate the daily salt: {err}")))?;
+        storage.execute(
+            "INSERT INTO daily_salts (day, salt) VALUES (?, ?)
+            ON CONFLICT (day) DO UPDATE SET salt = excluded.salt;",
+            values![day, salt.as_slice()],
+        )?;
+        Ok(DailySalt { day, salt })
+    }
+
+    /// Drops the salts of the days before yesterday, `now` being wall-clock time.
+    pub fn expire(storage: &mut dyn Storage, now: i64) -> StorageResult<usize> {
+        let today = now.div_euclid(DAY_MS);
+        storage.execute("DELETE FROM daily_salts WHERE day < ?;", values![today - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_addresses() {
+        let privacy = Privacy::default();
+        assert_eq!(privacy.truncate_ip("203.0.113.57"), "203.0.113.0");
+        assert_eq!(privacy.truncate_ip("2001:db8:1:2:3::1"), "2001:db8:1::");
+        assert_eq!(privacy.truncate_ip("unix:"), "-");
+        let privacy = Privacy {
+            ipv4_prefix: 16,
+            ipv6_prefix: 0,
+        };
+        assert_eq!(privacy.truncate_ip("203.0.113.57"), "203.0.0.0");
+        assert_eq!(privacy.truncate_ip("2001:db8::1"), "::");
+    }
+
+    #[test]
+    fn hash_depends_on_salt() {
+        let first = hash_visitor(&[1; 32], "203.0.113.57", "curl/8.0");
+        assert_eq!(first, hash_visitor(&[1; 32], "203.0.113.57", "curl/8.0"));
+        assert_ne!(first, hash_visitor(&[2; 32], "203.0.113.57", "curl/8.0"));
+        assert_eq!(first.len(), 64);
+    }
+}
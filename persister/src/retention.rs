@@ -0,0 +1,174 @@
+use crate::{
+    privacy::DAY_MS,
+    rollups::{Horizon, Period},
+    storage::{Storage, StorageResult, Value},
+    values,
+};
+
+/// How many days each kind of data is kept, `None` keeps it forever. Rollups are never
+/// touched by dropping raw rows, so the reports of pruned days stay the same as long as the
+/// rollups are kept.
+#[derive(Debug, Default, Clone)]
+pub struct Retention {
+    /// `access_log` rows, with the sessions and visitors they made up
+    pub raw_days: Option<u32>,
+    /// `access_log` rows of bots, usually much shorter than `raw_days`
+    pub bot_days: Option<u32>,
+    pub hourly_days: Option<u32>,
+    pub daily_days: Option<u32>,
+}
+
+/// What a `prune` run deleted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Pruned {
+    pub raw_rows: usize,
+    pub bot_rows: usize,
+    pub sessions: usize,
+    pub hourly_rollups: usize,
+    pub daily_rollups: usize,
+}
+
+fn cutoff(now: i64, days: Option<u32>) -> Option<i64> {
+    days.map(|days| now - i64::from(days) * DAY_MS)
+}
+
+/// Runs `delete`, which removes at most `batch_size` rows, until there's nothing left so no
+/// single statement holds the database for long.
+fn delete_in_batches(
+    storage: &mut dyn Storage,
+    delete: &str,
+    params: &[Value],
+    batch_size: usize,
+) -> StorageResult<usize> {
+    let mut params = params.to_vec();
+    params.push(Value::from(batch_size));
+    let mut deleted = 0;
+    loop {
+        let batch = storage.execute(delete, &params)?;
+        deleted += batch;
+        if batch < batch_size {
+            return Ok(deleted);
+        }
+    }
+}
+
+fn prune_access_log(
+    storage: &mut dyn Storage,
+    condition: &str,
+    before: i64,
+    batch_size: usize,
+) -> StorageResult<usize> {
+    delete_in_batches(
+        storage,
+        &format!(
+            "DELETE FROM access_log WHERE id IN (
+                SELECT id FROM access_log WHERE timestamp < ? {condition} LIMIT ?
+            );"
+        ),
+        values![before],
+        batch_size,
+    )
+}
+
+fn prune_sessions(
+    storage: &mut dyn Storage,
+    condition: &str,
+    before: i64,
+    batch_size: usize,
+) -> StorageResult<usize> {
+    let sessions = delete_in_batches(
+        storage,
+        &format!(
+            "DELETE FROM sessions WHERE id IN (
+                SELECT id FROM sessions WHERE ended_at < ? {condition} LIMIT ?
+            );"
+        ),
+        values![before],
+        batch_size,
+    )?;
+    delete_in_batches(
+        storage,
+        &format!(
+            "DELETE FROM visitors WHERE (http_host, visitor_hash) IN (
+                SELECT http_host, visitor_hash FROM visitors WHERE last_seen < ? {condition} LIMIT ?
+            );"
+        ),
+        values![before],
+        batch_size,
+    )?;
+    Ok(sessions)
+}
+
+/// Drops the buckets of `period` that ended before `before`, each batch together with the
+/// visitors, dimensions and latencies of its buckets so a bucket is never left half deleted.
+fn prune_rollups(
+    storage: &mut dyn Storage,
+    period: Period,
+    before: i64,
+    batch_size: usize,
+) -> StorageResult<usize> {
+    let mut deleted = 0;
+    loop {
+        let buckets = storage.query(
+            "SELECT http_host, bucket FROM rollups WHERE period = ? AND bucket < ? LIMIT ?;",
+            values![period.as_str(), period.floor(before), batch_size],
+        )?;
+        if buckets.is_empty() {
+            return Ok(deleted);
+        }
+        storage.begin()?;
+        for bucket in &buckets {
+            let key = values![
+                bucket.get::<String>(0)?,
+                period.as_str(),
+                bucket.get::<i64>(1)?
+            ];
+            for table in [
+                "rollup_visitors",
+                "rollup_dimensions",
+                "rollup_latency",
+                "rollups",
+            ] {
+                let result = storage.execute(
+                    &format!(
+                        "DELETE FROM {table} WHERE http_host = ? AND period = ? AND bucket = ?;"
+                    ),
+                    key,
+                );
+                if let Err(err) = result {
+                    let _ = storage.rollback();
+                    return Err(err);
+                }
+            }
+        }
+        storage.commit()?;
+        deleted += buckets.len();
+    }
+}
+
+pub(crate) fn prune(
+    storage: &mut dyn Storage,
+    retention: &Retention,
+    now: i64,
+    batch_size: usize,
+) -> StorageResult<Pruned> {
+    let mut pruned = Pruned::default();
+    if let Some(before) = cutoff(now, retention.bot_days) {
+        pruned.bot_rows = prune_access_log(storage, "AND is_bot = 1", before, batch_size)?;
+        pruned.sessions += prune_sessions(storage, "AND is_bot = 1", before, batch_size)?;
+        Horizon::advance(storage, "bot", before)?;
+    }
+    if let Some(before) = cutoff(now, retention.raw_days) {
+        pruned.raw_rows = prune_access_log(storage, "", before, batch_size)?;
+        pruned.sessions += prune_sessions(storage, "", before, batch_size)?;
+        Horizon::advance(storage, "raw", before)?;
+    }
+    if let Some(before) = cutoff(now, retention.hourly_days) {
+        pruned.hourly_rollups = prune_rollups(storage, Period::Hour, before, batch_size)?;
+        Horizon::advance(storage, "hour", Period::Hour.floor(before))?;
+    }
+    if let Some(before) = cutoff(now, retention.daily_days) {
+        pruned.daily_rollups = prune_rollups(storage, Period::Day, before, batch_size)?;
+    }
+    Ok(pruned)
+}
@@ -0,0 +1,469 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    latency::{Metric, latency_bucket, path},
+    storage::{Storage, StorageResult, Value},
+    values,
+};
+
+const HOUR_MS: i64 = 60 * 60 * 1000;
+
+/// Length of the rollup buckets, and the step of time series read from them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Period {
+    Hour,
+    Day,
+}
+
+impl Period {
+    const ALL: [Period; 2] = [Period::Hour, Period::Day];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Period::Hour => "hour",
+            Period::Day => "day",
+        }
+    }
+
+    /// milliseconds
+    pub fn length(&self) -> i64 {
+        match self {
+            Period::Hour => HOUR_MS,
+            Period::Day => crate::privacy::DAY_MS,
+        }
+    }
+
+    /// start of the bucket `timestamp` falls in
+    pub fn floor(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.length())
+    }
+
+    pub fn ceil(&self, timestamp: i64) -> i64 {
+        let floor = self.floor(timestamp);
+        if floor == timestamp {
+            floor
+        } else {
+            floor + self.length()
+        }
+    }
+}
+
+/// How far back `access_log` and the hourly rollups are complete, moved forward by `prune`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Horizon {
+    /// raw rows of bots before this were pruned
+    pub bots: Option<i64>,
+    /// all raw rows before this were pruned
+    pub raw: Option<i64>,
+    /// hourly rollups before this were pruned
+    pub hourly: Option<i64>,
+}
+
+impl Horizon {
+    pub fn load(storage: &mut dyn Storage) -> StorageResult<Horizon> {
+        let mut horizon = Horizon::default();
+        for row in storage.query(
+            "SELECT source, pruned_before FROM retention_horizons;",
+            values![],
+        )? {
+            let pruned_before = Some(row.get(1)?);
+            match row.get::<String>(0)?.as_str() {
+                "bot" => horizon.bots = pruned_before,
+                "raw" => horizon.raw = pruned_before,
+                "hour" => horizon.hourly = pruned_before,
+                _ => {}
+            }
+        }
+        Ok(horizon)
+    }
+
+    /// Remembers that `source`, `bot`, `raw` or `hour`, has nothing before `before` any more.
+    pub fn advance(storage: &mut dyn Storage, source: &str, before: i64) -> StorageResult<()> {
+        let greatest = storage.dialect().greatest();
+        storage.execute(
+            &format!(
+                "INSERT INTO retention_horizons (source, pruned_before) VALUES (?, ?)
+                ON CONFLICT (source) DO UPDATE SET
+                    pruned_before = {greatest}(retention_horizons.pruned_before, excluded.pruned_before);"
+            ),
+            values![source, before],
+        )?;
+        Ok(())
+    }
+
+    /// `timestamp` moved out to the edge of the rollup bucket that covers it, `round` being
+    /// `Period::floor` or `Period::ceil`, when what would be read at it is pruned. A little
+    /// more than asked rather than losing the ragged edges of long ranges.
+    fn widen(&self, timestamp: i64, round: fn(&Period, i64) -> i64) -> i64 {
+        if self.hourly.is_some_and(|hourly| timestamp < hourly) {
+            round(&Period::Day, timestamp)
+        } else if self.raw.max(self.bots).is_some_and(|raw| timestamp < raw) {
+            round(&Period::Hour, timestamp)
+        } else {
+            timestamp
+        }
+    }
+
+    /// Whether a line of `timestamp` would be stored where `prune` already dropped the rows.
+    /// They were counted into the rollups when they were first stored, if they ever were.
+    pub fn dropped(&self, timestamp: i64, is_bot: bool) -> bool {
+        let before = if is_bot {
+            self.raw.max(self.bots)
+        } else {
+            self.raw
+        };
+        before.is_some_and(|before| timestamp < before)
+    }
+}
+
+/// What a part of a time range is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Source {
+    Raw,
+    Rollup(Period),
+}
+
+/// `[from, to)` of a time range, `to` open ended when `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Segment {
+    pub source: Source,
+    pub from: i64,
+    pub to: Option<i64>,
+}
+
+impl Segment {
+    /// `WHERE` condition selecting the rows of `host`, or of every host, in the segment,
+    /// `timestamp` being the time column of raw rows.
+    pub fn filter(&self, host: Option<&str>, timestamp: &str) -> (String, Vec<Value>) {
+        let (column, mut conditions, mut params) = match self.source {
+            Source::Raw => (timestamp, Vec::new(), Vec::new()),
+            Source::Rollup(period) => (
+                "bucket",
+                vec![String::from("period = ?")],
+                values![period.as_str()].to_vec(),
+            ),
+        };
+        if let Some(host) = host {
+            conditions.push(String::from("http_host = ?"));
+            params.push(Value::from(host));
+        }
+        conditions.push(format!("{column} >= ?"));
+        params.push(Value::from(self.from));
+        if let Some(to) = self.to {
+            conditions.push(format!("{column} < ?"));
+            params.push(Value::from(to));
+        }
+        (conditions.join(" AND "), params)
+    }
+}
+
+/// Splits a time range so that whole days come from the daily rollups, whole hours around
+/// them from the hourly ones and only the ragged edges from `access_log`. A range of all time
+/// is read from the daily rollups alone. Edges older than `horizon` are widened to what the
+/// rollups still cover.
+pub(crate) fn segments(since: i64, until: Option<i64>, horizon: Horizon) -> Vec<Segment> {
+    let since = horizon.widen(since, Period::floor);
+    let until = until.map(|until| horizon.widen(until, Period::ceil));
+    let mut segments = Vec::new();
+    let mut push = |source, from: i64, to: Option<i64>| {
+        if to.is_none_or(|to| from < to) {
+            segments.push(Segment { source, from, to });
+        }
+    };
+    let Some(until) = until else {
+        let hour = Period::Hour.ceil(since);
+        let day = Period::Day.ceil(since);
+        push(Source::Raw, since, Some(hour));
+        push(Source::Rollup(Period::Hour), hour, Some(day));
+        push(Source::Rollup(Period::Day), day, None);
+        return segments;
+    };
+    let (first_hour, last_hour) = (Period::Hour.ceil(since), Period::Hour.floor(until));
+    let (first_day, last_day) = (Period::Day.ceil(since), Period::Day.floor(until));
+    if first_hour >= last_hour {
+        push(Source::Raw, since, Some(until));
+    } else if first_day >= last_day {
+        push(Source::Raw, since, Some(first_hour));
+        push(Source::Rollup(Period::Hour), first_hour, Some(last_hour));
+        push(Source::Raw, last_hour, Some(until));
+    } else {
+        push(Source::Raw, since, Some(first_hour));
+        push(Source::Rollup(Period::Hour), first_hour, Some(first_day));
+        push(Source::Rollup(Period::Day), first_day, Some(last_day));
+        push(Source::Rollup(Period::Hour), last_day, Some(last_hour));
+        push(Source::Raw, last_hour, Some(until));
+    }
+    segments
+}
+
+/// One stored request, as far as the rollups are concerned.
+pub(crate) struct Entry<'a> {
+    pub http_host: &'a str,
+    pub timestamp: i64,
+    pub visitor: &'a str,
+    pub is_pageview: bool,
+    pub page: &'a str,
+    pub country: &'a str,
+    pub city: &'a str,
+    pub referrer: Option<&'a str>,
+    pub status: u16,
+    pub bytes_sent: u64,
+    pub user_agent: &'a str,
+    /// seconds
+    pub request_time: f64,
+    /// seconds, when the request was proxied
+    pub upstream_time: Option<f64>,
+    pub is_bot: bool,
+    pub is_vpn: bool,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    requests: i64,
+    pageviews: i64,
+    human_requests: i64,
+    bot_requests: i64,
+    vpn_requests: i64,
+    bytes_sent: i64,
+    /// 1xx to 5xx
+    status: [i64; 5],
+    request_time_sum: f64,
+}
+
+type Bucket = (String, Period, i64);
+
+/// Rollup changes of a batch, summed up in memory and written once per bucket when the batch
+/// commits instead of once per line.
+#[derive(Debug, Default)]
+pub(crate) struct Pending {
+    counters: HashMap<Bucket, Counters>,
+    visitors: HashSet<(Bucket, String)>,
+    /// hits and bytes sent
+    dimensions: HashMap<(Bucket, &'static str, String), (i64, i64)>,
+    latency: HashMap<(Bucket, Metric, String, i64), i64>,
+}
+
+impl Pending {
+    pub fn add(&mut self, entry: &Entry) {
+        let bot = entry
+            .is_bot
+            .then(|| enricher::bot_family(entry.user_agent).unwrap_or("other"));
+        let dimensions = [
+            ("page", entry.is_pageview.then_some(entry.page)),
+            ("path", Some(path(entry.page))),
+            ("country", Some(entry.country)),
+            ("city", Some(entry.city)),
+            ("referrer", entry.referrer),
+            ("bot", bot),
+        ];
+        for period in Period::ALL {
+            let bucket = (
+                entry.http_host.to_string(),
+                period,
+                period.floor(entry.timestamp),
+            );
+            let counters = self.counters.entry(bucket.clone()).or_default();
+            counters.requests += 1;
+            counters.pageviews += i64::from(entry.is_pageview);
+            counters.human_requests += i64::from(!entry.is_bot);
+            counters.bot_requests += i64::from(entry.is_bot);
+            counters.vpn_requests += i64::from(entry.is_vpn);
+            counters.bytes_sent += entry.bytes_sent as i64;
+            if let Some(class) = (entry.status / 100)
+                .checked_sub(1)
+                .filter(|class| *class < 5)
+            {
+                counters.status[class as usize] += 1;
+            }
+            counters.request_time_sum += entry.request_time;
+
+            self.visitors
+                .insert((bucket.clone(), entry.visitor.to_string()));
+            for (dimension, value) in dimensions {
+                if let Some(value) = value.filter(|value| !value.is_empty()) {
+                    let (hits, bytes) = self
+                        .dimensions
+                        .entry((bucket.clone(), dimension, value.to_string()))
+                        .or_default();
+                    *hits += 1;
+                    *bytes += entry.bytes_sent as i64;
+                }
+            }
+            let times = [
+                (Metric::Request, Some(entry.request_time)),
+                (Metric::Upstream, entry.upstream_time),
+            ];
+            for (metric, seconds) in times {
+                if let Some(seconds) = seconds {
+                    let key = (
+                        bucket.clone(),
+                        metric,
+                        path(entry.page).to_string(),
+                        latency_bucket(seconds),
+                    );
+                    *self.latency.entry(key).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    pub fn flush(self, storage: &mut dyn Storage) -> StorageResult<()> {
+        for ((host, period, bucket), counters) in self.counters {
+            let [status_1xx, status_2xx, status_3xx, status_4xx, status_5xx] = counters.status;
+            storage.execute(
+                "INSERT INTO rollups (
+                    http_host, period, bucket, requests, pageviews, human_requests, bot_requests,
+                    vpn_requests, bytes_sent, status_1xx, status_2xx, status_3xx, status_4xx,
+                    status_5xx, request_time_sum
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (http_host, period, bucket) DO UPDATE SET
+                    requests = rollups.requests + excluded.requests,
+                    pageviews = rollups.pageviews + excluded.pageviews,
+                    human_requests = rollups.human_requests + excluded.human_requests,
+                    bot_requests = rollups.bot_requests + excluded.bot_requests,
+                    vpn_requests = rollups.vpn_requests + excluded.vpn_requests,
+                    bytes_sent = rollups.bytes_sent + excluded.bytes_sent,
+                    status_1xx = rollups.status_1xx + excluded.status_1xx,
+                    status_2xx = rollups.status_2xx + excluded.status_2xx,
+                    status_3xx = rollups.status_3xx + excluded.status_3xx,
+                    status_4xx = rollups.status_4xx + excluded.status_4xx,
+                    status_5xx = rollups.status_5xx + excluded.status_5xx,
+                    request_time_sum = rollups.request_time_sum + excluded.request_time_sum;",
+                values![
+                    host,
+                    period.as_str(),
+                    bucket,
+                    counters.requests,
+                    counters.pageviews,
+                    counters.human_requests,
+                    counters.bot_requests,
+                    counters.vpn_requests,
+                    counters.bytes_sent,
+                    status_1xx,
+                    status_2xx,
+                    status_3xx,
+                    status_4xx,
+                    status_5xx,
+                    counters.request_time_sum,
+                ],
+            )?;
+        }
+        for ((host, period, bucket), visitor) in self.visitors {
+            storage.execute(
+                "INSERT INTO rollup_visitors (http_host, period, bucket, visitor) VALUES (?, ?, ?, ?)
+                ON CONFLICT DO NOTHING;",
+                values![host, period.as_str(), bucket, visitor],
+            )?;
+        }
+        for (((host, period, bucket), dimension, value), (hits, bytes)) in self.dimensions {
+            storage.execute(
+                "INSERT INTO rollup_dimensions (
+                    http_host, period, bucket, dimension, value, hits, bytes
+                ) VALUES (?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (http_host, period, bucket, dimension, value) DO UPDATE SET
+                    hits = rollup_dimensions.hits + excluded.hits,
+                    bytes = rollup_dimensions.bytes + excluded.bytes;",
+                values![host, period.as_str(), bucket, dimension, value, hits, bytes],
+            )?;
+        }
+        for (((host, period, bucket), metric, path, latency_bucket), hits) in self.latency {
+            storage.execute(
+                "INSERT INTO rollup_latency (
+                    http_host, period, bucket, metric, path, latency_bucket, hits
+                ) VALUES (?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (http_host, period, bucket, metric, path, latency_bucket) DO UPDATE SET
+                    hits = rollup_latency.hits + excluded.hits;",
+                values![
+                    host,
+                    period.as_str(),
+                    bucket,
+                    metric.as_str(),
+                    path,
+                    latency_bucket,
+                    hits
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = crate::privacy::DAY_MS;
+
+    fn segment(source: Source, from: i64, to: Option<i64>) -> Segment {
+        Segment { source, from, to }
+    }
+
+    #[test]
+    fn reads_whole_days_from_daily_rollups() {
+        assert_eq!(
+            segments(0, None, Horizon::default()),
+            vec![segment(Source::Rollup(Period::Day), 0, None)]
+        );
+        let since = 3 * DAY - 90 * 60 * 1000;
+        assert_eq!(
+            segments(since, None, Horizon::default()),
+            vec![
+                segment(Source::Raw, since, Some(since + 30 * 60 * 1000)),
+                segment(
+                    Source::Rollup(Period::Hour),
+                    3 * DAY - HOUR_MS,
+                    Some(3 * DAY)
+                ),
+                segment(Source::Rollup(Period::Day), 3 * DAY, None),
+            ]
+        );
+        assert_eq!(
+            segments(DAY + 10, Some(3 * DAY + HOUR_MS + 10), Horizon::default()),
+            vec![
+                segment(Source::Raw, DAY + 10, Some(DAY + HOUR_MS)),
+                segment(Source::Rollup(Period::Hour), DAY + HOUR_MS, Some(2 * DAY)),
+                segment(Source::Rollup(Period::Day), 2 * DAY, Some(3 * DAY)),
+                segment(
+                    Source::Rollup(Period::Hour),
+                    3 * DAY,
+                    Some(3 * DAY + HOUR_MS)
+                ),
+                segment(Source::Raw, 3 * DAY + HOUR_MS, Some(3 * DAY + HOUR_MS + 10)),
+            ]
+        );
+        assert_eq!(
+            segments(10, Some(20), Horizon::default()),
+            vec![segment(Source::Raw, 10, Some(20))]
+        );
+    }
+
+    #[test]
+    fn widens_edges_past_the_horizon() {
+        let since = DAY + HOUR_MS + 10;
+        let until = Some(3 * DAY + 10);
+        let raw = Horizon {
+            raw: Some(2 * DAY),
+            ..Horizon::default()
+        };
+        // the start is gone from access_log, so its hour is read whole from the rollups
+        assert_eq!(
+            segments(since, until, raw),
+            vec![
+                segment(Source::Rollup(Period::Hour), DAY + HOUR_MS, Some(2 * DAY)),
+                segment(Source::Rollup(Period::Day), 2 * DAY, Some(3 * DAY)),
+                segment(Source::Raw, 3 * DAY, until),
+            ]
+        );
+        let hourly = Horizon {
+            hourly: Some(2 * DAY),
+            ..raw
+        };
+        assert_eq!(
+            segments(since, until, hourly),
+            vec![
+                segment(Source::Rollup(Period::Day), DAY, Some(3 * DAY)),
+                segment(Source::Raw, 3 * DAY, until),
+            ]
+        );
+    }
+}
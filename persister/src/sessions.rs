@@ -0,0 +1,220 @@
+use crate::{
+    filter::{Filter, Traffic},
+    storage::{Storage, StorageResult, Value},
+    values,
+};
+
+/// Default inactivity gap after which the next request of a visitor starts a new session.
+pub const DEFAULT_SESSION_GAP_MS: i64 = 30 * 60 * 1000;
+
+#[derive(Debug, Default, Clone)]
+pub struct SessionStats {
+    pub sessions: i32,
+    pub bounces: i32,
+    /// average session duration in milliseconds
+    pub avg_duration: f64,
+    pub avg_pages: f64,
+}
+
+pub(crate) struct Hit<'a> {
+    pub http_host: &'a str,
+    pub visitor_hash: &'a str,
+    pub timestamp: i64,
+    pub page: &'a str,
+    pub is_bot: bool,
+}
+
+/// Attaches a pageview to the session of its visitor. Lines don't always arrive in order, so
+/// a pageview extends any session of the visitor it is within `gap` of, on either side, and
+/// only opens a new session when there's none.
+pub(crate) fn record_hit(storage: &mut dyn Storage, hit: &Hit, gap: i64) -> StorageResult<()> {
+    let dialect = storage.dialect();
+    storage.execute(
+        &format!(
+            "INSERT INTO visitors (http_host, visitor_hash, first_seen, last_seen, pageviews, sessions, is_bot)
+            VALUES (?, ?, ?, ?, 1, 0, ?)
+            ON CONFLICT (http_host, visitor_hash) DO UPDATE SET
+                first_seen = {least}(visitors.first_seen, excluded.first_seen),
+                last_seen = {greatest}(visitors.last_seen, excluded.last_seen),
+                pageviews = visitors.pageviews + 1,
+                is_bot = {greatest}(visitors.is_bot, excluded.is_bot);",
+            least = dialect.least(),
+            greatest = dialect.greatest(),
+        ),
+        values![hit.http_host, hit.visitor_hash, hit.timestamp, hit.timestamp, hit.is_bot],
+    )?;
+
+    let session = storage.query_row(
+        "SELECT id, started_at, ended_at FROM sessions
+        WHERE http_host = ? AND visitor_hash = ? AND ended_at >= ? AND started_at <= ?
+        ORDER BY ended_at DESC LIMIT 1;",
+        values![
+            hit.http_host,
+            hit.visitor_hash,
+            hit.timestamp - gap,
+            hit.timestamp + gap
+        ],
+    )?;
+
+    match session {
+        Some(row) => {
+            let (id, started_at, ended_at): (i64, i64, i64) =
+                (row.get(0)?, row.get(1)?, row.get(2)?);
+            if hit.timestamp < started_at {
+                storage.execute(
+                    "UPDATE sessions SET started_at = ?, entry_page = ? WHERE id = ?;",
+                    values![hit.timestamp, hit.page, id],
+                )?;
+            }
+            if hit.timestamp >= ended_at {
+                storage.execute(
+                    "UPDATE sessions SET ended_at = ?, exit_page = ? WHERE id = ?;",
+                    values![hit.timestamp, hit.page, id],
+                )?;
+            }
+            storage.execute(
+                "UPDATE sessions SET
+                    page_count = page_count + 1,
+                    duration = ended_at - started_at,
+                    is_bounce = 0
+                WHERE id = ?;",
+                values![id],
+            )?;
+        }
+        None => {
+            storage.execute(
+                "INSERT INTO sessions (
+                    http_host, visitor_hash, started_at, ended_at,
+                    entry_page, exit_page, page_count, duration, is_bounce, is_bot
+                ) VALUES (?, ?, ?, ?, ?, ?, 1, 0, 1, ?);",
+                values![
+                    hit.http_host,
+                    hit.visitor_hash,
+                    hit.timestamp,
+                    hit.timestamp,
+                    hit.page,
+                    hit.page,
+                    hit.is_bot
+                ],
+            )?;
+            storage.execute(
+                "UPDATE visitors SET sessions = sessions + 1
+                WHERE http_host = ? AND visitor_hash = ?;",
+                values![hit.http_host, hit.visitor_hash],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Sessions of the filtered host and time range that started in it, those of humans unless
+/// the filter asks for bots. Sessions are kept per visitor rather than per request, so the
+/// rest of the filter picks the sessions with at least one matching request.
+pub(crate) fn session_stats(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+) -> StorageResult<SessionStats> {
+    let mut condition = String::from("started_at >= ? AND is_bot = ?");
+    let mut params = values![
+        filter.since.unwrap_or(0),
+        filter.traffic == Some(Traffic::Bots)
+    ]
+    .to_vec();
+    if let Some(host) = &filter.host {
+        condition.push_str(" AND http_host = ?");
+        params.push(Value::from(host));
+    }
+    if let Some(until) = filter.until {
+        condition.push_str(" AND started_at < ?");
+        params.push(Value::from(until));
+    }
+    let requests = Filter {
+        traffic: None,
+        ..filter.clone()
+    };
+    if !requests.by_host_and_time() {
+        let (dimensions, dimension_params) = requests.dimensions();
+        condition.push_str(&format!(
+            " AND EXISTS (
+                SELECT 1 FROM access_log
+                WHERE access_log.http_host = sessions.http_host
+                AND access_log.visitor_hash = sessions.visitor_hash
+                AND access_log.timestamp >= sessions.started_at
+                AND access_log.timestamp <= sessions.ended_at
+                {dimensions}
+            )"
+        ));
+        params.extend(dimension_params);
+    }
+    let row = storage.query_one(
+        &format!(
+            "SELECT
+                count(*),
+                CAST(coalesce(sum(is_bounce), 0) AS BIGINT),
+                CAST(coalesce(avg(duration), 0) AS DOUBLE PRECISION),
+                CAST(coalesce(avg(page_count), 0) AS DOUBLE PRECISION)
+            FROM sessions
+            WHERE {condition};"
+        ),
+        &params,
+    )?;
+    Ok(SessionStats {
+        sessions: row.get(0)?,
+        bounces: row.get(1)?,
+        avg_duration: row.get(2)?,
+        avg_pages: row.get(3)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sqlite::SqliteStorage;
+
+    fn hit(timestamp: i64, page: &str) -> Hit<'_> {
+        Hit {
+            http_host: "example.com",
+            visitor_hash: "abc",
+            timestamp,
+            page,
+            is_bot: false,
+        }
+    }
+
+    #[test]
+    fn splits_sessions_on_inactivity() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        crate::migrations::migrate(&mut storage).unwrap();
+        let minute = 60 * 1000;
+        let gap = DEFAULT_SESSION_GAP_MS;
+        record_hit(&mut storage, &hit(0, "/"), gap).unwrap();
+        record_hit(&mut storage, &hit(5 * minute, "/about"), gap).unwrap();
+        // arrives late but still belongs to the first session
+        record_hit(&mut storage, &hit(2 * minute, "/blog"), gap).unwrap();
+        record_hit(&mut storage, &hit(60 * minute, "/contact"), gap).unwrap();
+
+        let stats = session_stats(&mut storage, &Filter::new().host("example.com")).unwrap();
+        assert_eq!(stats.sessions, 2);
+        assert_eq!(stats.bounces, 1);
+        assert_eq!(stats.avg_pages, 2.0);
+
+        let first = storage
+            .query_row(
+                "SELECT entry_page, exit_page, duration FROM sessions ORDER BY started_at LIMIT 1;",
+                &[],
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.get::<String>(0).unwrap(), "/");
+        assert_eq!(first.get::<String>(1).unwrap(), "/about");
+        assert_eq!(first.get::<i64>(2).unwrap(), 5 * minute);
+
+        let sessions: i64 = storage
+            .query_row("SELECT sessions FROM visitors;", &[])
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(sessions, 2);
+    }
+}
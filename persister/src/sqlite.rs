@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use rusqlite::{
+    Connection, OpenFlags, params_from_iter,
+    types::{ToSqlOutput, ValueRef},
+};
+
+use crate::storage::{Dialect, Row, Storage, StorageError, StorageResult, Value};
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(err: rusqlite::Error) -> StorageError {
+        StorageError(err.to_string())
+    }
+}
+
+impl rusqlite::ToSql for Value {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            Value::Null => ToSqlOutput::Borrowed(ValueRef::Null),
+            Value::Integer(value) => ToSqlOutput::Borrowed(ValueRef::Integer(*value)),
+            Value::Real(value) => ToSqlOutput::Borrowed(ValueRef::Real(*value)),
+            Value::Text(value) => ToSqlOutput::Borrowed(ValueRef::Text(value.as_bytes())),
+            Value::Blob(value) => ToSqlOutput::Borrowed(ValueRef::Blob(value)),
+        })
+    }
+}
+
+fn to_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(value) => Value::Integer(value),
+        ValueRef::Real(value) => Value::Real(value),
+        ValueRef::Text(value) => Value::Text(String::from_utf8_lossy(value).into_owned()),
+        ValueRef::Blob(value) => Value::Blob(value.to_vec()),
+    }
+}
+
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> StorageResult<SqliteStorage> {
+        let connection = Connection::open(path)?;
+        // WAL lets the report queries read while a backfill is writing, NORMAL sync is still
+        // crash safe with it and a lot cheaper than FULL
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        Ok(SqliteStorage { connection })
+    }
+
+    /// Opens an existing database without ever writing to it, a missing file is an error.
+    pub fn open_read_only(path: &Path) -> StorageResult<SqliteStorage> {
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        Ok(SqliteStorage { connection })
+    }
+
+    /// Writes a consistent copy of the database to `path`, which must not exist yet.
+    pub fn copy_to(&mut self, path: &Path) -> StorageResult<()> {
+        self.connection
+            .execute("VACUUM INTO ?;", [path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    pub fn in_memory() -> StorageResult<SqliteStorage> {
+        Ok(SqliteStorage {
+            connection: Connection::open_in_memory()?,
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    fn execute(&mut self, sql: &str, params: &[Value]) -> StorageResult<usize> {
+        let mut statement = self.connection.prepare_cached(sql)?;
+        Ok(statement.execute(params_from_iter(params))?)
+    }
+
+    fn execute_batch(&mut self, sql: &str) -> StorageResult<()> {
+        Ok(self.connection.execute_batch(sql)?)
+    }
+
+    fn vacuum(&mut self) -> StorageResult<()> {
+        // the checkpoint moves the WAL back into the main file so it can shrink as well
+        Ok(self
+            .connection
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE); VACUUM; ANALYZE;")?)
+    }
+
+    fn query(&mut self, sql: &str, params: &[Value]) -> StorageResult<Vec<Row>> {
+        let mut statement = self.connection.prepare_cached(sql)?;
+        let columns = statement.column_count();
+        let mut rows = statement.query(params_from_iter(params))?;
+        let mut result = vec![];
+        while let Some(row) = rows.next()? {
+            let mut values = Vec::with_capacity(columns);
+            for column in 0..columns {
+                values.push(to_value(row.get_ref(column)?));
+            }
+            result.push(Row(values));
+        }
+        Ok(result)
+    }
+
+    fn schema_version(&mut self) -> StorageResult<i32> {
+        Ok(self
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    fn set_schema_version(&mut self, version: i32) -> StorageResult<()> {
+        Ok(self
+            .connection
+            .pragma_update(None, "user_version", version)?)
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{
+    filter::Filter,
+    latency::{Latency, latency},
+    rollups::{Horizon, Segment, Source},
+    sessions::{SessionStats, session_stats},
+    storage::{Storage, StorageResult, Value},
+    values,
+};
+
+const TOP_LIMIT: i32 = 10;
+
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    pub total_requests: i32,
+    pub pageviews: i32,
+    /// distinct visitor hashes (address and user agent)
+    pub unique_visitors: i32,
+    pub human_requests: i32,
+    pub bot_requests: i32,
+    pub vpn_requests: i32,
+    /// response bodies
+    pub bytes_sent: i64,
+    /// milliseconds
+    pub avg_response_time: f32,
+    /// most viewed pages, pageviews only
+    pub pages: Vec<(String, i32)>,
+    pub countries: Vec<(String, i32)>,
+    pub cities: Vec<(String, i32)>,
+    pub referrers: Vec<(String, i32)>,
+    pub sessions: SessionStats,
+    pub latency: Latency,
+}
+
+/// How the headline numbers moved against the previous period, percent. `None` when the
+/// previous period had nothing to compare with.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Changes {
+    pub total_requests: Option<f64>,
+    pub pageviews: Option<f64>,
+    pub unique_visitors: Option<f64>,
+    pub bytes_sent: Option<f64>,
+    pub avg_response_time: Option<f64>,
+    /// percentage points, the share being a percentage already
+    pub bot_share: Option<f64>,
+}
+
+fn change(current: f64, previous: f64) -> Option<f64> {
+    (previous > 0.0).then(|| (current - previous) * 100.0 / previous)
+}
+
+impl Stats {
+    /// bot requests in percent of all requests
+    pub fn bot_share(&self) -> f64 {
+        if self.total_requests > 0 {
+            f64::from(self.bot_requests) * 100.0 / f64::from(self.total_requests)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Changes {
+    pub fn between(current: &Stats, previous: &Stats) -> Changes {
+        Changes {
+            total_requests: change(
+                f64::from(current.total_requests),
+                f64::from(previous.total_requests),
+            ),
+            pageviews: change(f64::from(current.pageviews), f64::from(previous.pageviews)),
+            unique_visitors: change(
+                f64::from(current.unique_visitors),
+                f64::from(previous.unique_visitors),
+            ),
+            bytes_sent: change(current.bytes_sent as f64, previous.bytes_sent as f64),
+            avg_response_time: change(
+                f64::from(current.avg_response_time),
+                f64::from(previous.avg_response_time),
+            ),
+            bot_share: (previous.total_requests > 0)
+                .then(|| current.bot_share() - previous.bot_share()),
+        }
+    }
+}
+
+pub(crate) fn hosts(storage: &mut dyn Storage) -> StorageResult<Vec<String>> {
+    storage
+        .query(
+            // every period, `prune` may have dropped the daily rows of a host but not the rest
+            "SELECT http_host FROM rollups UNION SELECT http_host FROM access_log
+            ORDER BY http_host;",
+            values![],
+        )?
+        .iter()
+        .map(|row| row.get(0))
+        .collect()
+}
+
+/// Glues the per segment queries into one `UNION ALL`, with their parameters in order. Raw
+/// segments get the rest of the filter on top of host and time.
+pub(crate) fn union(
+    filter: &Filter,
+    segments: &[Segment],
+    part: impl Fn(Source, &str) -> (String, Vec<Value>),
+) -> (String, Vec<Value>) {
+    let mut parts = Vec::new();
+    let mut params = Vec::new();
+    for segment in segments {
+        let (mut condition, mut filter_params) =
+            segment.filter(filter.host.as_deref(), "timestamp");
+        if segment.source == Source::Raw {
+            let (dimensions, dimension_params) = filter.dimensions();
+            condition.push_str(&dimensions);
+            filter_params.extend(dimension_params);
+        }
+        let filter = condition;
+        let (sql, extra_params) = part(segment.source, &filter);
+        parts.push(sql);
+        params.extend(filter_params);
+        params.extend(extra_params);
+    }
+    (parts.join(" UNION ALL "), params)
+}
+
+fn top(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+    segments: &[Segment],
+    dimension: &str,
+    column: &str,
+    extra: &str,
+) -> StorageResult<Vec<(String, i32)>> {
+    let (parts, mut params) = union(filter, segments, |source, filter| match source {
+        Source::Raw => (
+            format!(
+                "SELECT {column} AS value, count(*) AS hits FROM access_log
+                WHERE {filter} AND {column} IS NOT NULL AND {column} != '' {extra}
+                GROUP BY {column}"
+            ),
+            Vec::new(),
+        ),
+        Source::Rollup(_) => (
+            format!("SELECT value, hits FROM rollup_dimensions WHERE {filter} AND dimension = ?"),
+            values![dimension].to_vec(),
+        ),
+    });
+    params.push(Value::from(TOP_LIMIT));
+    storage
+        .query(
+            &format!(
+                "SELECT value, CAST(sum(hits) AS BIGINT) AS total FROM ({parts}) AS parts
+                GROUP BY value
+                ORDER BY total DESC, value
+                LIMIT ?;"
+            ),
+            &params,
+        )?
+        .iter()
+        .map(|row| Ok((row.get(0)?, row.get(1)?)))
+        .collect()
+}
+
+fn unique_visitors(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+    segments: &[Segment],
+) -> StorageResult<i32> {
+    let (parts, params) = union(filter, segments, |source, filter| match source {
+        Source::Raw => (
+            format!(
+                "SELECT coalesce(visitor_hash, remote_addr) AS visitor FROM access_log WHERE {filter}"
+            ),
+            Vec::new(),
+        ),
+        Source::Rollup(_) => (
+            format!("SELECT visitor FROM rollup_visitors WHERE {filter}"),
+            Vec::new(),
+        ),
+    });
+    storage
+        .query_one(
+            &format!("SELECT count(DISTINCT visitor) FROM ({parts}) AS parts;"),
+            &params,
+        )?
+        .get(0)
+}
+
+/// Everything the report shows for the requests `filter` selects. Unless it narrows more
+/// than host and time, whole hours and days are read from the rollups and only the rest of
+/// the range from `access_log`.
+pub(crate) fn stats(storage: &mut dyn Storage, filter: &Filter) -> StorageResult<Stats> {
+    let segments = filter.segments(Horizon::load(storage)?);
+    let (parts, params) = union(filter, &segments, |source, filter| match source {
+        Source::Raw => (
+            format!(
+                "SELECT
+                    count(*) AS requests,
+                    sum(CASE WHEN request_kind = 'pageview' THEN 1 ELSE 0 END) AS pageviews,
+                    sum(1 - is_bot) AS human_requests,
+                    sum(is_bot) AS bot_requests,
+                    sum(is_vpn) AS vpn_requests,
+                    sum(request_time) AS request_time_sum,
+                    0 AS imported_visitors,
+                    sum(body_bytes_sent) AS bytes_sent
+                FROM access_log WHERE {filter}"
+            ),
+            Vec::new(),
+        ),
+        Source::Rollup(_) => (
+            format!(
+                "SELECT
+                    sum(requests) AS requests,
+                    sum(pageviews) AS pageviews,
+                    sum(human_requests) AS human_requests,
+                    sum(bot_requests) AS bot_requests,
+                    sum(vpn_requests) AS vpn_requests,
+                    sum(request_time_sum) AS request_time_sum,
+                    sum(imported_visitors) AS imported_visitors,
+                    sum(bytes_sent) AS bytes_sent
+                FROM rollups WHERE {filter}"
+            ),
+            Vec::new(),
+        ),
+    });
+    let row = storage.query_one(
+        &format!(
+            "SELECT
+                    CAST(coalesce(sum(requests), 0) AS BIGINT),
+                    CAST(coalesce(sum(pageviews), 0) AS BIGINT),
+                    CAST(coalesce(sum(human_requests), 0) AS BIGINT),
+                    CAST(coalesce(sum(bot_requests), 0) AS BIGINT),
+                    CAST(coalesce(sum(vpn_requests), 0) AS BIGINT),
+                    CAST(coalesce(sum(request_time_sum), 0) AS DOUBLE PRECISION),
+                    CAST(coalesce(sum(imported_visitors), 0) AS BIGINT),
+                    CAST(coalesce(sum(bytes_sent), 0) AS BIGINT)
+                FROM ({parts}) AS parts;"
+        ),
+        &params,
+    )?;
+    let total_requests: i32 = row.get(0)?;
+    let request_time_sum: f64 = row.get(5)?;
+    let imported_visitors: i32 = row.get(6)?;
+    Ok(Stats {
+        total_requests,
+        pageviews: row.get(1)?,
+        unique_visitors: unique_visitors(storage, filter, &segments)? + imported_visitors,
+        human_requests: row.get(2)?,
+        bot_requests: row.get(3)?,
+        vpn_requests: row.get(4)?,
+        bytes_sent: row.get(7)?,
+        avg_response_time: if total_requests > 0 {
+            (request_time_sum / f64::from(total_requests) * 1000.0) as f32
+        } else {
+            0.0
+        },
+        pages: top(
+            storage,
+            filter,
+            &segments,
+            "page",
+            "request_uri",
+            "AND request_kind = 'pageview'",
+        )?,
+        countries: top(storage, filter, &segments, "country", "country", "")?,
+        cities: top(storage, filter, &segments, "city", "city", "")?,
+        referrers: top(storage, filter, &segments, "referrer", "http_referer", "")?,
+        sessions: session_stats(storage, filter)?,
+        latency: latency(storage, filter)?,
+    })
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use crate::{
+    filter::Filter,
+    latency::{EndpointLatency, busiest},
+    rollups::{Horizon, Source},
+    stats::union,
+    storage::{Storage, StorageResult},
+};
+
+const TOP_LIMIT: usize = 10;
+
+/// Referrers listed per broken path.
+const REFERRER_LIMIT: usize = 3;
+
+/// A path answered with 404 and the pages linking to it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BrokenLink {
+    /// request uri without the query string
+    pub path: String,
+    pub hits: i32,
+    /// most linking first, requests without a referrer are left out
+    pub referrers: Vec<(String, i32)>,
+}
+
+/// Status codes of the requests a filter selects. Only the classes come from the rollups, the
+/// rest needs the rows in `access_log` and covers as much of the range as is kept there.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StatusReport {
+    /// requests per status class, 1xx to 5xx
+    pub classes: [i32; 5],
+    /// paths answered with 404 most often
+    pub not_found: Vec<BrokenLink>,
+    /// paths answered with 5xx most often
+    pub server_errors: Vec<EndpointLatency>,
+    /// 4xx and 5xx responses to humans
+    pub human_errors: i32,
+    /// 4xx and 5xx responses to bots
+    pub bot_errors: i32,
+}
+
+impl StatusReport {
+    /// 4xx and 5xx responses in percent of all requests
+    pub fn error_rate(&self) -> f64 {
+        let total: i32 = self.classes.iter().sum();
+        if total > 0 {
+            f64::from(self.classes[3] + self.classes[4]) * 100.0 / f64::from(total)
+        } else {
+            0.0
+        }
+    }
+
+    /// the errors humans got in percent of all errors, `None` without errors
+    pub fn human_error_share(&self) -> Option<f64> {
+        let errors = self.human_errors + self.bot_errors;
+        (errors > 0).then(|| f64::from(self.human_errors) * 100.0 / f64::from(errors))
+    }
+}
+
+fn classes(storage: &mut dyn Storage, filter: &Filter) -> StorageResult<[i32; 5]> {
+    let columns = |column: fn(u16) -> String| {
+        (1..=5)
+            .map(|class| format!("{} AS status_{class}xx", column(class)))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    let count = |class| format!("sum(CASE WHEN status / 100 = {class} THEN 1 ELSE 0 END)");
+    let segments = filter.segments(Horizon::load(storage)?);
+    let (parts, params) = union(filter, &segments, |source, condition| match source {
+        Source::Raw => (
+            format!(
+                "SELECT {} FROM access_log WHERE {condition}",
+                columns(count)
+            ),
+            Vec::new(),
+        ),
+        Source::Rollup(_) => (
+            format!(
+                "SELECT {} FROM rollups WHERE {condition}",
+                columns(|class| format!("sum(status_{class}xx)"))
+            ),
+            Vec::new(),
+        ),
+    });
+    let totals: Vec<String> = (1..=5)
+        .map(|class| format!("CAST(coalesce(sum(status_{class}xx), 0) AS BIGINT)"))
+        .collect();
+    let row = storage.query_one(
+        &format!("SELECT {} FROM ({parts}) AS parts;", totals.join(", ")),
+        &params,
+    )?;
+    Ok([
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+    ])
+}
+
+fn not_found(storage: &mut dyn Storage, filter: &Filter) -> StorageResult<Vec<BrokenLink>> {
+    let (condition, params) = filter.raw_condition();
+    let path = storage.dialect().path("request_uri");
+    let rows = storage.query(
+        &format!(
+            "SELECT {path}, http_referer, count(*) FROM access_log
+            WHERE status = 404{condition}
+            GROUP BY {path}, http_referer;"
+        ),
+        &params,
+    )?;
+    let mut links: HashMap<String, BrokenLink> = HashMap::new();
+    for row in rows {
+        let path: String = row.get(0)?;
+        let referrer: Option<String> = row.get(1)?;
+        let hits: i32 = row.get(2)?;
+        let link = links.entry(path.clone()).or_insert_with(|| BrokenLink {
+            path,
+            ..BrokenLink::default()
+        });
+        link.hits += hits;
+        match referrer.as_deref() {
+            None | Some("" | "-") => {}
+            Some(referrer) => link.referrers.push((referrer.to_string(), hits)),
+        }
+    }
+    let mut links: Vec<BrokenLink> = links.into_values().collect();
+    links.sort_by(|a, b| b.hits.cmp(&a.hits).then(a.path.cmp(&b.path)));
+    links.truncate(TOP_LIMIT);
+    for link in &mut links {
+        link.referrers
+            .sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        link.referrers.truncate(REFERRER_LIMIT);
+    }
+    Ok(links)
+}
+
+/// The status breakdown of the requests `filter` selects.
+pub(crate) fn status_report(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+) -> StorageResult<StatusReport> {
+    let (condition, params) = filter.raw_condition();
+    let errors = storage.query_one(
+        &format!(
+            "SELECT
+                CAST(coalesce(sum(1 - is_bot), 0) AS BIGINT),
+                CAST(coalesce(sum(is_bot), 0) AS BIGINT)
+            FROM access_log WHERE status >= 400{condition};"
+        ),
+        &params,
+    )?;
+    let server_errors = match filter.status_class {
+        // another class was asked for, there are no server errors among it
+        Some(class) if class != 5 => Vec::new(),
+        _ => busiest(storage, &filter.clone().status_class(5), TOP_LIMIT)?,
+    };
+    Ok(StatusReport {
+        classes: classes(storage, filter)?,
+        not_found: not_found(storage, filter)?,
+        server_errors,
+        human_errors: errors.get(0)?,
+        bot_errors: errors.get(1)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_errors_between_humans_and_bots() {
+        let report = StatusReport {
+            classes: [0, 90, 0, 8, 2],
+            human_errors: 3,
+            bot_errors: 7,
+            ..StatusReport::default()
+        };
+        assert_eq!(report.error_rate(), 10.0);
+        assert_eq!(report.human_error_share(), Some(30.0));
+        assert_eq!(StatusReport::default().human_error_share(), None);
+        assert_eq!(StatusReport::default().error_rate(), 0.0);
+    }
+}
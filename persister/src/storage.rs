@@ -0,0 +1,261 @@
+use std::fmt;
+
+/// The SQL flavour a storage speaks. Queries are written once in the subset both understand,
+/// the few spots where they differ ask the dialect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Sqlite,
+    Postgres,
+}
+
+impl Dialect {
+    /// scalar `min(a, b)`
+    pub fn least(&self) -> &'static str {
+        match self {
+            Dialect::Sqlite => "min",
+            Dialect::Postgres => "LEAST",
+        }
+    }
+
+    /// scalar `max(a, b)`
+    pub fn greatest(&self) -> &'static str {
+        match self {
+            Dialect::Sqlite => "max",
+            Dialect::Postgres => "GREATEST",
+        }
+    }
+
+    /// `uri` without its query string
+    pub fn path(&self, uri: &str) -> String {
+        match self {
+            Dialect::Sqlite => format!(
+                "CASE WHEN instr({uri}, '?') > 0 THEN substr({uri}, 1, instr({uri}, '?') - 1) ELSE {uri} END"
+            ),
+            Dialect::Postgres => format!("split_part({uri}, '?', 1)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Integer(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Value {
+        Value::Integer(i64::from(value))
+    }
+}
+
+impl From<u16> for Value {
+    fn from(value: u16) -> Value {
+        Value::Integer(i64::from(value))
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Value {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Value {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Integer(i64::from(value))
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Real(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::Text(value)
+    }
+}
+
+impl From<&String> for Value {
+    fn from(value: &String) -> Value {
+        Value::Text(value.clone())
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(value: &[u8]) -> Value {
+        Value::Blob(value.to_vec())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// Builds the parameter slice for `Storage` calls, like `rusqlite::params!`.
+#[macro_export]
+macro_rules! values {
+    () => { &[] as &[$crate::Value] };
+    ($($param:expr),+ $(,)?) => { &[$($crate::Value::from($param)),+] as &[$crate::Value] };
+}
+
+#[derive(Debug, Clone)]
+pub struct StorageError(pub String);
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> StorageResult<Self>;
+}
+
+fn mismatch<T>(value: &Value, expected: &str) -> StorageResult<T> {
+    Err(StorageError(format!("expected {expected}, got {value:?}")))
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> StorageResult<i64> {
+        match value {
+            Value::Integer(value) => Ok(*value),
+            Value::Real(value) => Ok(*value as i64),
+            _ => mismatch(value, "an integer"),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> StorageResult<i32> {
+        i64::from_value(value).map(|value| value as i32)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> StorageResult<f64> {
+        match value {
+            Value::Integer(value) => Ok(*value as f64),
+            Value::Real(value) => Ok(*value),
+            _ => mismatch(value, "a number"),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> StorageResult<bool> {
+        i64::from_value(value).map(|value| value != 0)
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> StorageResult<String> {
+        match value {
+            Value::Text(value) => Ok(value.clone()),
+            _ => mismatch(value, "text"),
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> StorageResult<Vec<u8>> {
+        match value {
+            Value::Blob(value) => Ok(value.clone()),
+            _ => mismatch(value, "a blob"),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> StorageResult<Option<T>> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Row(pub Vec<Value>);
+
+impl Row {
+    pub fn get<T: FromValue>(&self, index: usize) -> StorageResult<T> {
+        match self.0.get(index) {
+            Some(value) => T::from_value(value),
+            None => Err(StorageError(format!("no column {index}"))),
+        }
+    }
+}
+
+/// A database kirinox can keep its data in. Implementations only run SQL, everything on top
+/// (schema, ingestion, reports) is shared so the backends can't drift apart. Statements use
+/// `?` placeholders whatever the backend.
+pub trait Storage {
+    fn dialect(&self) -> Dialect;
+
+    fn execute(&mut self, sql: &str, params: &[Value]) -> StorageResult<usize>;
+
+    /// Runs several `;` separated statements without parameters, used for migrations.
+    fn execute_batch(&mut self, sql: &str) -> StorageResult<()>;
+
+    fn query(&mut self, sql: &str, params: &[Value]) -> StorageResult<Vec<Row>>;
+
+    fn schema_version(&mut self) -> StorageResult<i32>;
+
+    fn set_schema_version(&mut self, version: i32) -> StorageResult<()>;
+
+    /// Gives the space of deleted rows back and refreshes the planner statistics, run after
+    /// large deletes.
+    fn vacuum(&mut self) -> StorageResult<()>;
+
+    fn query_row(&mut self, sql: &str, params: &[Value]) -> StorageResult<Option<Row>> {
+        Ok(self.query(sql, params)?.into_iter().next())
+    }
+
+    /// The row of a query that always returns one, like an aggregate.
+    fn query_one(&mut self, sql: &str, params: &[Value]) -> StorageResult<Row> {
+        self.query_row(sql, params)?
+            .ok_or_else(|| StorageError(String::from("the query returned no row")))
+    }
+
+    fn begin(&mut self) -> StorageResult<()> {
+        self.execute_batch("BEGIN;")
+    }
+
+    fn commit(&mut self) -> StorageResult<()> {
+        self.execute_batch("COMMIT;")
+    }
+
+    fn rollback(&mut self) -> StorageResult<()> {
+        self.execute_batch("ROLLBACK;")
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    filter::Filter,
+    rollups::{Period, Segment, Source},
+    storage::{Storage, StorageResult},
+};
+
+/// Totals of one bucket of a time series.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TimePoint {
+    /// start of the bucket, unix milliseconds
+    pub bucket: i64,
+    pub requests: i32,
+    pub pageviews: i32,
+    pub unique_visitors: i32,
+    pub human_requests: i32,
+    pub bot_requests: i32,
+    /// milliseconds
+    pub avg_response_time: f64,
+    /// requests per status class, 1xx to 5xx
+    pub status: [i32; 5],
+    pub bytes_sent: i64,
+}
+
+/// Most buckets a series has, ten years of days. Ranges given from outside could ask for far
+/// more.
+const MAX_BUCKETS: i64 = 3660;
+
+/// The requests `filter` selects per `step`, from the bucket `since` falls in up to `until`,
+/// or up to the last bucket with requests when the range is open. Buckets without requests
+/// are there with zeros so the series has no gaps. Only whole buckets are counted, so the
+/// first one may start before `since`. A range of more than `MAX_BUCKETS` is narrowed to the
+/// buckets with requests and then to the last `MAX_BUCKETS` of them.
+pub(crate) fn timeseries(
+    storage: &mut dyn Storage,
+    filter: &Filter,
+    step: Period,
+) -> StorageResult<Vec<TimePoint>> {
+    let from = step.floor(filter.since.unwrap_or(0));
+    let source = if filter.by_host_and_time() {
+        Source::Rollup(step)
+    } else {
+        Source::Raw
+    };
+    let segment = Segment {
+        source,
+        from,
+        to: filter.until,
+    };
+    let (mut condition, mut params) = segment.filter(filter.host.as_deref(), "timestamp");
+    let length = step.length();
+    let status = |column: fn(u16) -> String| {
+        (1..=5)
+            .map(|class| format!("CAST(sum({}) AS BIGINT)", column(class)))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    let (totals, visitors) = match source {
+        Source::Raw => {
+            let (dimensions, dimension_params) = filter.dimensions();
+            condition.push_str(&dimensions);
+            params.extend(dimension_params);
+            (
+                format!(
+                    "SELECT
+                        timestamp - timestamp % {length},
+                        count(*),
+                        CAST(sum(CASE WHEN request_kind = 'pageview' THEN 1 ELSE 0 END) AS BIGINT),
+                        CAST(sum(1 - is_bot) AS BIGINT),
+                        CAST(sum(is_bot) AS BIGINT),
+                        0,
+                        CAST(sum(request_time) AS DOUBLE PRECISION),
+                        {},
+                        CAST(sum(body_bytes_sent) AS BIGINT)
+                    FROM access_log WHERE {condition}
+                    GROUP BY timestamp - timestamp % {length};",
+                    status(|class| format!("CASE WHEN status / 100 = {class} THEN 1 ELSE 0 END"))
+                ),
+                format!(
+                    "SELECT
+                        timestamp - timestamp % {length},
+                        count(DISTINCT coalesce(visitor_hash, remote_addr))
+                    FROM access_log WHERE {condition}
+                    GROUP BY timestamp - timestamp % {length};"
+                ),
+            )
+        }
+        Source::Rollup(_) => (
+            format!(
+                "SELECT
+                    bucket,
+                    CAST(sum(requests) AS BIGINT),
+                    CAST(sum(pageviews) AS BIGINT),
+                    CAST(sum(human_requests) AS BIGINT),
+                    CAST(sum(bot_requests) AS BIGINT),
+                    CAST(sum(imported_visitors) AS BIGINT),
+                    CAST(sum(request_time_sum) AS DOUBLE PRECISION),
+                    {},
+                    CAST(sum(bytes_sent) AS BIGINT)
+                FROM rollups WHERE {condition}
+                GROUP BY bucket;",
+                status(|class| format!("status_{class}xx"))
+            ),
+            format!(
+                "SELECT bucket, count(DISTINCT visitor) FROM rollup_visitors WHERE {condition}
+                GROUP BY bucket;"
+            ),
+        ),
+    };
+    let mut points: BTreeMap<i64, TimePoint> = BTreeMap::new();
+    for row in storage.query(&totals, &params)? {
+        let bucket = row.get(0)?;
+        let requests: i32 = row.get(1)?;
+        let request_time_sum: f64 = row.get(6)?;
+        points.insert(
+            bucket,
+            TimePoint {
+                bucket,
+                requests,
+                pageviews: row.get(2)?,
+                // days imported from other tools only know how many visitors there were
+                unique_visitors: row.get(5)?,
+                human_requests: row.get(3)?,
+                bot_requests: row.get(4)?,
+                avg_response_time: if requests > 0 {
+                    request_time_sum / f64::from(requests) * 1000.0
+                } else {
+                    0.0
+                },
+                status: [
+                    row.get(7)?,
+                    row.get(8)?,
+                    row.get(9)?,
+                    row.get(10)?,
+                    row.get(11)?,
+                ],
+                bytes_sent: row.get(12)?,
+            },
+        );
+    }
+    for row in storage.query(&visitors, &params)? {
+        let visitors: i32 = row.get(1)?;
+        points.entry(row.get(0)?).or_default().unique_visitors += visitors;
+    }
+    let Some(mut last) = filter
+        .until
+        .map(|until| step.floor(until - 1))
+        .or_else(|| points.keys().next_back().copied())
+    else {
+        return Ok(Vec::new());
+    };
+    let mut first = match filter.since {
+        Some(_) => from,
+        None => points.keys().next().copied().unwrap_or(last),
+    };
+    if (last - first) / length >= MAX_BUCKETS {
+        let (Some(&earliest), Some(&latest)) = (points.keys().next(), points.keys().next_back())
+        else {
+            return Ok(Vec::new());
+        };
+        first = first.max(earliest);
+        last = last.min(latest);
+        first = first.max(last - (MAX_BUCKETS - 1) * length);
+    }
+    Ok((first..=last)
+        .step_by(length as usize)
+        .map(|bucket| TimePoint {
+            bucket,
+            ..points.remove(&bucket).unwrap_or_default()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sqlite::SqliteStorage, values};
+
+    #[test]
+    fn fills_the_buckets_without_requests() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        crate::migrations::migrate(&mut storage).unwrap();
+        let hour = Period::Hour.length();
+        for bucket in [0, 3 * hour] {
+            storage
+                .execute(
+                    "INSERT INTO rollups (
+                        http_host, period, bucket, requests, pageviews, human_requests,
+                        bot_requests, vpn_requests, bytes_sent, status_1xx, status_2xx,
+                        status_3xx, status_4xx, status_5xx, request_time_sum
+                    ) VALUES ('example.com', 'hour', ?, 4, 2, 3, 1, 0, 0, 0, 4, 0, 0, 0, 0);",
+                    values![bucket],
+                )
+                .unwrap();
+        }
+        let filter = Filter::new().host("example.com").since(10).until(5 * hour);
+        let series = timeseries(&mut storage, &filter, Period::Hour).unwrap();
+        let requests: Vec<i32> = series.iter().map(|point| point.requests).collect();
+        assert_eq!(requests, vec![4, 0, 0, 4, 0]);
+        assert_eq!(series[3].bucket, 3 * hour);
+        assert_eq!(series[3].bot_requests, 1);
+        assert_eq!(series[3].status, [0, 4, 0, 0, 0]);
+
+        let open = timeseries(&mut storage, &Filter::new(), Period::Hour).unwrap();
+        assert_eq!(open.len(), 4);
+    }
+
+    #[test]
+    fn caps_the_buckets_of_long_ranges() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        crate::migrations::migrate(&mut storage).unwrap();
+        let hour = Period::Hour.length();
+        let start = 1_000_000 * hour;
+        for bucket in [start, start + 2 * hour] {
+            storage
+                .execute(
+                    "INSERT INTO rollups (
+                        http_host, period, bucket, requests, pageviews, human_requests,
+                        bot_requests, vpn_requests, bytes_sent, status_1xx, status_2xx,
+                        status_3xx, status_4xx, status_5xx, request_time_sum
+                    ) VALUES ('example.com', 'hour', ?, 4, 2, 3, 1, 0, 0, 0, 4, 0, 0, 0, 0);",
+                    values![bucket],
+                )
+                .unwrap();
+        }
+        let filter = Filter::new()
+            .host("example.com")
+            .since(0)
+            .until(start + 10 * hour);
+        let series = timeseries(&mut storage, &filter, Period::Hour).unwrap();
+        let requests: Vec<i32> = series.iter().map(|point| point.requests).collect();
+        assert_eq!(requests, vec![4, 0, 4]);
+        assert_eq!(series[0].bucket, start);
+
+        let empty = Filter::new().host("example.org").since(0).until(start);
+        assert!(
+            timeseries(&mut storage, &empty, Period::Hour)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}